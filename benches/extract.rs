@@ -0,0 +1,77 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use std::fmt::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use sandgate::{
+    extract::TablePlan,
+    mib::{
+        self,
+        apc::{Group, OutletStatus},
+    },
+    walk::WalkedValues,
+};
+
+const OUTLETS: u32 = 48;
+
+/**
+ * A walk of the rPDU2 switched outlet status table of a large PDU.
+ */
+fn outlet_status() -> WalkedValues {
+    let mut capture = String::new();
+    let mut line = |column: &str, value: &dyn Fn(u32) -> String| {
+        for i in 1..=OUTLETS {
+            writeln!(
+                capture,
+                "PowerNet-MIB::rPDU2OutletSwitchedStatus{column}.{i} = {}",
+                value(i)
+            )
+            .unwrap();
+        }
+    };
+    line("Index", &|i| format!("INTEGER: {i}"));
+    line("Module", &|_| "INTEGER: 1".into());
+    line("Name", &|i| format!("STRING: \"outlet {i}\""));
+    line("Number", &|i| format!("INTEGER: {i}"));
+    line("State", &|i| format!("INTEGER: {}", 1 + i % 2));
+    line("CommandPending", &|_| "INTEGER: 2".into());
+    line("ExternalLink", &|_| "STRING: \"\"".into());
+    capture.push_str(&format!(
+        "PowerNet-MIB::rPDU2OutletSwitchedTableSize.0 = INTEGER: {OUTLETS}\n"
+    ));
+
+    WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap()
+}
+
+fn extract(c: &mut Criterion) {
+    let walk = outlet_status();
+    let tree = walk.tree();
+    let (size, entry, prefix) = Group::OutletStatus.table_oids(tree).unwrap();
+
+    let mut g = c.benchmark_group("outlet status");
+    g.bench_function("extract_table", |b| {
+        b.iter(|| {
+            walk.extract_table::<OutletStatus>(size, entry, prefix).unwrap()
+        })
+    });
+    g.bench_function("extract_planned", |b| {
+        let plan =
+            TablePlan::<OutletStatus>::new(tree, size, entry, prefix).unwrap();
+        b.iter(|| walk.extract_planned(&plan).unwrap())
+    });
+    g.bench_function("plan and extract_planned", |b| {
+        b.iter(|| {
+            let plan =
+                TablePlan::<OutletStatus>::new(tree, size, entry, prefix)
+                    .unwrap();
+            walk.extract_planned(&plan).unwrap()
+        })
+    });
+    g.finish();
+}
+
+criterion_group!(benches, extract);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use sandgate::futures::StreamExt;
+use sandgate::{capture, mib, Client};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = getopts::Options::new()
+        .optopt("c", "", "community string", "COMMUNITY")
+        .optopt("o", "", "write capture to this file", "FILE")
+        .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
+        .parse(std::env::args_os().skip(1))?;
+
+    if opts.free.is_empty() || opts.free.len() > 2 {
+        bail!("specify IP address of SNMP target, and optionally an OID");
+    }
+
+    let c = Client::builder()
+        .community(opts.opt_str("c").as_deref().unwrap_or("public"))
+        .oid_tree(mib::bundled())
+        .build(opts.free[0].parse()?)
+        .await?;
+
+    let top = c.tree().oid_by_name(
+        opts.free.get(1).map(String::as_str).unwrap_or("internet"),
+    )?;
+
+    let mut out: Box<dyn Write> = if let Some(path) = opts.opt_str("o") {
+        Box::new(std::io::BufWriter::new(std::fs::File::create(path)?))
+    } else {
+        Box::new(std::io::stdout().lock())
+    };
+
+    /*
+     * Write out each value as it arrives, so that a long walk shows progress
+     * and whatever was captured before any failure is kept.
+     */
+    let mut count = 0;
+    let mut walk = std::pin::pin!(c.walk_stream(top));
+    while let Some(v) = walk.next().await {
+        let (oid, val) = v?;
+        capture::write_snmpwalk(&mut out, [(&oid, &val)])?;
+        count += 1;
+    }
+    out.flush()?;
+
+    eprintln!("captured {count} values under {top}");
+    Ok(())
+}
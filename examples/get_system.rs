@@ -20,8 +20,7 @@ async fn main() -> Result<()> {
 
     let c = Client::builder()
         .community(opts.opt_str("c").as_deref().unwrap_or("public"))
-        .with_oid_tree(|tree| mib::mib_2::populate(tree))?
-        .with_oid_tree(|tree| mib::apc::populate(tree))?
+        .oid_tree(mib::bundled())
         .build(opts.free[0].parse()?)
         .await?;
 
@@ -0,0 +1,52 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use anyhow::{bail, Result};
+
+use sandgate::mib;
+use sandgate::reconcile::{reconcile, DesiredState};
+use sandgate::Client;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = getopts::Options::new()
+        .optopt("c", "", "community string", "COMMUNITY")
+        .optflag("n", "", "dry run: report changes without making them")
+        .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
+        .parse(std::env::args_os().skip(1))?;
+
+    if opts.free.len() != 2 {
+        bail!("specify IP address of SNMP target and desired state file");
+    }
+
+    /*
+     * The desired state may be written in either TOML or JSON:
+     */
+    let path = &opts.free[1];
+    let text = std::fs::read_to_string(path)?;
+    let desired: DesiredState = if path.ends_with(".json") {
+        serde_json::from_str(&text)?
+    } else {
+        toml::from_str(&text)?
+    };
+
+    let c = Client::builder()
+        .community(opts.opt_str("c").as_deref().unwrap_or("public"))
+        .oid_tree(mib::bundled())
+        .build(opts.free[0].parse()?)
+        .await?;
+
+    let dry_run = opts.opt_present("n");
+    let plan = reconcile(&c, &desired, dry_run).await?;
+    if plan.is_empty() {
+        println!("no changes needed");
+    } else {
+        print!("{plan}");
+        if dry_run {
+            println!("(dry run: no changes made)");
+        }
+    }
+
+    Ok(())
+}
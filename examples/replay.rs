@@ -0,0 +1,33 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use anyhow::{bail, Result};
+
+use sandgate::mib::{self, apc::Pdu, mib_2::System};
+use sandgate::walk::WalkedValues;
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.len() != 1 {
+        bail!("specify a capture file produced by \"snmpwalk -On\"");
+    }
+
+    let walk =
+        WalkedValues::load(std::fs::File::open(&args[0])?, mib::bundled())?;
+    println!("loaded {} values", walk.len());
+    println!();
+
+    match System::from_walk(&walk) {
+        Ok(s) => println!("system = {s:#?}"),
+        Err(e) => println!("no system information: {e}"),
+    }
+    println!();
+
+    let pdu = Pdu::from_walk(walk)?;
+    println!("pdu ident =          {:#?}", pdu.ident()?);
+    println!("pdu outlet status =  {:#?}", pdu.outlet_status()?);
+    println!();
+
+    Ok(())
+}
@@ -30,8 +30,7 @@ async fn main() -> Result<()> {
 
     let c = Client::builder()
         .community(opts.opt_str("c").as_deref().unwrap_or("public"))
-        .with_oid_tree(|tree| mib::mib_2::populate(tree))?
-        .with_oid_tree(|tree| mib::apc::populate(tree))?
+        .oid_tree(mib::bundled())
         .build(opts.free[0].parse()?)
         .await?;
 
@@ -40,37 +39,30 @@ async fn main() -> Result<()> {
     println!("pdu ident =          {:#?}", pdu.ident()?);
     println!();
 
-    let cfg = pdu.outlet_config()?;
-    let props = pdu.outlet_props()?;
-    let status = pdu.outlet_status()?;
-    let ctl = pdu.outlet_control()?;
-
-    let Some((((cfg, props), status), ctl)) = cfg
-        .get(&outlet)
-        .zip(props.get(&outlet))
-        .zip(status.get(&outlet))
-        .zip(ctl.get(&outlet))
-    else {
+    let outlets = pdu.outlets()?;
+    for i in outlets.inconsistencies() {
+        eprintln!("warning: {i}");
+    }
+    let Some(o) = outlets.by_index(outlet) else {
         bail!("could not get status for outlet {outlet}");
     };
 
-    println!("pdu outlet config =  {:#?}", cfg);
-    println!("pdu outlet props =   {:#?}", props);
-    println!("pdu outlet status =  {:#?}", status);
-    println!("pdu outlet control = {:#?}", ctl);
+    println!("pdu outlet =         {:#?}", o);
     println!();
 
-    if status.is_command_pending()? {
-        bail!("command already pending");
+    match o.status.is_command_pending() {
+        Some(false) => (),
+        Some(true) => bail!("command already pending"),
+        None => bail!("command pending state unknown"),
     }
 
-    if ctl.command == cmd {
+    if o.control.command == cmd {
         bail!("command {cmd:?} already in effect");
     }
 
     println!("sending command {cmd:?} to outlet {outlet}...");
-    let res = Pdu::send_command(&c, outlet, cmd).await?;
-    println!("command result = {res:?}");
+    Pdu::send_command(&c, outlet, cmd).await?;
+    println!("command accepted");
 
     loop {
         tokio::time::sleep(Duration::from_millis(250)).await;
@@ -0,0 +1,259 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * A record of every SET request a client makes.  SET requests change the
+ * physical state of things like outlets, so a client can be given an
+ * AuditSink which is told about each value that is set, along with who set
+ * it and why.
+ */
+
+use std::{
+    fmt::Write as _,
+    io::Write,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use csnmp::ObjectValue;
+
+use crate::{value::Value, Oid};
+
+/**
+ * Who is making changes through a client, and why.  Both are free text,
+ * supplied by the caller, and copied into each audit record.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditContext {
+    pub principal: Option<String>,
+    pub reason: Option<String>,
+}
+
+/**
+ * One value in a SET request.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub timestamp: SystemTime,
+    pub target: SocketAddr,
+    pub oid: Oid,
+    /**
+     * The name of the OID in the client's OID tree, if it has one, in the
+     * short form; e.g., "ifAdminStatus[3]".
+     */
+    pub name: Option<String>,
+    /**
+     * The value before the request, if the client was built to read values
+     * before setting them and the agent returned one.
+     */
+    pub old: Option<Value>,
+    pub new: Value,
+    /**
+     * The value echoed by the agent, or a description of the failure if the
+     * request failed.
+     */
+    pub result: std::result::Result<Value, String>,
+    pub principal: Option<String>,
+    pub reason: Option<String>,
+}
+
+pub trait AuditSink: Send + Sync {
+    /**
+     * Record the values in one SET request, once it has completed.  If this
+     * fails after a successful request, the client returns Error::Audit even
+     * though the agent made the change; if the request itself failed, the
+     * client returns the error from the request instead.
+     */
+    fn record(&self, records: &[AuditRecord]) -> std::io::Result<()>;
+}
+
+/**
+ * Writes each record as a line of JSON, flushing after each request.
+ */
+pub struct JsonLinesSink {
+    w: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLinesSink {
+    pub fn new(w: impl Write + Send + 'static) -> JsonLinesSink {
+        JsonLinesSink { w: Mutex::new(Box::new(w)) }
+    }
+
+    /**
+     * Append records to a file, creating it if it does not exist.
+     */
+    pub fn open<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> std::io::Result<JsonLinesSink> {
+        let f =
+            std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesSink::new(f))
+    }
+}
+
+impl AuditSink for JsonLinesSink {
+    fn record(&self, records: &[AuditRecord]) -> std::io::Result<()> {
+        /*
+         * Format every line first, so that the lines for one request are
+         * written together.
+         */
+        let mut out = String::new();
+        for r in records {
+            json_line(&mut out, r);
+        }
+
+        let mut w = self.w.lock().unwrap();
+        w.write_all(out.as_bytes())?;
+        w.flush()
+    }
+}
+
+/**
+ * Keeps records in memory, for tests and for callers that ship them
+ * elsewhere themselves.
+ */
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        Default::default()
+    }
+
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /**
+     * Remove and return the records kept so far.
+     */
+    pub fn take(&self) -> Vec<AuditRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap())
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn record(&self, records: &[AuditRecord]) -> std::io::Result<()> {
+        self.records.lock().unwrap().extend_from_slice(records);
+        Ok(())
+    }
+}
+
+fn json_line(out: &mut String, r: &AuditRecord) {
+    out.push('{');
+    out.push_str("\"timestamp\":");
+    json_string(out, &rfc3339(r.timestamp));
+    out.push_str(",\"target\":");
+    json_string(out, &r.target.to_string());
+    out.push_str(",\"oid\":");
+    json_string(out, &r.oid.to_string());
+    out.push_str(",\"name\":");
+    json_option(out, r.name.as_deref());
+    out.push_str(",\"old\":");
+    match &r.old {
+        Some(v) => json_value(out, v),
+        None => out.push_str("null"),
+    }
+    out.push_str(",\"new\":");
+    json_value(out, &r.new);
+    match &r.result {
+        Ok(v) => {
+            out.push_str(",\"result\":");
+            json_value(out, v);
+        }
+        Err(e) => {
+            out.push_str(",\"error\":");
+            json_string(out, e);
+        }
+    }
+    out.push_str(",\"principal\":");
+    json_option(out, r.principal.as_deref());
+    out.push_str(",\"reason\":");
+    json_option(out, r.reason.as_deref());
+    out.push_str("}\n");
+}
+
+/**
+ * Numeric values are written as numbers, and everything else as a string.
+ * An Opaque value is written as hexadecimal octets, as in a capture file.
+ */
+fn json_value(out: &mut String, v: &Value) {
+    match &v.0 {
+        ObjectValue::Integer(i) => write!(out, "{i}").unwrap(),
+        ObjectValue::Counter32(u)
+        | ObjectValue::Unsigned32(u)
+        | ObjectValue::TimeTicks(u) => write!(out, "{u}").unwrap(),
+        ObjectValue::Counter64(u) => write!(out, "{u}").unwrap(),
+        ObjectValue::String(buf) => {
+            json_string(out, &String::from_utf8_lossy(buf))
+        }
+        ObjectValue::ObjectId(oid) => json_string(out, &oid.to_string()),
+        ObjectValue::IpAddress(ip) => json_string(out, &ip.to_string()),
+        ObjectValue::Opaque(buf) => {
+            let hex = buf
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            json_string(out, &hex)
+        }
+    }
+}
+
+fn json_option(out: &mut String, s: Option<&str>) {
+    match s {
+        Some(s) => json_string(out, s),
+        None => out.push_str("null"),
+    }
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/**
+ * Format a time as UTC, to the millisecond, e.g. "2024-03-14T15:09:26.535Z".
+ */
+fn rfc3339(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    /*
+     * Convert days since the epoch to a civil date, using Howard Hinnant's
+     * civil_from_days() algorithm.
+     */
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
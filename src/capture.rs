@@ -0,0 +1,386 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * Reading and writing captures of walked values in the text format produced
+ * by the net-snmp "snmpwalk -On" command, so that a walk of a real device can
+ * be recorded and later replayed without access to the device.
+ */
+
+use std::{collections::BTreeMap, io::Write, net::Ipv4Addr};
+
+use crate::{oidtree::OidTree, value::Value, Error, Oid, Result};
+use csnmp::ObjectValue;
+
+/**
+ * Write values, one per line, in the format produced by "snmpwalk -On".
+ */
+pub fn write_snmpwalk<'a, W: Write>(
+    w: &mut W,
+    values: impl IntoIterator<Item = (&'a Oid, &'a Value)>,
+) -> std::io::Result<()> {
+    for (oid, val) in values {
+        write!(w, ".{oid} = ")?;
+
+        match &val.0 {
+            ObjectValue::Integer(i) => write!(w, "INTEGER: {i}")?,
+            ObjectValue::String(buf) => {
+                if let Some(s) = printable(buf) {
+                    write!(w, "STRING: \"")?;
+                    for c in s.chars() {
+                        if c == '"' || c == '\\' {
+                            write!(w, "\\")?;
+                        }
+                        write!(w, "{c}")?;
+                    }
+                    write!(w, "\"")?;
+                } else {
+                    write!(w, "Hex-STRING:")?;
+                    for b in buf {
+                        write!(w, " {b:02X}")?;
+                    }
+                }
+            }
+            ObjectValue::ObjectId(oid) => write!(w, "OID: .{oid}")?,
+            ObjectValue::IpAddress(ip) => write!(w, "IpAddress: {ip}")?,
+            ObjectValue::Counter32(u) => write!(w, "Counter32: {u}")?,
+            ObjectValue::Unsigned32(u) => write!(w, "Gauge32: {u}")?,
+            ObjectValue::TimeTicks(u) => {
+                let (cs, s) = (u % 100, u / 100);
+                let (days, h, m, s) =
+                    (s / 86400, (s / 3600) % 24, (s / 60) % 60, s % 60);
+                write!(w, "Timeticks: ({u}) ")?;
+                match days {
+                    0 => (),
+                    1 => write!(w, "1 day, ")?,
+                    n => write!(w, "{n} days, ")?,
+                }
+                write!(w, "{h}:{m:02}:{s:02}.{cs:02}")?;
+            }
+            ObjectValue::Opaque(buf) => {
+                write!(w, "OPAQUE:")?;
+                for b in buf {
+                    write!(w, " {b:02X}")?;
+                }
+            }
+            ObjectValue::Counter64(u) => write!(w, "Counter64: {u}")?,
+        }
+
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Strings that are valid UTF-8 and contain no control characters other than
+ * line breaks and tabs are written in quoted form; anything else is written
+ * as hexadecimal octets.
+ */
+fn printable(buf: &[u8]) -> Option<&str> {
+    std::str::from_utf8(buf)
+        .ok()
+        .filter(|s| s.chars().all(|c| !c.is_control() || "\r\n\t".contains(c)))
+}
+
+/**
+ * Parse the output of "snmpwalk".  Numeric OIDs (as produced with "-On") are
+ * preferred, but any OID name that the provided tree can resolve is also
+ * accepted.  Lines reporting exceptions like "No Such Object available on this
+ * agent at this OID" are skipped, as are blank lines and lines that begin with
+ * a "#".
+ */
+pub fn parse_snmpwalk(
+    input: &str,
+    tree: &OidTree,
+) -> Result<BTreeMap<Oid, Value>> {
+    let mut out = BTreeMap::new();
+
+    /*
+     * Lines are kept with their endings, so that the line breaks in a quoted
+     * string (which may be "\r\n") are preserved.
+     */
+    let mut lines = input.split_inclusive('\n').enumerate().peekable();
+
+    while let Some((i, raw)) = lines.next() {
+        let lnum = i + 1;
+        let line = raw.trim_end_matches(['\r', '\n']);
+
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let Some((name, rest)) = line.split_once(" = ") else {
+            return Err(capture_error(lnum, "expected \"OID = VALUE\""));
+        };
+
+        let name = name.trim();
+        let oid = if let Some(name) = name.strip_prefix("iso.") {
+            /*
+             * Without MIBs loaded, snmpwalk names only the first arc.
+             */
+            tree.oid_by_name(&format!("1.{name}"))
+        } else {
+            tree.oid_by_name(name)
+        }
+        .map_err(|e| capture_error(lnum, e))?;
+
+        let (ty, body) = match rest.split_once(": ") {
+            Some((ty, body))
+                if !ty.is_empty()
+                    && ty.chars().all(|c| {
+                        c.is_ascii_alphanumeric() || c == '-' || c == ' '
+                    }) =>
+            {
+                (ty, body)
+            }
+            /*
+             * An empty value, such as an empty Opaque, has no space after the
+             * type.
+             */
+            _ => match rest.strip_suffix(':') {
+                Some(ty)
+                    if !ty.is_empty()
+                        && ty.chars().all(|c| c.is_ascii_alphanumeric()) =>
+                {
+                    (ty, "")
+                }
+                _ => ("", rest),
+            },
+        };
+
+        let val = match ty.to_ascii_lowercase().as_str() {
+            "" if body.trim() == "\"\"" => ObjectValue::String(Vec::new()),
+            "" if body.starts_with("No Such ")
+                || body.starts_with("No more variables") =>
+            {
+                continue;
+            }
+            "string" => {
+                let mut s = body.to_string();
+                if s.starts_with('"') {
+                    /*
+                     * Quoted strings may span several lines.
+                     */
+                    s.push_str(&raw[line.len()..]);
+                    while !quoted_complete(&s) {
+                        let Some((_, next)) = lines.next() else {
+                            return Err(capture_error(
+                                lnum,
+                                "unterminated string",
+                            ));
+                        };
+                        s.push_str(next);
+                    }
+                    ObjectValue::String(unquote(&s).into_bytes())
+                } else {
+                    ObjectValue::String(s.into_bytes())
+                }
+            }
+            "hex-string" | "bits" | "opaque" => {
+                let mut buf = hex_octets(body);
+
+                if ty.eq_ignore_ascii_case("opaque") {
+                    if let Some((kind, num)) = body.split_once(": ") {
+                        buf = opaque_number(kind, num.trim()).ok_or_else(
+                            || capture_error(lnum, format!("bad {body:?}")),
+                        )?;
+                    }
+                }
+
+                /*
+                 * Long hexadecimal strings are continued on subsequent lines.
+                 */
+                if !ty.eq_ignore_ascii_case("bits") {
+                    while let Some((_, next)) = lines.peek() {
+                        if next.contains(" = ") || !is_hex_line(next) {
+                            break;
+                        }
+                        buf.extend(hex_octets(next));
+                        lines.next();
+                    }
+                }
+
+                if ty.eq_ignore_ascii_case("opaque") {
+                    ObjectValue::Opaque(buf)
+                } else {
+                    ObjectValue::String(buf)
+                }
+            }
+            "integer" | "integer32" => {
+                ObjectValue::Integer(enum_or_number(body).ok_or_else(|| {
+                    capture_error(lnum, format!("bad integer {body:?}"))
+                })?)
+            }
+            "counter32" => {
+                ObjectValue::Counter32(first_number(body).ok_or_else(|| {
+                    capture_error(lnum, format!("bad counter {body:?}"))
+                })?)
+            }
+            "gauge32" | "unsigned32" | "uinteger32" => {
+                ObjectValue::Unsigned32(first_number(body).ok_or_else(
+                    || capture_error(lnum, format!("bad gauge {body:?}")),
+                )?)
+            }
+            "counter64" => {
+                ObjectValue::Counter64(first_number(body).ok_or_else(|| {
+                    capture_error(lnum, format!("bad counter {body:?}"))
+                })?)
+            }
+            "timeticks" => {
+                let ticks = body
+                    .strip_prefix('(')
+                    .and_then(|b| b.split_once(')'))
+                    .map(|(t, _)| t)
+                    .unwrap_or(body);
+                ObjectValue::TimeTicks(first_number(ticks).ok_or_else(
+                    || capture_error(lnum, format!("bad timeticks {body:?}")),
+                )?)
+            }
+            "ipaddress" => ObjectValue::IpAddress(
+                body.trim()
+                    .parse::<Ipv4Addr>()
+                    .map_err(|e| capture_error(lnum, e))?,
+            ),
+            "network address" => {
+                let buf = body
+                    .trim()
+                    .split(':')
+                    .map(|b| u8::from_str_radix(b, 16))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| capture_error(lnum, e))?;
+                let Ok(octets) = <[u8; 4]>::try_from(buf) else {
+                    return Err(capture_error(
+                        lnum,
+                        format!("bad network address {body:?}"),
+                    ));
+                };
+                ObjectValue::IpAddress(Ipv4Addr::from(octets))
+            }
+            "oid" => {
+                let target = body.trim();
+                let target = if let Some(t) = target.strip_prefix("iso.") {
+                    tree.oid_by_name(&format!("1.{t}"))
+                } else {
+                    tree.oid_by_name(target)
+                }
+                .map_err(|e| capture_error(lnum, e))?;
+                ObjectValue::ObjectId(target.0)
+            }
+            other => {
+                return Err(capture_error(
+                    lnum,
+                    format!("unsupported type {other:?}"),
+                ))
+            }
+        };
+
+        if out.insert(oid, Value(val)).is_some() {
+            return Err(capture_error(
+                lnum,
+                format!("duplicate value for {oid}"),
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+fn capture_error(line: usize, message: impl std::fmt::Display) -> Error {
+    Error::Capture { line, message: message.to_string() }
+}
+
+/**
+ * Determine whether a string that begins with a double quote has also been
+ * terminated with one, taking escaped quotes into account.
+ */
+fn quoted_complete(s: &str) -> bool {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return s[i + 1..].trim().is_empty(),
+            _ => (),
+        }
+    }
+    false
+}
+
+fn unquote(s: &str) -> String {
+    let s = s.trim_end();
+    let inner = &s[1..s.len() - 1];
+    let mut out = String::with_capacity(inner.len());
+    let mut escaped = false;
+    for c in inner.chars() {
+        if !escaped && c == '\\' {
+            escaped = true;
+        } else {
+            escaped = false;
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn is_hex_line(line: &str) -> bool {
+    !line.trim().is_empty()
+        && line
+            .split_whitespace()
+            .all(|t| t.len() == 2 && t.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/**
+ * Collect leading hexadecimal octets, stopping at the first token that is not
+ * one (e.g., the decoded bit names that snmpwalk prints after a BITS value).
+ */
+fn hex_octets(s: &str) -> Vec<u8> {
+    s.split_whitespace()
+        .map_while(|t| {
+            if t.len() == 2 {
+                u8::from_str_radix(t, 16).ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn first_number<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.split_whitespace().next()?.parse().ok()
+}
+
+/**
+ * Enumerated integers are printed as "name(value)" when a MIB is loaded.
+ */
+fn enum_or_number(s: &str) -> Option<i32> {
+    let t = s.split_whitespace().next()?;
+    if let Some((_, num)) = t.strip_suffix(')').and_then(|t| t.split_once('('))
+    {
+        num.parse().ok()
+    } else {
+        t.parse().ok()
+    }
+}
+
+/**
+ * snmpwalk decodes the net-snmp Opaque float and double encodings, printing
+ * them as "Opaque: Float: 1.5" or "Opaque: Double: 1.5"; reconstruct the
+ * original encoding.
+ */
+fn opaque_number(kind: &str, num: &str) -> Option<Vec<u8>> {
+    match kind {
+        "Float" => {
+            let mut buf = vec![0x9f, 0x78, 0x04];
+            buf.extend(num.parse::<f32>().ok()?.to_be_bytes());
+            Some(buf)
+        }
+        "Double" => {
+            let mut buf = vec![0x9f, 0x79, 0x08];
+            buf.extend(num.parse::<f64>().ok()?.to_be_bytes());
+            Some(buf)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,338 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use std::{fmt::Display, net::SocketAddr};
+
+use csnmp::message::ErrorStatus;
+
+use crate::{extract::FieldError, value::Value, Oid};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/**
+ * The variable binding exceptions that an SNMPv2 agent may return in place of
+ * a value.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Exception::NoSuchObject => "no such object",
+            Exception::NoSuchInstance => "no such instance",
+            Exception::EndOfMibView => "end of MIB view",
+        })
+    }
+}
+
+/**
+ * Errors produced by this crate.  Where a particular OID was involved in the
+ * failure, it is included so that callers can report or act on it; e.g., by
+ * retrying on Timeout but not on UnknownName.  An Oid holds its sub-identifiers
+ * inline, so it is boxed here to keep the error small.
+ *
+ * The type implements std::error::Error, so it may be used with anyhow or
+ * similar crates by the consumer.
+ */
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /**
+     * No response was received from the agent within the timeout, after
+     * exhausting any retries.
+     */
+    Timeout { target: SocketAddr, oid: Option<Box<Oid>> },
+    /**
+     * A socket could not be created, or a request could not be sent.
+     */
+    Socket { target: SocketAddr, source: std::io::Error },
+    /**
+     * The agent sent a response that could not be decoded, or that did not
+     * correspond to the request.
+     */
+    Protocol { target: SocketAddr, oid: Option<Box<Oid>>, message: String },
+    /**
+     * The agent reported an error in response to a request; e.g., tooBig,
+     * noSuchName, wrongType or notWritable.  The index counts variable
+     * bindings in the request from 1, and the OID is that of the binding to
+     * which the error applies, if any.
+     */
+    Agent { status: ErrorStatus, index: u32, oid: Option<Box<Oid>> },
+    /**
+     * During a walk, the agent returned an OID that did not follow the one
+     * before it, which would otherwise lead to an endless loop.
+     */
+    NonIncreasing { previous: Box<Oid>, next: Box<Oid> },
+    /**
+     * A walk returned more values than the configured limit.
+     */
+    TooManyValues { top: Box<Oid>, limit: usize },
+    /**
+     * A walk did not complete before the configured deadline.  The last OID
+     * received, if any, is included.
+     */
+    WalkDeadline { top: Box<Oid>, last: Option<Box<Oid>> },
+    /**
+     * The agent returned an exception rather than a value for an OID.
+     */
+    Exception { oid: Box<Oid>, exception: Exception },
+    /**
+     * A name could not be resolved to an OID, either from the root of the tree
+     * or from the provided parent OID.
+     */
+    UnknownName { name: String, under: Option<Box<Oid>>, message: String },
+    /**
+     * An OID does not have a name in the tree.
+     */
+    UnknownOid { oid: Box<Oid> },
+    /**
+     * The tree could not be modified as requested.
+     */
+    Tree { oid: Vec<u32>, message: String },
+    /**
+     * The table size object was not found in the walked values.
+     */
+    MissingTableSize { oid: Box<Oid> },
+    /**
+     * The table size object did not have a usable value.
+     */
+    InvalidTableSize { oid: Box<Oid>, value: Box<Value> },
+    /**
+     * A row that the table size says should exist was not found.
+     */
+    MissingRow { table: Box<Oid>, index: u32 },
+    /**
+     * The values under a table or object were not arranged as expected.
+     */
+    TableStructure { oid: Box<Oid>, message: String },
+    /**
+     * A value was rejected before being sent to the agent, because it is
+     * outside the range allowed for the named object.
+     */
+    InvalidSetting { name: String, message: String },
+    /**
+     * A command was refused by a safety policy before being sent.  Each
+     * reason describes one command that the policy did not allow.
+     */
+    PolicyRefused { reasons: Vec<String> },
+    /**
+     * There is no such module in a daisy chain of devices.
+     */
+    NoSuchModule { module: u32 },
+    /**
+     * There is no outlet with this number on a module in a daisy chain of
+     * devices.
+     */
+    NoSuchOutlet { module: u32, number: u32 },
+    /**
+     * There is no bank with this number on a module in a daisy chain of
+     * devices.
+     */
+    NoSuchBank { module: u32, number: u32 },
+    /**
+     * The values beneath this OID were not among those fetched from the
+     * agent.
+     */
+    NotFetched { oid: Box<Oid> },
+    /**
+     * A value, row or object could not be decoded into the requested type.
+     */
+    Decode { oid: Box<Oid>, message: String },
+    /**
+     * A particular value could not be decoded into a field of the requested
+     * type.
+     */
+    DecodeField(Box<FieldError>),
+    /**
+     * A capture file could not be parsed.
+     */
+    Capture { line: usize, message: String },
+    /**
+     * A capture file could not be read or written.
+     */
+    Io(std::io::Error),
+    /**
+     * A SET request succeeded, but the audit record of it could not be
+     * written.  The agent has made the change.
+     */
+    Audit(std::io::Error),
+}
+
+impl Error {
+    /**
+     * Was this a failure to hear back from the agent at all?
+     */
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout { .. })
+    }
+
+    /**
+     * The agent error status, if the agent reported an error.
+     */
+    pub fn agent_status(&self) -> Option<ErrorStatus> {
+        match self {
+            Error::Agent { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /**
+     * The OID most closely involved in the failure, if there is one.
+     */
+    pub fn oid(&self) -> Option<Oid> {
+        match self {
+            Error::Timeout { oid, .. }
+            | Error::Protocol { oid, .. }
+            | Error::Agent { oid, .. } => oid.as_deref().copied(),
+            Error::NonIncreasing { next, .. } => Some(**next),
+            Error::TooManyValues { top, .. } => Some(**top),
+            Error::WalkDeadline { top, last } => {
+                Some(**last.as_ref().unwrap_or(top))
+            }
+            Error::Exception { oid, .. }
+            | Error::UnknownOid { oid }
+            | Error::MissingTableSize { oid }
+            | Error::InvalidTableSize { oid, .. }
+            | Error::TableStructure { oid, .. }
+            | Error::NotFetched { oid }
+            | Error::Decode { oid, .. } => Some(**oid),
+            Error::DecodeField(fe) => Some(fe.oid),
+            Error::UnknownName { under, .. } => under.as_deref().copied(),
+            Error::MissingRow { table, .. } => Some(**table),
+            Error::InvalidSetting { .. }
+            | Error::PolicyRefused { .. }
+            | Error::NoSuchModule { .. }
+            | Error::NoSuchOutlet { .. }
+            | Error::NoSuchBank { .. }
+            | Error::Socket { .. }
+            | Error::Tree { .. }
+            | Error::Capture { .. }
+            | Error::Io(_)
+            | Error::Audit(_) => None,
+        }
+    }
+
+    /**
+     * Append a hint to the message of a name lookup failure, such as which MIB
+     * module may be missing from the tree.
+     */
+    pub(crate) fn hint(self, hint: &str) -> Error {
+        match self {
+            Error::UnknownName { name, under, message } => Error::UnknownName {
+                name,
+                under,
+                message: format!("{message} ({hint})"),
+            },
+            other => other,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Timeout { target, oid: Some(oid) } => {
+                write!(f, "timed out waiting for {target} (requesting {oid})")
+            }
+            Error::Timeout { target, oid: None } => {
+                write!(f, "timed out waiting for {target}")
+            }
+            Error::Socket { target, source } => {
+                write!(f, "socket error talking to {target}: {source}")
+            }
+            Error::Protocol { target, oid: Some(oid), message } => {
+                write!(f, "bad response from {target} for {oid}: {message}")
+            }
+            Error::Protocol { target, oid: None, message } => {
+                write!(f, "bad response from {target}: {message}")
+            }
+            Error::Agent { status, index, oid: Some(oid) } => {
+                write!(f, "agent error {status:?} at binding {index} ({oid})")
+            }
+            Error::Agent { status, oid: None, .. } => {
+                write!(f, "agent error {status:?}")
+            }
+            Error::NonIncreasing { previous, next } => {
+                write!(f, "OID {next} does not follow {previous}")
+            }
+            Error::TooManyValues { top, limit } => {
+                write!(f, "walk of {top} exceeded {limit} values")
+            }
+            Error::WalkDeadline { top, last: Some(last) } => {
+                write!(f, "walk of {top} ran out of time after {last}")
+            }
+            Error::WalkDeadline { top, last: None } => {
+                write!(f, "walk of {top} ran out of time")
+            }
+            Error::Exception { oid, exception } => {
+                write!(f, "{exception} at {oid}")
+            }
+            Error::UnknownName { name, under: Some(under), message } => {
+                write!(f, "mapping OID {name:?} under {under}: {message}")
+            }
+            Error::UnknownName { name, under: None, message } => {
+                write!(f, "mapping OID {name:?}: {message}")
+            }
+            Error::UnknownOid { oid } => write!(f, "no name found for {oid}"),
+            Error::Tree { oid, message } if oid.is_empty() => {
+                write!(f, "OID tree: {message}")
+            }
+            Error::Tree { oid, message } => {
+                write!(f, "OID tree at {oid:?}: {message}")
+            }
+            Error::MissingTableSize { oid } => {
+                write!(f, "could not locate table size at {oid}")
+            }
+            Error::InvalidTableSize { oid, value } => {
+                write!(f, "invalid size {value:?} at {oid}")
+            }
+            Error::MissingRow { table, index } => {
+                write!(f, "table {table} is missing index {index}")
+            }
+            Error::TableStructure { oid, message } => {
+                write!(f, "unusual table structure at {oid}: {message}")
+            }
+            Error::InvalidSetting { name, message } => {
+                write!(f, "invalid {name}: {message}")
+            }
+            Error::PolicyRefused { reasons } => {
+                write!(f, "refused by policy: {}", reasons.join("; "))
+            }
+            Error::NoSuchModule { module } => write!(f, "no module {module}"),
+            Error::NoSuchOutlet { module, number } => {
+                write!(f, "no outlet {number} on module {module}")
+            }
+            Error::NoSuchBank { module, number } => {
+                write!(f, "no bank {number} on module {module}")
+            }
+            Error::NotFetched { oid } => {
+                write!(f, "values under {oid} were not fetched")
+            }
+            Error::Decode { oid, message } => {
+                write!(f, "decoding {oid}: {message}")
+            }
+            Error::DecodeField(fe) => write!(f, "decoding {fe}"),
+            Error::Capture { line, message } => {
+                write!(f, "capture line {line}: {message}")
+            }
+            Error::Io(e) => write!(f, "capture I/O: {e}"),
+            Error::Audit(e) => write!(f, "writing audit record: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Socket { source, .. } => Some(source),
+            Error::Io(e) | Error::Audit(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,572 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * Decoding of the values in a table row, or the scalars of an object, into a
+ * structure.  WalkedValues::extract_table() and extract_object() stop at the
+ * first value that does not decode; their lenient forms instead leave such
+ * values out, as if the agent had not returned them, and report each one.
+ *
+ * When a structure does not match what an agent returns (e.g., after a
+ * firmware update), WalkedValues::column_report() shows which columns were
+ * ignored and which fields had no column.
+ *
+ * WalkedValues::extract_table_with(), extract_rows() and
+ * extract_table_lenient_with() take a TableShape, which can put the index of
+ * each row into a field of the structure, and join further tables that share
+ * the index into each row.
+ *
+ * When the same table is extracted over and over, such as when polling many
+ * devices, a TablePlan resolves its columns to the fields of the structure
+ * once, for use with WalkedValues::extract_planned().
+ */
+
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+
+use serde::de::value::{BorrowedStrDeserializer, U32Deserializer};
+use serde::de::{DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer};
+
+use crate::oidtree::OidTree;
+use crate::{value::Value, Error, Oid};
+
+/**
+ * How the rows of a table are decoded into a structure, beyond the columns of
+ * the table itself.  A shape is used by WalkedValues::extract_table_with(),
+ * extract_rows() and extract_table_lenient_with().  Extraction with a
+ * TablePlan, and Client::table_stream(), decode only the columns of the one
+ * table, without an index field.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct TableShape {
+    /**
+     * The field into which to decode the index of each row, as a u32,
+     * whether or not the agent returns a column with the index.  Such a
+     * column, if it has the same name, is ignored.
+     */
+    pub index_field: Option<String>,
+    /**
+     * Further tables with the same index, each of which is joined into the
+     * rows of the table being extracted.
+     */
+    pub groups: Vec<TableGroup>,
+}
+
+/**
+ * A table whose rows are joined into those of another by index; e.g., the
+ * rPDU2 outlet status table, joined into the outlet configuration table.
+ */
+#[derive(Debug, Clone)]
+pub struct TableGroup {
+    /**
+     * The field into which the columns of this table are decoded as a
+     * nested structure.  A row with no values in this table has no such
+     * field, so the field should be an Option if that is expected.  If None,
+     * the columns are instead decoded into the outer structure, except for
+     * any that it already has, such as a shared Index column.
+     */
+    pub field: Option<String>,
+    pub entry: Oid,
+    pub strip_name_prefix: String,
+}
+
+impl TableGroup {
+    pub fn nested(
+        field: &str,
+        entry: Oid,
+        strip_name_prefix: &str,
+    ) -> TableGroup {
+        TableGroup {
+            field: Some(field.to_string()),
+            entry,
+            strip_name_prefix: strip_name_prefix.to_string(),
+        }
+    }
+
+    pub fn flattened(entry: Oid, strip_name_prefix: &str) -> TableGroup {
+        TableGroup {
+            field: None,
+            entry,
+            strip_name_prefix: strip_name_prefix.to_string(),
+        }
+    }
+}
+
+/**
+ * The columns of a table, resolved once for decoding into a particular
+ * structure, so that rows can be decoded without looking up the name of each
+ * value.  Columns that the structure has no field for are skipped without
+ * being decoded, so a structure with #[serde(deny_unknown_fields)] does not
+ * see them.  A plan covers the columns of one table only, so it cannot be
+ * combined with a TableShape.
+ */
+pub struct TablePlan<T> {
+    pub(crate) table_size: Oid,
+    pub(crate) table_entry: Oid,
+    /**
+     * The number of each column under the entry, in order, with the name of
+     * its field, or None if the structure has no such field.
+     */
+    columns: Vec<(u32, Option<String>)>,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> TablePlan<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    pub fn new(
+        tree: &OidTree,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<TablePlan<T>, Error> {
+        let fields = struct_fields::<T>();
+
+        let mut columns = tree
+            .children(table_entry)?
+            .into_iter()
+            .map(|oid| {
+                let n = tree.oid_name(oid)?;
+                let Some(name) = n.basename().strip_prefix(strip_name_prefix)
+                else {
+                    return Err(Error::TableStructure {
+                        oid: Box::new(oid),
+                        message: format!(
+                            "name {n} not prefixed with {strip_name_prefix:?}"
+                        ),
+                    });
+                };
+                let number = *oid.as_slice().last().unwrap();
+                let field = match fields {
+                    Some(fields) if !fields.contains(&name) => None,
+                    _ => Some(name.to_string()),
+                };
+                Ok((number, field))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if columns.is_empty() {
+            return Err(Error::TableStructure {
+                oid: Box::new(table_entry),
+                message: "no columns in the OID tree".into(),
+            });
+        }
+        columns.sort_by_key(|(number, _)| *number);
+
+        Ok(TablePlan { table_size, table_entry, columns, _t: PhantomData })
+    }
+}
+
+impl<T> TablePlan<T> {
+    /**
+     * The field for a column, by number: None if the tree has no such
+     * column, or Some(None) if the structure has no field for it.
+     */
+    pub(crate) fn column(&self, number: u32) -> Option<Option<&str>> {
+        let i = self
+            .columns
+            .binary_search_by_key(&number, |(number, _)| *number)
+            .ok()?;
+        Some(self.columns[i].1.as_deref())
+    }
+}
+
+impl<T> std::fmt::Debug for TablePlan<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TablePlan")
+            .field("table_size", &self.table_size)
+            .field("table_entry", &self.table_entry)
+            .field("columns", &self.columns)
+            .finish()
+    }
+}
+
+/**
+ * A value that could not be decoded into the field of a structure.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub oid: Oid,
+    /**
+     * The name of the OID in the tree, in the short form; e.g.,
+     * "rPDU2OutletSwitchedStatusState[2]".
+     */
+    pub name: Option<String>,
+    /**
+     * The index of the row, for a value in a table.
+     */
+    pub index: Option<u32>,
+    /**
+     * The name of the column or object, without the prefix that was
+     * stripped; i.e., the name of the field it was to be decoded into.  For
+     * a column of a nested table group, this is preceded by the name of the
+     * group field; e.g., "Status.State".
+     */
+    pub field: String,
+    pub value: Value,
+    pub message: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name} ({})", self.oid)?,
+            None => write!(f, "{}", self.oid)?,
+        }
+        if let Some(index) = self.index {
+            write!(f, ", row {index}")?;
+        }
+        write!(
+            f,
+            ", field {:?}: {} (value {:?})",
+            self.field, self.message, self.value
+        )
+    }
+}
+
+/**
+ * The result of a lenient extraction.
+ */
+#[derive(Debug)]
+pub struct Extraction<T> {
+    pub value: T,
+    /**
+     * Values that could not be decoded, and were left out.
+     */
+    pub errors: Vec<FieldError>,
+    /**
+     * Rows of a table that could not be decoded even without those values
+     * (e.g., because a field that is not optional was left out), with the
+     * reason, by index.  These rows are missing from the value.
+     */
+    pub skipped: BTreeMap<u32, String>,
+}
+
+impl<T> Extraction<T> {
+    /**
+     * Whether everything the agent returned was decoded.
+     */
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/**
+ * Which columns of a table, or objects under a root, were ignored when
+ * decoding into a structure, and which fields of the structure had nothing
+ * to decode.  Names are those of the fields; i.e., with the prefix stripped.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnReport {
+    /**
+     * The fields of the structure, or None if it does not list them (e.g.,
+     * because it has a flattened field).
+     */
+    pub fields: Option<Vec<&'static str>>,
+    /**
+     * Columns that were walked, but have no field.
+     */
+    pub ignored: Vec<String>,
+    /**
+     * Fields that have no column in any row, which must be optional or have
+     * a default for decoding to succeed.
+     */
+    pub missing: Vec<&'static str>,
+}
+
+impl ColumnReport {
+    pub(crate) fn new<T>(columns: BTreeSet<&str>) -> ColumnReport
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let Some(fields) = struct_fields::<T>() else {
+            return ColumnReport {
+                fields: None,
+                ignored: Vec::new(),
+                missing: Vec::new(),
+            };
+        };
+
+        ColumnReport {
+            fields: Some(fields.to_vec()),
+            ignored: columns
+                .iter()
+                .filter(|c| !fields.contains(c))
+                .map(|c| c.to_string())
+                .collect(),
+            missing: fields
+                .iter()
+                .filter(|f| !columns.contains(*f))
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for ColumnReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.fields.is_none() {
+            return writeln!(f, "the structure does not list its fields");
+        }
+        writeln!(f, "ignored columns: {}", list(&self.ignored))?;
+        writeln!(f, "fields without a column: {}", list(&self.missing))
+    }
+}
+
+fn list<S: AsRef<str>>(names: &[S]) -> String {
+    if names.is_empty() {
+        return "none".into();
+    }
+    names.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ")
+}
+
+/**
+ * One value to decode, with the name of the field it belongs in, and of the
+ * nested structure that field is in, if any.
+ */
+pub(crate) struct Field<'a> {
+    pub(crate) name: Cow<'a, str>,
+    pub(crate) group: Option<String>,
+    pub(crate) oid: Oid,
+    pub(crate) value: &'a Value,
+}
+
+/**
+ * Decode a row of values, or the scalars of an object, into a structure.  If
+ * errors are being collected, any value that fails to decode is recorded and
+ * removed, and the row is decoded again without it, until it either succeeds
+ * or fails for some other reason.
+ */
+pub(crate) fn decode<T>(
+    tree: &OidTree,
+    root: Oid,
+    index: Option<u32>,
+    index_field: Option<&str>,
+    mut fields: Vec<Field<'_>>,
+    mut errors: Option<&mut Vec<FieldError>>,
+) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let injected = index_field.zip(index);
+
+    loop {
+        let failed = Cell::new(None);
+        let res = T::deserialize(RowDeserializer::new(
+            &fields, None, injected, &failed,
+        ));
+        let e = match res {
+            Ok(t) => return Ok(t),
+            Err(e) => e,
+        };
+
+        /*
+         * If a particular value could not be decoded, describe it fully.
+         * Otherwise, such as when a field has no value at all, the failure
+         * belongs to the row as a whole.
+         */
+        let Some(i) = failed.get() else {
+            let message = match index {
+                Some(index) => format!("row {index}: {e}"),
+                None => e.to_string(),
+            };
+            return Err(Error::Decode { oid: Box::new(root), message });
+        };
+        let f = fields.remove(i);
+        let fe = FieldError {
+            oid: f.oid,
+            name: tree.oid_name(f.oid).ok().map(|n| format!("{n:#}")),
+            index,
+            field: match f.group {
+                Some(group) => format!("{group}.{}", f.name),
+                None => f.name.into_owned(),
+            },
+            value: f.value.clone(),
+            message: e.to_string(),
+        };
+        match errors.as_deref_mut() {
+            Some(errors) => errors.push(fe),
+            None => return Err(Error::DecodeField(Box::new(fe))),
+        }
+    }
+}
+
+/**
+ * The names of the fields of a structure, as listed by its Deserialize
+ * implementation.
+ */
+fn struct_fields<T>() -> Option<&'static [&'static str]>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    struct Probe<'a>(&'a Cell<Option<&'static [&'static str]>>);
+
+    impl<'de> Deserializer<'de> for Probe<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V>(self, _v: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            Err(serde::de::Error::custom("not a structure"))
+        }
+
+        fn deserialize_struct<V>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _v: V,
+        ) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            self.0.set(Some(fields));
+            Err(serde::de::Error::custom("fields listed"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str
+            string bytes byte_buf option unit unit_struct newtype_struct seq
+            tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let fields = Cell::new(None);
+    let _ = T::deserialize(Probe(&fields));
+    fields.get()
+}
+
+/**
+ * What is presented under one key of a row.
+ */
+enum Entry<'a> {
+    /**
+     * The value at a position in the row.
+     */
+    Field(usize),
+    /**
+     * The values of a nested table group, as a map of their own.
+     */
+    Group(&'a str),
+    Index(&'a str, u32),
+}
+
+/**
+ * Presents a row, or the part of it in one nested group, as a map from field
+ * name to value, noting the position of any value that fails to decode.
+ */
+struct RowDeserializer<'a> {
+    fields: &'a [Field<'a>],
+    entries: Vec<Entry<'a>>,
+    pos: usize,
+    failed: &'a Cell<Option<usize>>,
+}
+
+impl<'a> RowDeserializer<'a> {
+    fn new(
+        fields: &'a [Field<'a>],
+        group: Option<&'a str>,
+        index: Option<(&'a str, u32)>,
+        failed: &'a Cell<Option<usize>>,
+    ) -> RowDeserializer<'a> {
+        let mut entries = Vec::new();
+        if let Some((name, i)) = index {
+            entries.push(Entry::Index(name, i));
+        }
+        for (i, f) in fields.iter().enumerate() {
+            let fg = f.group.as_deref();
+            if fg == group {
+                if index.is_none_or(|(name, _)| name != f.name) {
+                    entries.push(Entry::Field(i));
+                }
+            } else if let (None, Some(fg)) = (group, fg) {
+                /*
+                 * At the top level, a group is presented once, under the
+                 * name of its field.
+                 */
+                if !entries
+                    .iter()
+                    .any(|e| matches!(e, Entry::Group(g) if *g == fg))
+                {
+                    entries.push(Entry::Group(fg));
+                }
+            }
+        }
+
+        RowDeserializer { fields, entries, pos: 0, failed }
+    }
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'de> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V>(self, v: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        v.visit_map(self)
+    }
+
+    /**
+     * A nested group is present if it has any values in the row.
+     */
+    fn deserialize_option<V>(self, v: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        v.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for RowDeserializer<'de> {
+    type Error = serde::de::value::Error;
+
+    fn next_key_seed<K>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let name = match self.entries.get(self.pos) {
+            Some(Entry::Field(i)) => self.fields[*i].name.as_ref(),
+            Some(Entry::Group(name) | Entry::Index(name, _)) => name,
+            None => return Ok(None),
+        };
+        seed.deserialize(BorrowedStrDeserializer::new(name)).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let pos = self.pos;
+        self.pos += 1;
+        match self.entries[pos] {
+            Entry::Field(i) => seed
+                .deserialize(self.fields[i].value.into_deserializer())
+                .inspect_err(|_| self.failed.set(Some(i))),
+            Entry::Group(group) => seed.deserialize(RowDeserializer::new(
+                self.fields,
+                Some(group),
+                None,
+                self.failed,
+            )),
+            Entry::Index(_, i) => seed.deserialize(U32Deserializer::new(i)),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len() - self.pos)
+    }
+}
@@ -3,26 +3,42 @@
  */
 
 use std::{
+    collections::BTreeMap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     ops::Deref,
-    result::Result as SResult,
     sync::Arc,
     time::Duration,
 };
 
-use anyhow::{anyhow, Result};
-
 /*
  * Re-export the csnmp module we're using:
  */
 pub use csnmp;
 use csnmp::ObjectIdentifier;
+/*
+ * Streaming walks produce a futures::Stream, so re-export that too:
+ */
+pub use futures;
+use futures::Stream;
 use serde::{de::Visitor, Deserialize, Deserializer};
 
+pub mod audit;
+pub mod capture;
+mod error;
+pub mod extract;
 pub mod mib;
 pub mod oidtree;
+pub mod policy;
+pub mod reconcile;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+pub mod tc;
+mod transport;
 pub mod value;
 pub mod walk;
+pub mod watch;
+
+pub use error::{Error, Exception, Result};
 
 #[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct Oid(ObjectIdentifier);
@@ -123,9 +139,18 @@ impl From<ObjectIdentifier> for RelativeOid {
     }
 }
 
+/**
+ * A client for one SNMP agent.  Clones share the same socket, so a clone can
+ * be given a different audit context for each caller.
+ */
+#[derive(Clone)]
 pub struct Client {
-    snmp: csnmp::Snmp2cClient,
+    transport: Arc<transport::Transport>,
     tree: Arc<oidtree::OidTree>,
+    walk_options: walk::WalkOptions,
+    audit: Option<Arc<dyn audit::AuditSink>>,
+    audit_context: audit::AuditContext,
+    read_before_set: bool,
 }
 
 impl Client {
@@ -136,28 +161,240 @@ impl Client {
             community: b"public".to_vec(),
             timeout: Duration::from_secs(5),
             retries: 0,
-            tree: mib::base(),
+            tree: Arc::new(mib::base()),
+            walk_options: Default::default(),
+            audit: None,
+            audit_context: Default::default(),
+            read_before_set: false,
         }
     }
 
+    /**
+     * A clone of this client that records a different principal and reason
+     * in the audit records of its SET requests.
+     */
+    pub fn with_audit_context(&self, context: audit::AuditContext) -> Client {
+        Client { audit_context: context, ..self.clone() }
+    }
+
+    pub async fn get(&self, oid: Oid) -> Result<value::Value> {
+        Ok(self.get_multiple(&[oid]).await?.remove(&oid).unwrap())
+    }
+
+    /**
+     * Get several values in a single request.  If the agent has no value for
+     * any of the OIDs, an Exception error is returned for the first of them.
+     */
+    pub async fn get_multiple(
+        &self,
+        oids: &[Oid],
+    ) -> Result<BTreeMap<Oid, value::Value>> {
+        self.transport
+            .get(oids)
+            .await?
+            .into_iter()
+            .map(transport::binding_value)
+            .collect()
+    }
+
+    /**
+     * Get several values in a single request, reporting separately for each
+     * OID whether the agent returned a value or an exception.
+     */
+    pub async fn get_varbinds(
+        &self,
+        oids: &[Oid],
+    ) -> Result<BTreeMap<Oid, value::Varbind>> {
+        Ok(self
+            .transport
+            .get(oids)
+            .await?
+            .into_iter()
+            .map(transport::varbind)
+            .collect())
+    }
+
+    /**
+     * Issue a single GETBULK request.  The results are returned in the order
+     * provided by the agent, which is generally interleaved by repetition,
+     * and include any exceptions (e.g., endOfMibView) in place of values.
+     */
+    pub async fn get_bulk(
+        &self,
+        oids: &[Oid],
+        non_repeaters: u32,
+        max_repetitions: u32,
+    ) -> Result<Vec<(Oid, value::Varbind)>> {
+        Ok(self
+            .transport
+            .get_bulk(oids, non_repeaters, max_repetitions)
+            .await?
+            .into_iter()
+            .map(transport::varbind)
+            .collect())
+    }
+
     pub async fn set(
         &self,
         oid: Oid,
         value: value::Value,
-    ) -> SResult<value::Value, csnmp::SnmpClientError> {
-        self.snmp.set(oid.0, value.0).await.map(|val| value::Value(val))
+    ) -> Result<value::Value> {
+        let mut res = self.set_bindings(&[(oid, value)]).await?;
+        Ok(transport::binding_value(res.remove(0))?.1)
+    }
+
+    /**
+     * Set several values in a single request.  The agent either makes every
+     * change or, if it reports an error for any of them, none at all.  The
+     * values returned are those in the agent's response.
+     */
+    pub async fn set_multiple(
+        &self,
+        values: &[(Oid, value::Value)],
+    ) -> Result<BTreeMap<Oid, value::Value>> {
+        self.set_bindings(values)
+            .await?
+            .into_iter()
+            .map(transport::binding_value)
+            .collect()
     }
 
+    /**
+     * Make a SET request, and record it with the audit sink if there is one.
+     */
+    async fn set_bindings(
+        &self,
+        values: &[(Oid, value::Value)],
+    ) -> Result<Vec<csnmp::message::VariableBinding>> {
+        let Some(sink) = &self.audit else {
+            return self.transport.set(values).await;
+        };
+
+        /*
+         * A failure to read the old values is not a reason to refuse the
+         * change, so the records just go without them.
+         */
+        let mut old = BTreeMap::new();
+        if self.read_before_set {
+            let oids = values.iter().map(|(oid, _)| *oid).collect::<Vec<_>>();
+            if let Ok(res) = self.transport.get(&oids).await {
+                old.extend(res.into_iter().filter_map(|vb| {
+                    let (oid, vb) = transport::varbind(vb);
+                    Some((oid, vb.into_value()?))
+                }));
+            }
+        }
+
+        let res = self.transport.set(values).await;
+
+        let timestamp = std::time::SystemTime::now();
+        let records = values
+            .iter()
+            .enumerate()
+            .map(|(i, (oid, new))| audit::AuditRecord {
+                timestamp,
+                target: self.transport.target(),
+                oid: *oid,
+                name: self.tree.oid_name(*oid).ok().map(|n| format!("{n:#}")),
+                old: old.remove(oid),
+                new: new.clone(),
+                result: match &res {
+                    Ok(resp) => resp
+                        .get(i)
+                        .cloned()
+                        .ok_or_else(|| "missing from response".to_string())
+                        .and_then(|vb| {
+                            transport::binding_value(vb)
+                                .map(|(_, v)| v)
+                                .map_err(|e| e.to_string())
+                        }),
+                    Err(e) => Err(e.to_string()),
+                },
+                principal: self.audit_context.principal.clone(),
+                reason: self.audit_context.reason.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        /*
+         * If the request failed, that is the error to report: Error::Audit
+         * would hide why the change was not made.
+         */
+        match (res, sink.record(&records)) {
+            (Ok(_), Err(e)) => Err(Error::Audit(e)),
+            (res, _) => res,
+        }
+    }
+
+    /**
+     * Walk the subtree beneath an OID, using the walk options provided when
+     * the client was built.
+     */
     pub async fn walk(&self, top: Oid) -> Result<walk::WalkedValues> {
-        let res = self.snmp.walk_bulk(top.0, 63).await?;
+        self.walk_with(top, &self.walk_options).await
+    }
 
-        Ok(walk::WalkedValues {
-            values: res
-                .into_iter()
-                .map(|(k, v)| (Oid(k), value::Value(v)))
-                .collect(),
-            tree: Arc::clone(&self.tree),
-        })
+    /**
+     * Walk the subtree beneath an OID with specific walk options.
+     */
+    pub async fn walk_with(
+        &self,
+        top: Oid,
+        options: &walk::WalkOptions,
+    ) -> Result<walk::WalkedValues> {
+        let values = walk::walk(&self.transport, top, options).await?;
+
+        Ok(walk::WalkedValues { values, tree: Arc::clone(&self.tree) })
+    }
+
+    /**
+     * Walk the subtree beneath an OID, using the walk options provided when
+     * the client was built, producing each value in order as it arrives
+     * rather than once the walk is complete.  The stream ends after the last
+     * value, or after an error.  WalkedValues::from_stream() will collect the
+     * values, keeping those received before any error.
+     */
+    pub fn walk_stream(
+        &self,
+        top: Oid,
+    ) -> impl Stream<Item = Result<(Oid, value::Value)>> + '_ {
+        self.walk_stream_with(top, &self.walk_options)
+    }
+
+    /**
+     * Walk the subtree beneath an OID with specific walk options, producing
+     * each value as it arrives.
+     */
+    pub fn walk_stream_with(
+        &self,
+        top: Oid,
+        options: &walk::WalkOptions,
+    ) -> impl Stream<Item = Result<(Oid, value::Value)>> + '_ {
+        walk::walk_stream(&self.transport, top, options)
+    }
+
+    /**
+     * Walk the columns of a table together, as "snmptable" does, producing
+     * each row as soon as it is complete.  The columns are those beneath the
+     * table entry in the OID tree, named as for WalkedValues::extract_table().
+     * There is no form that takes a TableShape, as the rows are decoded
+     * before any other table could be walked to join into them.  The stream
+     * ends after the last row, or after an error.
+     */
+    pub fn table_stream<T>(
+        &self,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+    ) -> impl Stream<Item = Result<(u32, T)>> + '_
+    where
+        T: for<'de> Deserialize<'de> + 'static,
+    {
+        walk::table_stream(
+            &self.transport,
+            &self.tree,
+            table_entry,
+            strip_name_prefix,
+            &self.walk_options,
+        )
     }
 
     pub fn tree(&self) -> &oidtree::OidTree {
@@ -171,7 +408,11 @@ pub struct ClientBuilder {
     community: Vec<u8>,
     timeout: Duration,
     retries: usize,
-    tree: oidtree::OidTree,
+    tree: Arc<oidtree::OidTree>,
+    walk_options: walk::WalkOptions,
+    audit: Option<Arc<dyn audit::AuditSink>>,
+    audit_context: audit::AuditContext,
+    read_before_set: bool,
 }
 
 impl ClientBuilder {
@@ -185,12 +426,71 @@ impl ClientBuilder {
         self
     }
 
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(&mut self, retries: usize) -> &mut Self {
+        self.retries = retries;
+        self
+    }
+
+    /**
+     * Set the default options for walks made with Client::walk().
+     */
+    pub fn walk_options(&mut self, options: walk::WalkOptions) -> &mut Self {
+        self.walk_options = options;
+        self
+    }
+
+    /**
+     * Record every SET request made by the client, and its clones, with this
+     * sink.
+     */
+    pub fn audit_sink(&mut self, sink: Arc<dyn audit::AuditSink>) -> &mut Self {
+        self.audit = Some(sink);
+        self
+    }
+
+    /**
+     * The principal and reason to record for SET requests, until changed
+     * with Client::with_audit_context().
+     */
+    pub fn audit_context(&mut self, context: audit::AuditContext) -> &mut Self {
+        self.audit_context = context;
+        self
+    }
+
+    /**
+     * Read each value before setting it, so that the audit record has the
+     * old value as well as the new.  This costs an extra request for each
+     * SET, and has no effect without an audit sink.
+     */
+    pub fn read_before_set(&mut self, read: bool) -> &mut Self {
+        self.read_before_set = read;
+        self
+    }
+
+    /**
+     * Use a prebuilt OID tree in place of the default base tree.  The tree is
+     * shared with, rather than copied into, every client built from this
+     * builder.  If the tree is subsequently modified with with_oid_tree(), a
+     * private copy is made first.
+     */
+    pub fn oid_tree(&mut self, tree: Arc<oidtree::OidTree>) -> &mut Self {
+        self.tree = tree;
+        self
+    }
+
     pub fn with_oid_tree<E: std::fmt::Display + Send + Sync>(
         &mut self,
         func: impl Fn(&mut oidtree::OidTree) -> std::result::Result<(), E>,
     ) -> Result<&mut Self> {
-        func(&mut self.tree)
-            .map_err(|e| anyhow!("client builder with_oid_tree(): {e}"))?;
+        func(Arc::make_mut(&mut self.tree)).map_err(|e| Error::Tree {
+            oid: Vec::new(),
+            message: format!("client builder with_oid_tree(): {e}"),
+        })?;
         Ok(self)
     }
 
@@ -216,15 +516,22 @@ impl ClientBuilder {
             )
         };
 
-        let snmp = csnmp::Snmp2cClient::new(
+        let transport = transport::Transport::new(
+            bind,
             target,
             self.community.clone(),
-            Some(bind),
-            Some(self.timeout),
+            self.timeout,
             self.retries,
         )
         .await?;
 
-        Ok(Client { snmp, tree: Arc::new(self.tree.clone()) })
+        Ok(Client {
+            transport: Arc::new(transport),
+            tree: Arc::clone(&self.tree),
+            walk_options: self.walk_options.clone(),
+            audit: self.audit.clone(),
+            audit_context: self.audit_context.clone(),
+            read_before_set: self.read_before_set,
+        })
     }
 }
@@ -34,17 +34,105 @@ pub struct Ident {
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
 pub struct BankConfiguration {
-    index: u32,
-    module: u32,
-    number: u32,
-    overload_restriction: OverloadRestriction,
-    low_load_current_threshold: u32,
-    near_overload_current_threshold: u32,
-    overload_current_threshold: u32,
-    bank_peak_current_reset: PeakCurrentReset,
+    pub index: u32,
+    pub module: u32,
+    pub number: u32,
+    pub overload_restriction: OverloadRestriction,
+    /**
+     * In amps, as are the other thresholds.
+     */
+    pub low_load_current_threshold: u32,
+    pub near_overload_current_threshold: u32,
+    pub overload_current_threshold: u32,
+    pub bank_peak_current_reset: PeakCurrentReset,
+}
+
+/**
+ * Changes to the configuration of one bank, as made by Pdu::configure_bank().
+ * Anything left as None is not changed.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BankConfigChange {
+    pub overload_restriction: Option<OverloadRestriction>,
+    pub low_load_current_threshold: Option<u32>,
+    pub near_overload_current_threshold: Option<u32>,
+    pub overload_current_threshold: Option<u32>,
+    /**
+     * Start measuring the peak current afresh.
+     */
+    pub reset_peak_current: bool,
+}
+
+impl BankConfigChange {
+    /**
+     * Check the parts of the change that do not depend on the current
+     * configuration of the bank.  Pdu::configure_bank() and
+     * reconcile::Plan::new() also check that the thresholds will be in
+     * order, and within the breaker rating.
+     */
+    pub fn validate(&self) -> Result<()> {
+        if self.overload_restriction == Some(OverloadRestriction::NotSupported)
+        {
+            return Err(invalid(
+                "rPDU2BankConfigOverloadRestriction",
+                "notSupported may not be set".into(),
+            ));
+        }
+        for (name, t) in [
+            (
+                "rPDU2BankConfigLowLoadCurrentThreshold",
+                self.low_load_current_threshold,
+            ),
+            (
+                "rPDU2BankConfigNearOverloadCurrentThreshold",
+                self.near_overload_current_threshold,
+            ),
+            (
+                "rPDU2BankConfigOverloadCurrentThreshold",
+                self.overload_current_threshold,
+            ),
+        ] {
+            if let Some(t) = t {
+                check_range(name, i64::from(t), 0..=i64::from(i32::MAX))?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Check that the thresholds will be in order, and the overload threshold
+     * within the breaker rating, once this change is made to a bank with the
+     * provided thresholds.
+     */
+    pub(crate) fn check_thresholds(
+        &self,
+        [low, near, over]: [i64; 3],
+        rating: i64,
+    ) -> Result<()> {
+        let low = self.low_load_current_threshold.map_or(low, i64::from);
+        let near = self.near_overload_current_threshold.map_or(near, i64::from);
+        let over = self.overload_current_threshold.map_or(over, i64::from);
+
+        check_range(
+            "rPDU2BankConfigNearOverloadCurrentThreshold",
+            near,
+            low..=over,
+        )?;
+        check_range(
+            "rPDU2BankConfigOverloadCurrentThreshold",
+            over,
+            near..=rating,
+        )
+    }
+
+    fn has_thresholds(&self) -> bool {
+        self.low_load_current_threshold.is_some()
+            || self.near_overload_current_threshold.is_some()
+            || self.overload_current_threshold.is_some()
+    }
 }
 
-#[derive(Deserialize_repr, PartialEq, Eq, Debug)]
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
 #[repr(i32)]
 pub enum OverloadRestriction {
     AlwaysAllowTurnOn = 1,
@@ -53,7 +141,7 @@ pub enum OverloadRestriction {
     NotSupported = 4,
 }
 
-#[derive(Deserialize_repr, PartialEq, Eq, Debug)]
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
 #[repr(i32)]
 pub enum PeakCurrentReset {
     NoOperation = 1,
@@ -64,25 +152,31 @@ pub enum PeakCurrentReset {
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
 pub struct BankProperties {
-    index: u32,
-    module: u32,
-    number: u32,
-    phase_layout: PhaseLayoutType,
-    breaker_rating: u32,
+    pub index: u32,
+    pub module: u32,
+    pub number: u32,
+    pub phase_layout: PhaseLayoutType,
+    /**
+     * In amps.
+     */
+    pub breaker_rating: u32,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
 pub struct BankStatus {
-    index: u32,
-    module: u32,
-    number: u32,
-    load_state: LoadState,
-    current: u32,
-    peak_current: u32,
-    peak_current_timestamp: String,
-    peak_current_start_time: String,
+    pub index: u32,
+    pub module: u32,
+    pub number: u32,
+    pub load_state: LoadState,
+    /**
+     * In amps, as is the peak current.
+     */
+    pub current: Scaled<1>,
+    pub peak_current: Scaled<1>,
+    pub peak_current_timestamp: String,
+    pub peak_current_start_time: String,
 }
 
 #[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
@@ -118,6 +212,114 @@ pub enum OutletCommand {
     CancelPendingCommand = 8,
 }
 
+/**
+ * A command for every outlet on a PDU at once, as used with
+ * Pdu::send_device_command().
+ */
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum DeviceCommand {
+    ImmediateAllOn = 1,
+    ImmediateAllOff = 2,
+    ImmediateAllReboot = 3,
+    DelayedAllOn = 4,
+    DelayedAllOff = 5,
+    DelayedAllReboot = 6,
+    CancelAllPendingCommands = 7,
+    NoCommand = 8,
+}
+
+/**
+ * Options for Pdu::send_commands().
+ */
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /**
+     * The most commands to send in one SET request.  As with polling, the
+     * management interface struggles with large requests.
+     */
+    pub max_varbinds: usize,
+    /**
+     * If a request fails after earlier requests succeeded, return the outlets
+     * commanded by the earlier requests to their prior state.
+     */
+    pub rollback: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions { max_varbinds: 32, rollback: true }
+    }
+}
+
+/**
+ * What became of the command for one outlet in a batch.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /**
+     * The PDU accepted the command.
+     */
+    Sent,
+    /**
+     * The PDU rejected the request containing the command, so it did not act
+     * on it.
+     */
+    Failed,
+    /**
+     * The request containing the command timed out, or the response did not
+     * confirm the command, so the PDU may or may not have acted on it.
+     */
+    Unknown,
+    /**
+     * The command was not sent, because an earlier request failed.
+     */
+    NotSent,
+    /**
+     * The PDU accepted the command (or may have), but a later request failed
+     * and the outlet has since been turned on or off to match its prior
+     * state.  This does not cancel a delayed command that is still pending,
+     * which may yet change the outlet, nor stop a reboot that is under way,
+     * which may leave the outlet on.
+     */
+    RolledBack,
+    /**
+     * The PDU accepted the command, but a later request failed and the
+     * outlet could not be returned to its prior state.
+     */
+    RollbackFailed,
+}
+
+/**
+ * The result of Pdu::send_commands().
+ */
+#[derive(Debug)]
+pub struct BatchReport {
+    pub outcomes: BTreeMap<u32, CommandOutcome>,
+    /**
+     * The failure that stopped the batch, if there was one.
+     */
+    pub error: Option<Error>,
+    /**
+     * The first failure while rolling back, if there was one.
+     */
+    pub rollback_error: Option<Error>,
+    /**
+     * The first failure to write the audit record of a request that the PDU
+     * accepted, if there was one.  Such a failure does not stop the batch.
+     */
+    pub audit_error: Option<Error>,
+}
+
+impl BatchReport {
+    /**
+     * Did the PDU accept every command?
+     */
+    pub fn is_complete(&self) -> bool {
+        self.outcomes.values().all(|o| *o == CommandOutcome::Sent)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
@@ -132,6 +334,71 @@ pub struct OutletConfig {
     pub external_link: String,
 }
 
+/**
+ * Changes to the configuration of one outlet, as made by
+ * Pdu::configure_outlet().  Anything left as None is not changed.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutletConfigChange {
+    pub name: Option<String>,
+    /**
+     * Seconds to wait before turning the outlet on when the PDU powers up,
+     * or -1 to leave it off.
+     */
+    pub power_on_time: Option<i32>,
+    /**
+     * Seconds to wait before turning the outlet off for a delayed off
+     * command, or -1 to never turn it off.
+     */
+    pub power_off_time: Option<i32>,
+    /**
+     * Seconds to leave the outlet off during a reboot.
+     */
+    pub reboot_duration: Option<u32>,
+    pub external_link: Option<String>,
+}
+
+impl OutletConfigChange {
+    /**
+     * Check each value against the range the device allows, without sending
+     * anything.
+     */
+    pub fn validate(&self) -> Result<()> {
+        if let Some(name) = &self.name {
+            check_string("rPDU2OutletSwitchedConfigName", name, 32)?;
+        }
+        if let Some(t) = self.power_on_time {
+            check_range(
+                "rPDU2OutletSwitchedConfigPowerOnTime",
+                t.into(),
+                -1..=7200,
+            )?;
+        }
+        if let Some(t) = self.power_off_time {
+            check_range(
+                "rPDU2OutletSwitchedConfigPowerOffTime",
+                t.into(),
+                -1..=7200,
+            )?;
+        }
+        if let Some(t) = self.reboot_duration {
+            check_range(
+                "rPDU2OutletSwitchedConfigRebootDuration",
+                t.into(),
+                5..=60,
+            )?;
+        }
+        if let Some(link) = &self.external_link {
+            check_string("rPDU2OutletSwitchedConfigExternalLink", link, 64)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == OutletConfigChange::default()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 #[allow(unused)]
@@ -171,15 +438,46 @@ pub struct OutletStatus {
 }
 
 impl OutletStatus {
-    pub fn is_command_pending(&self) -> Result<bool> {
+    /**
+     * Returns None if the PDU reports that it does not know whether a command
+     * is pending.
+     */
+    pub fn is_command_pending(&self) -> Option<bool> {
         match self.command_pending {
-            CommandPending::Yes => Ok(true),
-            CommandPending::No => Ok(false),
-            CommandPending::Unknown => bail!("command pending state unknown"),
+            CommandPending::Yes => Some(true),
+            CommandPending::No => Some(false),
+            CommandPending::Unknown => None,
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+#[allow(unused)]
+pub struct OutletMeteredStatus {
+    pub index: u32,
+    pub module: u32,
+    pub name: String,
+    pub number: u32,
+
+    pub state: LoadState,
+    /**
+     * In amps.
+     */
+    pub current: Scaled<1>,
+    /**
+     * In watts.
+     */
+    pub power: u32,
+    pub peak_power: u32,
+    pub peak_power_timestamp: String,
+    pub peak_power_start_time: String,
+    /**
+     * In kilowatt-hours.
+     */
+    pub energy: Scaled<1>,
+}
+
 #[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
 #[repr(i32)]
 pub enum CommandPending {
@@ -196,11 +494,400 @@ pub enum State {
 }
 
 /**
- * An object that ties together all of the rPDU2 tables into one large snapshot.
+ * The result of polling one outlet with Pdu::poll_outlets().  Each column is
+ * None if the device did not have a value for it; if the outlet does not exist
+ * at all, every column is None.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutletPoll {
+    pub state: Option<State>,
+    pub command: Option<OutletCommand>,
+    pub command_pending: Option<CommandPending>,
+}
+
+impl OutletPoll {
+    /**
+     * Does the outlet exist at all?
+     */
+    pub fn is_present(&self) -> bool {
+        self.state.is_some()
+            || self.command.is_some()
+            || self.command_pending.is_some()
+    }
+
+    /**
+     * All three columns, if the device provided all of them.
+     */
+    pub fn complete(&self) -> Option<(State, OutletCommand, CommandPending)> {
+        Some((self.state?, self.command?, self.command_pending?))
+    }
+}
+
+/**
+ * Everything known about one outlet, joined from the switched outlet tables
+ * and, on PDUs that meter each outlet, the metered outlet status table.
+ */
+#[derive(Debug)]
+pub struct Outlet {
+    pub index: u32,
+    pub module: u32,
+    pub number: u32,
+    pub name: String,
+    pub bank: u32,
+    pub phase_layout: PhaseLayoutType,
+
+    pub config: OutletConfig,
+    pub props: OutletProperties,
+    pub status: OutletStatus,
+    pub control: OutletControl,
+    pub metered: Option<OutletMeteredStatus>,
+}
+
+/**
+ * A way in which the outlet tables disagree with one another.  Outlets with
+ * an inconsistency of this kind are not included in Outlets.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency {
+    pub index: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "outlet index {}: {}", self.index, self.message)
+    }
+}
+
+/**
+ * The outlets of a PDU, as produced by Pdu::outlets().
+ */
+#[derive(Debug)]
+pub struct Outlets {
+    outlets: BTreeMap<u32, Outlet>,
+    inconsistencies: Vec<Inconsistency>,
+}
+
+impl Outlets {
+    /**
+     * Look up an outlet by its index in the outlet tables.
+     */
+    pub fn by_index(&self, index: u32) -> Option<&Outlet> {
+        self.outlets.get(&index)
+    }
+
+    /**
+     * Look up an outlet by its physical number on a particular PDU in a
+     * daisy chain.  A standalone PDU is module 1.
+     */
+    pub fn by_module(&self, module: u32, number: u32) -> Option<&Outlet> {
+        self.iter().find(|o| o.module == module && o.number == number)
+    }
+
+    /**
+     * Look up an outlet by its physical number.  Each PDU in a daisy chain
+     * numbers its outlets from 1, so this returns None if more than one
+     * outlet has the number; use by_module() in that case.
+     */
+    pub fn by_number(&self, number: u32) -> Option<&Outlet> {
+        unique(self.iter().filter(|o| o.number == number))
+    }
+
+    /**
+     * Look up an outlet by name.  Returns None if more than one outlet has
+     * the name.
+     */
+    pub fn by_name(&self, name: &str) -> Option<&Outlet> {
+        unique(self.iter().filter(|o| o.name == name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Outlet> {
+        self.outlets.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.outlets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outlets.is_empty()
+    }
+
+    /**
+     * Any outlets that were left out because the tables disagree about
+     * them; e.g., because an outlet appears in only some of the tables, or
+     * because its name or number differs between them.
+     */
+    pub fn inconsistencies(&self) -> &[Inconsistency] {
+        &self.inconsistencies
+    }
+}
+
+fn unique<T>(mut iter: impl Iterator<Item = T>) -> Option<T> {
+    match (iter.next(), iter.next()) {
+        (Some(t), None) => Some(t),
+        _ => None,
+    }
+}
+
+/**
+ * The physical address of an outlet: the PDU within a daisy chain, numbered
+ * from 1 for the host PDU, and the number of the outlet on that PDU.
+ */
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize,
+)]
+#[serde(rename_all = "PascalCase")]
+pub struct OutletAddress {
+    pub module: u32,
+    pub number: u32,
+}
+
+impl OutletAddress {
+    pub fn new(module: u32, number: u32) -> OutletAddress {
+        OutletAddress { module, number }
+    }
+}
+
+impl std::fmt::Display for OutletAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "module {} outlet {}", self.module, self.number)
+    }
+}
+
+/**
+ * Maps the physical address of each outlet to its index in the outlet
+ * tables.  On a daisy chain of PDUs, the tables of the host PDU cover the
+ * outlets of every PDU in the chain, so the index of an outlet is not its
+ * number on the PDU it belongs to.
+ */
+#[derive(Debug, Clone)]
+pub struct OutletMap {
+    indexes: BTreeMap<OutletAddress, u32>,
+}
+
+impl OutletMap {
+    /**
+     * Fetch only the columns of the outlet control table needed to build the
+     * map, rather than the whole of any table.
+     */
+    pub async fn fetch(snmp: &Client) -> Result<OutletMap> {
+        let (size, entry, prefix) =
+            Group::OutletControl.table_oids(&snmp.tree)?;
+
+        let mut walk = snmp.walk(size).await?;
+        for col in ["Module", "Number"] {
+            let col = snmp
+                .tree
+                .oid_by_name_under(entry, &format!("{prefix}{col}"))?;
+            walk.merge(snmp.walk(col).await?);
+        }
+
+        OutletMap::from_table(entry, walk.extract_table(size, entry, prefix)?)
+    }
+
+    /**
+     * Invert the outlet control table.  Two rows with the same address would
+     * make the index of that outlet ambiguous, so are an error.
+     */
+    fn from_table(
+        entry: Oid,
+        rows: BTreeMap<u32, OutletAddress>,
+    ) -> Result<OutletMap> {
+        let mut indexes = BTreeMap::new();
+        for (idx, addr) in rows {
+            if let Some(other) = indexes.insert(addr, idx) {
+                return Err(Error::TableStructure {
+                    oid: Box::new(entry),
+                    message: format!(
+                        "{addr} appears at indexes {other} and {idx}"
+                    ),
+                });
+            }
+        }
+        Ok(OutletMap { indexes })
+    }
+
+    /**
+     * The index of an outlet in the outlet tables, for use with
+     * Pdu::send_command() and friends.
+     */
+    pub fn index(&self, address: OutletAddress) -> Result<u32> {
+        if let Some(idx) = self.indexes.get(&address) {
+            Ok(*idx)
+        } else if self.indexes.keys().any(|a| a.module == address.module) {
+            Err(Error::NoSuchOutlet {
+                module: address.module,
+                number: address.number,
+            })
+        } else {
+            Err(Error::NoSuchModule { module: address.module })
+        }
+    }
+
+    /**
+     * The physical address of the outlet at an index in the outlet tables.
+     */
+    pub fn address(&self, index: u32) -> Option<OutletAddress> {
+        self.indexes.iter().find(|(_, i)| **i == index).map(|(a, _)| *a)
+    }
+
+    /**
+     * The modules in the chain that have switched outlets.
+     */
+    pub fn modules(&self) -> BTreeSet<u32> {
+        self.indexes.keys().map(|a| a.module).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (OutletAddress, u32)> + '_ {
+        self.indexes.iter().map(|(a, i)| (*a, *i))
+    }
+}
+
+/**
+ * The groups of rPDU2 tables that may be fetched by Pdu::fetch(), each of
+ * which corresponds to one of the table accessors on Pdu.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Group {
+    Ident,
+    BankConfig,
+    BankProperties,
+    BankStatus,
+    OutletConfig,
+    OutletProperties,
+    OutletStatus,
+    OutletControl,
+    OutletMeteredStatus,
+}
+
+impl Group {
+    pub const ALL: [Group; 9] = [
+        Group::Ident,
+        Group::BankConfig,
+        Group::BankProperties,
+        Group::BankStatus,
+        Group::OutletConfig,
+        Group::OutletProperties,
+        Group::OutletStatus,
+        Group::OutletControl,
+        Group::OutletMeteredStatus,
+    ];
+
+    /**
+     * The groups used by Pdu::outlets().
+     */
+    pub const OUTLETS: [Group; 5] = [
+        Group::OutletConfig,
+        Group::OutletProperties,
+        Group::OutletStatus,
+        Group::OutletControl,
+        Group::OutletMeteredStatus,
+    ];
+
+    /**
+     * The names, beneath rPDU2, of the table size object and the table, then
+     * the name of the entry beneath the table, and the prefix of the column
+     * names.
+     */
+    fn location(
+        &self,
+    ) -> (&'static str, &'static str, &'static str, &'static str) {
+        match self {
+            Group::Ident => (
+                "rPDU2IdentTableSize",
+                "rPDU2IdentTable",
+                "rPDU2IdentEntry",
+                "rPDU2Ident",
+            ),
+            Group::BankConfig => (
+                "rPDU2BankTableSize",
+                "rPDU2Bank.rPDU2BankConfigTable",
+                "rPDU2BankConfigEntry",
+                "rPDU2BankConfig",
+            ),
+            Group::BankProperties => (
+                "rPDU2BankTableSize",
+                "rPDU2Bank.rPDU2BankPropertiesTable",
+                "rPDU2BankPropertiesEntry",
+                "rPDU2BankProperties",
+            ),
+            Group::BankStatus => (
+                "rPDU2BankTableSize",
+                "rPDU2Bank.rPDU2BankStatusTable",
+                "rPDU2BankStatusEntry",
+                "rPDU2BankStatus",
+            ),
+            Group::OutletConfig => (
+                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
+                "rPDU2Outlet.rPDU2OutletSwitched.\
+                    rPDU2OutletSwitchedConfigTable",
+                "rPDU2OutletSwitchedConfigEntry",
+                "rPDU2OutletSwitchedConfig",
+            ),
+            Group::OutletProperties => (
+                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
+                "rPDU2Outlet.rPDU2OutletSwitched.\
+                    rPDU2OutletSwitchedPropertiesTable",
+                "rPDU2OutletSwitchedPropertiesEntry",
+                "rPDU2OutletSwitchedProperties",
+            ),
+            Group::OutletStatus => (
+                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
+                "rPDU2Outlet.rPDU2OutletSwitched.\
+                    rPDU2OutletSwitchedStatusTable",
+                "rPDU2OutletSwitchedStatusEntry",
+                "rPDU2OutletSwitchedStatus",
+            ),
+            Group::OutletControl => (
+                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
+                "rPDU2Outlet.rPDU2OutletSwitched.\
+                    rPDU2OutletSwitchedControlTable",
+                "rPDU2OutletSwitchedControlEntry",
+                "rPDU2OutletSwitchedControl",
+            ),
+            Group::OutletMeteredStatus => (
+                "rPDU2Outlet.rPDU2OutletMeteredTableSize",
+                "rPDU2Outlet.rPDU2OutletMetered.\
+                    rPDU2OutletMeteredStatusTable",
+                "rPDU2OutletMeteredStatusEntry",
+                "rPDU2OutletMeteredStatus",
+            ),
+        }
+    }
+
+    /**
+     * The table size object and the table entry for the group in an OID
+     * tree, with the prefix of the column names, as WalkedValues::
+     * extract_table() and TablePlan::new() take them.
+     */
+    pub fn table_oids(
+        &self,
+        tree: &OidTree,
+    ) -> Result<(Oid, Oid, &'static str)> {
+        let top = tree
+            .oid_by_name(
+                "internet.private.enterprises.apc.products.hardware.rPDU2",
+            )
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
+        let (size, table, entry, prefix) = self.location();
+        let size = tree.oid_by_name_under(top, size)?;
+        let entry =
+            tree.oid_by_name_under(tree.oid_by_name_under(top, table)?, entry)?;
+        Ok((size, entry, prefix))
+    }
+}
+
+/**
+ * An object that ties together the rPDU2 tables into one large snapshot.
  */
 pub struct Pdu {
     top: Oid,
     walk: WalkedValues,
+    /*
+     * The groups that were fetched, or None if the whole rPDU2 subtree was.
+     */
+    groups: Option<BTreeSet<Group>>,
 }
 
 impl Pdu {
@@ -208,59 +895,375 @@ impl Pdu {
         snmp: &Client,
         outlet: u32,
         outlet_command: OutletCommand,
+    ) -> Result<()> {
+        let cmd = command_column(snmp)?;
+        set_commands(snmp, &[(child(cmd, outlet), outlet_command as i32)]).await
+    }
+
+    /**
+     * Send commands to several outlets, packing as many as the options allow
+     * into each SET request.  The PDU applies each request in full or not at
+     * all, so if a request fails, its outlets and any in later requests are
+     * left alone.  Outlets in earlier requests, and in a failed request that
+     * the PDU may have applied anyway (e.g., one that timed out), are then
+     * returned to the state they were in beforehand, unless the options say
+     * otherwise.  If an outlet appears more than once, the last command for
+     * it is used.
+     *
+     * An error is returned only if nothing was sent; otherwise the report
+     * describes what happened to each outlet.  A failure to write an audit
+     * record for a request the PDU accepted is kept in the report, and
+     * neither stops the batch nor causes a rollback.
+     */
+    pub async fn send_commands(
+        snmp: &Client,
+        commands: impl IntoIterator<Item = (u32, OutletCommand)>,
+        options: &BatchOptions,
+    ) -> Result<BatchReport> {
+        let cmd = command_column(snmp)?;
+        let commands = commands.into_iter().collect::<BTreeMap<_, _>>();
+        let outlets = commands.keys().copied().collect::<Vec<_>>();
+
+        let prior = if options.rollback {
+            Self::poll_outlets(snmp, &outlets).await?
+        } else {
+            Default::default()
+        };
+
+        let mut report = BatchReport {
+            outcomes: outlets
+                .iter()
+                .map(|o| (*o, CommandOutcome::NotSent))
+                .collect(),
+            error: None,
+            rollback_error: None,
+            audit_error: None,
+        };
+
+        let commands = commands.into_iter().collect::<Vec<_>>();
+        for ch in commands.chunks(options.max_varbinds.max(1)) {
+            let values = ch
+                .iter()
+                .map(|(o, c)| (child(cmd, *o), *c as i32))
+                .collect::<Vec<_>>();
+            let outcome = match set_commands(snmp, &values).await {
+                Ok(()) => CommandOutcome::Sent,
+                /*
+                 * Only the audit record failed; the PDU accepted the
+                 * commands, so carry on.
+                 */
+                Err(e @ Error::Audit(_)) => {
+                    report.audit_error.get_or_insert(e);
+                    CommandOutcome::Sent
+                }
+                Err(e) => {
+                    let outcome = match e {
+                        Error::Timeout { .. } | Error::Protocol { .. } => {
+                            CommandOutcome::Unknown
+                        }
+                        _ => CommandOutcome::Failed,
+                    };
+                    report.error = Some(e);
+                    outcome
+                }
+            };
+            for (o, _) in ch {
+                report.outcomes.insert(*o, outcome);
+            }
+            if report.error.is_some() {
+                break;
+            }
+        }
+
+        if report.error.is_none() || !options.rollback {
+            return Ok(report);
+        }
+
+        /*
+         * Put each outlet that we changed, or may have changed, back the way
+         * it was.  If we do not know how it was, we cannot.
+         */
+        let mut undo = Vec::new();
+        for (o, outcome) in report.outcomes.iter_mut() {
+            if !matches!(
+                *outcome,
+                CommandOutcome::Sent | CommandOutcome::Unknown
+            ) {
+                continue;
+            }
+            match prior.get(o).and_then(|p| p.state) {
+                Some(State::On) => undo.push((*o, OutletCommand::ImmediateOn)),
+                Some(State::Off) => {
+                    undo.push((*o, OutletCommand::ImmediateOff))
+                }
+                None => *outcome = CommandOutcome::RollbackFailed,
+            }
+        }
+        for ch in undo.chunks(options.max_varbinds.max(1)) {
+            let values = ch
+                .iter()
+                .map(|(o, c)| (child(cmd, *o), *c as i32))
+                .collect::<Vec<_>>();
+            let outcome = match set_commands(snmp, &values).await {
+                Ok(()) => CommandOutcome::RolledBack,
+                Err(e @ Error::Audit(_)) => {
+                    report.audit_error.get_or_insert(e);
+                    CommandOutcome::RolledBack
+                }
+                Err(e) => {
+                    report.rollback_error.get_or_insert(e);
+                    CommandOutcome::RollbackFailed
+                }
+            };
+            for (o, _) in ch {
+                report.outcomes.insert(*o, outcome);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /**
+     * Send a command that applies to every outlet on one PDU in a chain, using
+     * the device control table rather than a command for each outlet.
+     */
+    pub async fn send_device_command(
+        snmp: &Client,
+        module: u32,
+        device_command: DeviceCommand,
     ) -> Result<()> {
         let top = snmp
             .tree
             .oid_by_name(
                 "internet.private.enterprises.apc.products.hardware.rPDU2",
             )
-            .map_err(|e| anyhow!("{e} (is apc in the OID tree?)"))?;
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
         let ctl = snmp.tree.oid_by_name_under(
             top,
-            "rPDU2Outlet.\
-                     rPDU2OutletSwitched.\
-                     rPDU2OutletSwitchedControlTable.\
-                     rPDU2OutletSwitchedControlEntry",
+            "rPDU2Device.rPDU2DeviceControlTable.rPDU2DeviceControlEntry",
         )?;
-        let cmd: Oid = snmp
-            .tree
-            .oid_by_name_under(ctl, "rPDU2OutletSwitchedControlCommand")?
-            .child(outlet)
-            .unwrap()
-            .into();
-
-        let res = snmp
-            .set(cmd, Value(csnmp::ObjectValue::Integer(outlet_command as i32)))
-            .await?;
 
         /*
-         * The resultant value should be the same as the one we sent.
+         * Find the row of the device control table for this module.
          */
-        if let csnmp::ObjectValue::Integer(i) = res.0 {
-            if i == outlet_command as i32 {
-                return Ok(());
-            }
+        let modules = snmp
+            .walk(snmp.tree.oid_by_name_under(ctl, "rPDU2DeviceControlModule")?)
+            .await?;
+        let Some(row) = modules.iter().find_map(|(oid, val)| {
+            (val.as_i32() == i32::try_from(module).ok())
+                .then(|| oid.as_slice().last().copied())
+                .flatten()
+        }) else {
+            return Err(Error::NoSuchModule { module });
+        };
+
+        let cmd =
+            snmp.tree.oid_by_name_under(ctl, "rPDU2DeviceControlCommand")?;
+        set_commands(snmp, &[(child(cmd, row), device_command as i32)]).await
+    }
+
+    /**
+     * Change the configuration of an outlet.  Every value is checked before
+     * anything is sent, and all of the changes are made in one request, so
+     * that either all or none of them take effect.
+     */
+    pub async fn configure_outlet(
+        snmp: &Client,
+        outlet: u32,
+        change: &OutletConfigChange,
+    ) -> Result<()> {
+        change.validate()?;
+
+        let col = |name: &str| -> Result<Oid> {
+            Ok(child(column(&snmp.tree, Group::OutletConfig, name)?, outlet))
+        };
+        let mut values = Vec::new();
+        if let Some(name) = &change.name {
+            values.push((col("Name")?, string(name)));
+        }
+        if let Some(t) = change.power_on_time {
+            values.push((col("PowerOnTime")?, int(t)));
+        }
+        if let Some(t) = change.power_off_time {
+            values.push((col("PowerOffTime")?, int(t)));
+        }
+        if let Some(t) = change.reboot_duration {
+            values.push((col("RebootDuration")?, int(t as i32)));
+        }
+        if let Some(link) = &change.external_link {
+            values.push((col("ExternalLink")?, string(link)));
+        }
+
+        if !values.is_empty() {
+            snmp.set_multiple(&values).await?;
         }
-        bail!("unusual response from PDU: {res:?}");
+        Ok(())
     }
 
+    /**
+     * Change the configuration of a bank, in one request.  If any thresholds
+     * are to change, the current thresholds and breaker rating are fetched
+     * first, so that the new thresholds can be checked to be in order: low
+     * load, near overload, then overload, which may not exceed the breaker
+     * rating.
+     */
+    pub async fn configure_bank(
+        snmp: &Client,
+        bank: u32,
+        change: &BankConfigChange,
+    ) -> Result<()> {
+        change.validate()?;
+
+        let col = |name: &str| -> Result<Oid> {
+            Ok(child(column(&snmp.tree, Group::BankConfig, name)?, bank))
+        };
+        let low = col("LowLoadCurrentThreshold")?;
+        let near = col("NearOverloadCurrentThreshold")?;
+        let over = col("OverloadCurrentThreshold")?;
+
+        if change.has_thresholds() {
+            let rating = child(
+                column(&snmp.tree, Group::BankProperties, "BreakerRating")?,
+                bank,
+            );
+            let current = snmp.get_multiple(&[low, near, over, rating]).await?;
+            let current = |oid: Oid| -> Result<i64> {
+                current[&oid].as_i32().map(i64::from).ok_or_else(|| {
+                    Error::Decode {
+                        oid: Box::new(oid),
+                        message: format!("not an integer: {:?}", current[&oid]),
+                    }
+                })
+            };
+
+            change.check_thresholds(
+                [current(low)?, current(near)?, current(over)?],
+                current(rating)?,
+            )?;
+        }
+
+        let mut values = Vec::new();
+        if let Some(r) = change.overload_restriction {
+            values.push((col("OverloadRestriction")?, int(r as i32)));
+        }
+        if let Some(t) = change.low_load_current_threshold {
+            values.push((low, int(t as i32)));
+        }
+        if let Some(t) = change.near_overload_current_threshold {
+            values.push((near, int(t as i32)));
+        }
+        if let Some(t) = change.overload_current_threshold {
+            values.push((over, int(t as i32)));
+        }
+        if change.reset_peak_current {
+            values.push((
+                col("BankPeakCurrentReset")?,
+                int(PeakCurrentReset::Reset as i32),
+            ));
+        }
+
+        if !values.is_empty() {
+            snmp.set_multiple(&values).await?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Poll the state of a single outlet.  Unlike poll_outlets(), an outlet or
+     * column that does not exist on the device is an error.
+     */
     pub async fn poll_outlet(
         snmp: &Client,
         outlet: u32,
     ) -> Result<(State, OutletCommand, CommandPending)> {
-        Ok(Self::poll_outlets(snmp, &[outlet]).await?.remove(&outlet).unwrap())
+        let [(_, cols)] =
+            <[_; 1]>::try_from(Self::poll(snmp, &[outlet]).await?).unwrap();
+        let [state, cmd, cmd_pending] =
+            cols.map(|(oid, vb)| vb.into_result(oid).map(|val| (oid, val)));
+        let (state, cmd, cmd_pending) = (state?, cmd?, cmd_pending?);
+
+        Ok((
+            decode(state.0, &state.1)?,
+            decode(cmd.0, &cmd.1)?,
+            decode(cmd_pending.0, &cmd_pending.1)?,
+        ))
     }
 
+    /**
+     * Poll the state of several outlets at once.  Any outlet or column that is
+     * not present on the device is reported as None, rather than failing the
+     * entire poll.
+     */
     pub async fn poll_outlets(
         snmp: &Client,
         outlets: &[u32],
-    ) -> Result<BTreeMap<u32, (State, OutletCommand, CommandPending)>> {
+    ) -> Result<BTreeMap<u32, OutletPoll>> {
+        Self::poll(snmp, outlets)
+            .await?
+            .into_iter()
+            .map(|(num, [state, cmd, cmd_pending])| {
+                Ok((
+                    num,
+                    OutletPoll {
+                        state: decode_present(&state)?,
+                        command: decode_present(&cmd)?,
+                        command_pending: decode_present(&cmd_pending)?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /**
+     * Send a command to an outlet identified by its physical address, which
+     * is mapped to an index in the outlet tables first.  A command for a
+     * module or outlet that does not exist is rejected without being sent.
+     */
+    pub async fn send_command_at(
+        snmp: &Client,
+        address: OutletAddress,
+        outlet_command: OutletCommand,
+    ) -> Result<()> {
+        let idx = OutletMap::fetch(snmp).await?.index(address)?;
+        Self::send_command(snmp, idx, outlet_command).await
+    }
+
+    /**
+     * Poll the state of several outlets, identified by their physical
+     * addresses, at once.  Unlike poll_outlets(), every outlet must exist.
+     */
+    pub async fn poll_outlets_at(
+        snmp: &Client,
+        addresses: &[OutletAddress],
+    ) -> Result<BTreeMap<OutletAddress, OutletPoll>> {
+        let map = OutletMap::fetch(snmp).await?;
+        let indexes = addresses
+            .iter()
+            .map(|a| map.index(*a))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut polled = Self::poll_outlets(snmp, &indexes).await?;
+        Ok(addresses
+            .iter()
+            .zip(indexes)
+            .map(|(a, idx)| (*a, polled.remove(&idx).unwrap()))
+            .collect())
+    }
+
+    /**
+     * Fetch the state, control command and command pending columns for each
+     * outlet, in that order.
+     */
+    async fn poll(
+        snmp: &Client,
+        outlets: &[u32],
+    ) -> Result<Vec<(u32, [(Oid, Varbind); 3])>> {
         let top = snmp
             .tree
             .oid_by_name(
                 "internet.private.enterprises.apc.products.hardware.rPDU2",
             )
-            .map_err(|e| anyhow!("{e} (is apc in the OID tree?)"))?;
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
 
         let status = snmp.tree.oid_by_name_under(
             top,
@@ -277,86 +1280,43 @@ impl Pdu {
                      rPDU2OutletSwitchedControlEntry",
         )?;
 
+        let state = snmp
+            .tree
+            .oid_by_name_under(status, "rPDU2OutletSwitchedStatusState")?;
+        let cmd_pending = snmp.tree.oid_by_name_under(
+            status,
+            "rPDU2OutletSwitchedStatusCommandPending",
+        )?;
+        let cmd = snmp
+            .tree
+            .oid_by_name_under(ctl, "rPDU2OutletSwitchedControlCommand")?;
+
         let outlets = outlets
             .iter()
             .map(|num| {
-                let state: Oid = snmp
-                    .tree
-                    .oid_by_name_under(
-                        status,
-                        "rPDU2OutletSwitchedStatusState",
-                    )?
-                    .child(*num)
-                    .unwrap()
-                    .into();
-                let cmd_pending: Oid = snmp
-                    .tree
-                    .oid_by_name_under(
-                        status,
-                        "rPDU2OutletSwitchedStatusCommandPending",
-                    )?
-                    .child(*num)
-                    .unwrap()
-                    .into();
-                let cmd: Oid = snmp
-                    .tree
-                    .oid_by_name_under(
-                        ctl,
-                        "rPDU2OutletSwitchedControlCommand",
-                    )?
-                    .child(*num)
-                    .unwrap()
-                    .into();
-                Ok((*num, state, cmd_pending, cmd))
+                let col = |oid: Oid| -> Oid { oid.child(*num).unwrap().into() };
+                (*num, [col(state), col(cmd), col(cmd_pending)])
             })
-            .collect::<Result<Vec<_>>>()?;
-
-        let oids = outlets
-            .iter()
-            .map(|(_, a, b, c)| [a.0, b.0, c.0])
-            .flatten()
             .collect::<Vec<_>>();
 
-        let mut res: BTreeMap<Oid, Value> = Default::default();
+        let oids = outlets.iter().flat_map(|(_, o)| *o).collect::<Vec<_>>();
+
+        let mut res: BTreeMap<Oid, Varbind> = Default::default();
 
         /*
          * If we ask for too many values at once, it appears to overwhelm the
          * management interface.
          */
         for ch in oids.chunks(32) {
-            snmp.snmp
-                .get_multiple(ch.iter().cloned())
-                .await?
-                .into_iter()
-                .for_each(|(oid, val)| {
-                    res.insert(Oid(oid), Value(val));
-                });
+            res.extend(snmp.get_varbinds(ch).await?);
         }
 
-        outlets
+        Ok(outlets
             .into_iter()
-            .map(|(num, state, cmd, cmd_pending)| {
-                let Some(state) = res.get(&state) else {
-                    bail!("outlet {num}: no state");
-                };
-                let Some(cmd) = res.get(&cmd) else {
-                    bail!("outlet {num}: no cmd");
-                };
-                let Some(cmd_pending) = res.get(&cmd_pending) else {
-                    bail!("outlet {num}: no cmd_pending");
-                };
-                Ok((
-                    num,
-                    (
-                        State::deserialize(state.into_deserializer())?,
-                        OutletCommand::deserialize(cmd.into_deserializer())?,
-                        CommandPending::deserialize(
-                            cmd_pending.into_deserializer(),
-                        )?,
-                    ),
-                ))
+            .map(|(num, oids)| {
+                (num, oids.map(|oid| (oid, res.get(&oid).unwrap().clone())))
             })
-            .collect::<Result<_>>()
+            .collect())
     }
 
     pub async fn from_client(snmp: &Client) -> Result<Pdu> {
@@ -365,131 +1325,432 @@ impl Pdu {
             .oid_by_name(
                 "internet.private.enterprises.apc.products.hardware.rPDU2",
             )
-            .map_err(|e| anyhow!("{e} (is apc in the OID tree?)"))?;
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
 
         let walk = snmp.walk(top).await?;
 
-        Ok(Pdu { top, walk })
+        Ok(Pdu { top, walk, groups: None })
+    }
+
+    /**
+     * Fetch only the tables in the provided groups, rather than walking the
+     * entire rPDU2 subtree as from_client() does.  The accessors for any
+     * other groups will return Error::NotFetched.
+     */
+    pub async fn fetch(snmp: &Client, groups: &[Group]) -> Result<Pdu> {
+        let top = snmp
+            .tree
+            .oid_by_name(
+                "internet.private.enterprises.apc.products.hardware.rPDU2",
+            )
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
+
+        let groups = groups.iter().copied().collect::<BTreeSet<_>>();
+
+        /*
+         * Several groups share a table size object, so only walk each
+         * subtree once.
+         */
+        let subtrees = groups
+            .iter()
+            .flat_map(|g| {
+                let (size, table, _, _) = g.location();
+                [size, table]
+            })
+            .map(|n| snmp.tree.oid_by_name_under(top, n))
+            .collect::<Result<BTreeSet<_>>>()?;
+
+        let mut walk = WalkedValues::from_values(Arc::clone(&snmp.tree), []);
+        for oid in subtrees {
+            walk.merge(snmp.walk(oid).await?);
+        }
+
+        Ok(Pdu { top, walk, groups: Some(groups) })
+    }
+
+    /**
+     * Use values obtained by some other means, such as from a capture file,
+     * rather than walking a live device.  The values must include the rPDU2
+     * subtree.
+     */
+    pub fn from_walk(walk: WalkedValues) -> Result<Pdu> {
+        let top = walk
+            .tree
+            .oid_by_name(
+                "internet.private.enterprises.apc.products.hardware.rPDU2",
+            )
+            .map_err(|e| e.hint("is apc in the OID tree?"))?;
+
+        Ok(Pdu { top, walk, groups: None })
     }
 
     pub fn ident(&self) -> Result<BTreeMap<u32, Ident>> {
-        self.walk.extract_table(
-            self.walk
-                .tree
-                .oid_by_name_under(self.top, "rPDU2IdentTableSize")?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2IdentTable.rPDU2IdentEntry",
-            )?,
-            "rPDU2Ident",
-        )
+        self.table(Group::Ident)
     }
 
     pub fn bank_config(&self) -> Result<BTreeMap<u32, BankConfiguration>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(self.top, "rPDU2BankTableSize")?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Bank.rPDU2BankConfigTable.rPDU2BankConfigEntry",
-            )?,
-            "rPDU2BankConfig",
-        )
+        self.table(Group::BankConfig)
     }
 
     pub fn bank_props(&self) -> Result<BTreeMap<u32, BankProperties>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(self.top, "rPDU2BankTableSize")?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Bank.rPDU2BankPropertiesTable.rPDU2BankPropertiesEntry",
-            )?,
-            "rPDU2BankProperties",
-        )
+        self.table(Group::BankProperties)
     }
 
     pub fn bank_status(&self) -> Result<BTreeMap<u32, BankStatus>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(self.top, "rPDU2BankTableSize")?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Bank.rPDU2BankStatusTable.rPDU2BankStatusEntry",
-            )?,
-            "rPDU2BankStatus",
-        )
+        self.table(Group::BankStatus)
     }
 
     pub fn outlet_config(&self) -> Result<BTreeMap<u32, OutletConfig>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
-            )?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.\
-                    rPDU2OutletSwitched.\
-                    rPDU2OutletSwitchedConfigTable.\
-                    rPDU2OutletSwitchedConfigEntry",
-            )?,
-            "rPDU2OutletSwitchedConfig",
-        )
+        self.table(Group::OutletConfig)
     }
 
     pub fn outlet_props(&self) -> Result<BTreeMap<u32, OutletProperties>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
-            )?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.\
-                     rPDU2OutletSwitched.\
-                     rPDU2OutletSwitchedPropertiesTable.\
-                     rPDU2OutletSwitchedPropertiesEntry",
-            )?,
-            "rPDU2OutletSwitchedProperties",
-        )
+        self.table(Group::OutletProperties)
     }
 
     pub fn outlet_status(&self) -> Result<BTreeMap<u32, OutletStatus>> {
-        self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
-            )?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.\
-                     rPDU2OutletSwitched.\
-                     rPDU2OutletSwitchedStatusTable.\
-                     rPDU2OutletSwitchedStatusEntry",
-            )?,
-            "rPDU2OutletSwitchedStatus",
-        )
+        self.table(Group::OutletStatus)
     }
 
     pub fn outlet_control(&self) -> Result<BTreeMap<u32, OutletControl>> {
+        self.table(Group::OutletControl)
+    }
+
+    /**
+     * Only PDUs that meter each outlet have this table.
+     */
+    pub fn outlet_metered_status(
+        &self,
+    ) -> Result<BTreeMap<u32, OutletMeteredStatus>> {
+        self.table(Group::OutletMeteredStatus)
+    }
+
+    /**
+     * The map from physical outlet addresses to table indexes, according to
+     * the outlet control table in this snapshot.
+     */
+    pub fn outlet_map(&self) -> Result<OutletMap> {
+        let (_, entry, _) = Group::OutletControl.table_oids(&self.walk.tree)?;
+        OutletMap::from_table(entry, self.table(Group::OutletControl)?)
+    }
+
+    /**
+     * The modules in the chain of PDUs, according to the ident table.  A
+     * standalone PDU has just module 1.
+     */
+    pub fn modules(&self) -> Result<BTreeSet<u32>> {
+        Ok(self.ident()?.into_values().map(|i| i.module).collect())
+    }
+
+    /**
+     * A view of the tables restricted to one PDU in the chain.
+     */
+    pub fn module(&self, module: u32) -> Result<Module<'_>> {
+        if !self.modules()?.contains(&module) {
+            return Err(Error::NoSuchModule { module });
+        }
+        Ok(Module { pdu: self, module })
+    }
+
+    /**
+     * Join the switched outlet tables, and the metered outlet status table
+     * if the PDU has one and it was fetched, into one view of each outlet.
+     */
+    pub fn outlets(&self) -> Result<Outlets> {
+        let mut config = self.outlet_config()?;
+        let mut props = self.outlet_props()?;
+        let mut status = self.outlet_status()?;
+        let mut control = self.outlet_control()?;
+
+        /*
+         * The metered table is indexed separately, so match its rows to
+         * outlets by module and number.
+         */
+        let mut metered = match self.outlet_metered_status() {
+            Ok(metered) => Some(
+                metered
+                    .into_values()
+                    .map(|m| ((m.module, m.number), m))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+            Err(Error::NotFetched { .. } | Error::MissingTableSize { .. }) => {
+                None
+            }
+            Err(e) => return Err(e),
+        };
+
+        let indexes = config
+            .keys()
+            .chain(props.keys())
+            .chain(status.keys())
+            .chain(control.keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+
+        let mut outlets = BTreeMap::new();
+        let mut inconsistencies = Vec::new();
+        for index in indexes {
+            let fail = |message: String| Inconsistency { index, message };
+
+            let (Some(config), Some(props), Some(status), Some(control)) = (
+                config.remove(&index),
+                props.remove(&index),
+                status.remove(&index),
+                control.remove(&index),
+            ) else {
+                inconsistencies
+                    .push(fail("missing from some outlet tables".into()));
+                continue;
+            };
+
+            let m = metered
+                .as_mut()
+                .map(|m| m.remove(&(config.module, config.number)));
+
+            let ids = [
+                (props.module, props.number, &props.name),
+                (status.module, status.number, &status.name),
+                (control.module, control.number, &control.name),
+            ];
+            if let Some((module, number, name)) = ids
+                .into_iter()
+                .find(|id| *id != (config.module, config.number, &config.name))
+            {
+                inconsistencies.push(fail(format!(
+                    "module {}, outlet {} ({:?}) is also module {module}, \
+                    outlet {number} ({name:?})",
+                    config.module, config.number, config.name,
+                )));
+                continue;
+            }
+
+            let metered = match m {
+                Some(None) => {
+                    inconsistencies
+                        .push(fail("missing from metered table".into()));
+                    continue;
+                }
+                Some(m) => m,
+                None => None,
+            };
+
+            outlets.insert(
+                index,
+                Outlet {
+                    index,
+                    module: config.module,
+                    number: config.number,
+                    name: config.name.clone(),
+                    bank: props.bank,
+                    phase_layout: props.phase_layout,
+                    config,
+                    props,
+                    status,
+                    control,
+                    metered,
+                },
+            );
+        }
+
+        inconsistencies.extend(
+            metered.into_iter().flat_map(BTreeMap::into_values).map(|m| {
+                Inconsistency {
+                    index: m.index,
+                    message: format!(
+                        "metered module {}, outlet {} is not in the switched \
+                        outlet tables",
+                        m.module, m.number,
+                    ),
+                }
+            }),
+        );
+
+        Ok(Outlets { outlets, inconsistencies })
+    }
+
+    fn table<T>(&self, group: Group) -> Result<BTreeMap<u32, T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let tree = &self.walk.tree;
+        let (size, table, entry, prefix) = group.location();
+        let table = tree.oid_by_name_under(self.top, table)?;
+
+        if self.groups.as_ref().is_some_and(|g| !g.contains(&group)) {
+            return Err(Error::NotFetched { oid: Box::new(table) });
+        }
+
         self.walk.extract_table(
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.rPDU2OutletSwitchedTableSize",
-            )?,
-            self.walk.tree.oid_by_name_under(
-                self.top,
-                "rPDU2Outlet.\
-                    rPDU2OutletSwitched.\
-                    rPDU2OutletSwitchedControlTable.\
-                    rPDU2OutletSwitchedControlEntry",
-            )?,
-            "rPDU2OutletSwitchedControl",
+            tree.oid_by_name_under(self.top, size)?,
+            tree.oid_by_name_under(table, entry)?,
+            prefix,
         )
     }
 }
 
+/**
+ * The rows of the rPDU2 tables that belong to one PDU in a daisy chain, as
+ * produced by Pdu::module().  Bank tables are keyed by the bank number on
+ * this PDU, rather than by table index.
+ */
+pub struct Module<'a> {
+    pdu: &'a Pdu,
+    module: u32,
+}
+
+impl Module<'_> {
+    pub fn number(&self) -> u32 {
+        self.module
+    }
+
+    pub fn ident(&self) -> Result<Ident> {
+        self.pdu
+            .ident()?
+            .into_values()
+            .find(|i| i.module == self.module)
+            .ok_or(Error::NoSuchModule { module: self.module })
+    }
+
+    pub fn bank_config(&self) -> Result<BTreeMap<u32, BankConfiguration>> {
+        Ok(self.banks(self.pdu.bank_config()?, |b| (b.module, b.number)))
+    }
+
+    pub fn bank_props(&self) -> Result<BTreeMap<u32, BankProperties>> {
+        Ok(self.banks(self.pdu.bank_props()?, |b| (b.module, b.number)))
+    }
+
+    pub fn bank_status(&self) -> Result<BTreeMap<u32, BankStatus>> {
+        Ok(self.banks(self.pdu.bank_status()?, |b| (b.module, b.number)))
+    }
+
+    /**
+     * The outlets on this PDU.  Any inconsistencies are reported for the
+     * whole chain, as the module of an inconsistent outlet is not certain.
+     */
+    pub fn outlets(&self) -> Result<Outlets> {
+        let mut outlets = self.pdu.outlets()?;
+        outlets.outlets.retain(|_, o| o.module == self.module);
+        Ok(outlets)
+    }
+
+    fn banks<T>(
+        &self,
+        rows: BTreeMap<u32, T>,
+        id: impl Fn(&T) -> (u32, u32),
+    ) -> BTreeMap<u32, T> {
+        rows.into_values()
+            .filter_map(|row| {
+                let (module, number) = id(&row);
+                (module == self.module).then_some((number, row))
+            })
+            .collect()
+    }
+}
+
+/**
+ * The rPDU2OutletSwitchedControlCommand column.
+ */
+fn command_column(snmp: &Client) -> Result<Oid> {
+    let top = snmp
+        .tree
+        .oid_by_name("internet.private.enterprises.apc.products.hardware.rPDU2")
+        .map_err(|e| e.hint("is apc in the OID tree?"))?;
+    let ctl = snmp.tree.oid_by_name_under(
+        top,
+        "rPDU2Outlet.\
+                 rPDU2OutletSwitched.\
+                 rPDU2OutletSwitchedControlTable.\
+                 rPDU2OutletSwitchedControlEntry",
+    )?;
+    snmp.tree.oid_by_name_under(ctl, "rPDU2OutletSwitchedControlCommand")
+}
+
+/**
+ * A column of the table for a group, named without the prefix that all of
+ * the columns of the table share; e.g., "PowerOnTime".
+ */
+fn column(tree: &OidTree, group: Group, name: &str) -> Result<Oid> {
+    let (_, entry, prefix) = group.table_oids(tree)?;
+    tree.oid_by_name_under(entry, &format!("{prefix}{name}"))
+}
+
+fn int(i: i32) -> Value {
+    Value(csnmp::ObjectValue::Integer(i))
+}
+
+fn string(s: &str) -> Value {
+    Value(csnmp::ObjectValue::String(s.as_bytes().to_vec()))
+}
+
+fn child(column: Oid, index: u32) -> Oid {
+    column.child(index).unwrap().into()
+}
+
+/**
+ * Write integer commands in a single request, and check that the PDU echoes
+ * each of them back.
+ */
+async fn set_commands(snmp: &Client, commands: &[(Oid, i32)]) -> Result<()> {
+    let values = commands
+        .iter()
+        .map(|(oid, c)| (*oid, Value(csnmp::ObjectValue::Integer(*c))))
+        .collect::<Vec<_>>();
+    let res = snmp.set_multiple(&values).await?;
+
+    /*
+     * The resultant value should be the same as the one we sent.
+     */
+    for (oid, c) in commands {
+        match res.get(oid).map(|v| &v.0) {
+            Some(csnmp::ObjectValue::Integer(i)) if i == c => (),
+            other => {
+                return Err(Error::Protocol {
+                    target: snmp.transport.target(),
+                    oid: Some(Box::new(*oid)),
+                    message: format!("unusual response from PDU: {other:?}"),
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Decode a single value obtained with Client::get_varbinds().
+ */
+fn decode<T>(oid: Oid, val: &Value) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    T::deserialize(val.into_deserializer()).map_err(|e| Error::Decode {
+        oid: Box::new(oid),
+        message: e.to_string(),
+    })
+}
+
+/**
+ * Decode a value if the agent provided one, rather than an exception.
+ */
+fn decode_present<T>((oid, vb): &(Oid, Varbind)) -> Result<Option<T>>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    vb.value().map(|val| decode(*oid, val)).transpose()
+}
+
+/**
+ * Get a shared tree containing the base definitions and this MIB module,
+ * constructed on first use.
+ */
+pub fn tree() -> Arc<OidTree> {
+    static TREE: OnceLock<Arc<OidTree>> = OnceLock::new();
+    shared_tree(&TREE, &[populate])
+}
+
 pub fn populate(tree: &mut OidTree) -> Result<()> {
     add_from_instructions_under(
         tree,
+        "PowerNet-MIB",
         "enterprises",
         tree.oid_by_name("internet.private.enterprises")?.as_slice().to_vec(),
         &[
@@ -14290,8 +15551,54 @@ pub fn populate(tree: &mut OidTree) -> Result<()> {
             ("uioOutputRelayConfigDelay", "uioOutputRelayConfigEntry", 7),
             ("uioOutputRelayConfigHold", "uioOutputRelayConfigEntry", 8),
         ],
-    )
-    .map_err(|e| anyhow!("apc::populate: {e}"))?;
+    )?;
+
+    /*
+     * Each of the rPDU2 tables is indexed by its own integer index column:
+     */
+    for (entry, index) in [
+        ("rPDU2IdentEntry", "rPDU2IdentIndex"),
+        ("rPDU2DeviceConfigEntry", "rPDU2DeviceConfigIndex"),
+        ("rPDU2DevicePropertiesEntry", "rPDU2DevicePropertiesIndex"),
+        ("rPDU2DeviceStatusEntry", "rPDU2DeviceStatusIndex"),
+        ("rPDU2DeviceControlEntry", "rPDU2DeviceControlIndex"),
+        ("rPDU2PhaseConfigEntry", "rPDU2PhaseConfigIndex"),
+        ("rPDU2PhasePropertiesEntry", "rPDU2PhasePropertiesIndex"),
+        ("rPDU2PhaseStatusEntry", "rPDU2PhaseStatusIndex"),
+        ("rPDU2BankConfigEntry", "rPDU2BankConfigIndex"),
+        ("rPDU2BankPropertiesEntry", "rPDU2BankPropertiesIndex"),
+        ("rPDU2BankStatusEntry", "rPDU2BankStatusIndex"),
+        ("rPDU2OutletSwitchedConfigEntry", "rPDU2OutletSwitchedConfigIndex"),
+        (
+            "rPDU2OutletSwitchedPropertiesEntry",
+            "rPDU2OutletSwitchedPropertiesIndex",
+        ),
+        ("rPDU2OutletSwitchedStatusEntry", "rPDU2OutletSwitchedStatusIndex"),
+        ("rPDU2OutletSwitchedControlEntry", "rPDU2OutletSwitchedControlIndex"),
+        ("rPDU2OutletMeteredConfigEntry", "rPDU2OutletMeteredConfigIndex"),
+        (
+            "rPDU2OutletMeteredPropertiesEntry",
+            "rPDU2OutletMeteredPropertiesIndex",
+        ),
+        ("rPDU2OutletMeteredStatusEntry", "rPDU2OutletMeteredStatusIndex"),
+        (
+            "rPDU2SensorTempHumidityConfigEntry",
+            "rPDU2SensorTempHumidityConfigIndex",
+        ),
+        (
+            "rPDU2SensorTempHumidityStatusEntry",
+            "rPDU2SensorTempHumidityStatusIndex",
+        ),
+        ("rPDU2SensorDiscreteConfigEntry", "rPDU2SensorDiscreteConfigIndex"),
+        ("rPDU2SensorDiscreteStatusEntry", "rPDU2SensorDiscreteStatusIndex"),
+        ("rPDU2PhaseToPhaseStatusEntry", "rPDU2PhaseToPhaseStatusIndex"),
+        ("rPDU2AdvBankConfigEntry", "rPDU2AdvBankConfigIndex"),
+    ] {
+        tree.set_table_index(
+            tree.oid_by_name(&format!("PowerNet-MIB::{entry}"))?.as_slice(),
+            &[(index, IndexSyntax::Integer)],
+        )?;
+    }
 
     Ok(())
 }
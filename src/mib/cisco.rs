@@ -4,16 +4,47 @@
 
 use super::sublude::*;
 
+/**
+ * Get a shared tree containing the base definitions and this MIB module,
+ * constructed on first use.
+ */
+pub fn tree() -> Arc<OidTree> {
+    static TREE: OnceLock<Arc<OidTree>> = OnceLock::new();
+    shared_tree(&TREE, &[populate])
+}
+
 pub fn populate(tree: &mut OidTree) -> Result<()> {
     add_from_instructions_under(
         tree,
+        "CISCO-SMI",
         "enterprises",
         tree.oid_by_name("internet.private.enterprises")?.as_slice().to_vec(),
+        &[("cisco", "enterprises", 9), ("otherEnterprises", "cisco", 6)],
+    )?;
+
+    add_from_instructions_under(
+        tree,
+        "CISCOSB-MIB",
+        "otherEnterprises",
+        tree.oid_by_name(
+            "internet.private.enterprises.cisco.otherEnterprises",
+        )?
+        .as_slice()
+        .to_vec(),
+        &[("ciscoSB", "otherEnterprises", 1), ("switch001", "ciscoSB", 101)],
+    )?;
+
+    add_from_instructions_under(
+        tree,
+        "CISCOSB-rlInterfaces",
+        "switch001",
+        tree.oid_by_name(
+            "internet.private.enterprises.cisco.otherEnterprises.ciscoSB.\
+                switch001",
+        )?
+        .as_slice()
+        .to_vec(),
         &[
-            ("cisco", "enterprises", 9),
-            ("otherEnterprises", "cisco", 6),
-            ("ciscoSB", "otherEnterprises", 1),
-            ("switch001", "ciscoSB", 101),
             ("swInterfaces", "switch001", 43),
             ("swIfTable", "swInterfaces", 1),
             ("swIfEntry", "swIfTable", 1),
@@ -76,8 +107,12 @@ pub fn populate(tree: &mut OidTree) -> Result<()> {
             ("swIfPortFecMode", "swIfEntry", 49),
             ("swIfPortNumOfLanes", "swIfEntry", 50),
         ],
-    )
-    .map_err(|e| anyhow!("cisco::populate: {e}"))?;
+    )?;
+
+    tree.set_table_index(
+        tree.oid_by_name("CISCOSB-rlInterfaces::swIfEntry")?.as_slice(),
+        &[("swIfIndex", IndexSyntax::Integer)],
+    )?;
 
     Ok(())
 }
@@ -3,6 +3,7 @@
  */
 
 use super::sublude::*;
+use crate::tc::{PhysAddress, TimeStamp, TimeTicks};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -11,10 +12,7 @@ pub struct System {
     descr: String,
     object_id: Oid,
     #[serde(rename = "UpTime")]
-    /**
-     * The uptime of the system management component in hundredths of a second.
-     */
-    uptime: u32,
+    uptime: TimeTicks,
     contact: String,
     name: String,
     location: String,
@@ -38,9 +36,11 @@ impl System {
         &self.location
     }
 
+    /**
+     * The time since the system management component last started.
+     */
     pub fn uptime(&self) -> Duration {
-        let uptime: u64 = self.uptime.into();
-        Duration::from_secs(uptime.saturating_mul(10))
+        self.uptime.0
     }
 
     pub fn object_id(&self) -> Oid {
@@ -51,13 +51,175 @@ impl System {
         let top = snmp
             .tree
             .oid_by_name("internet.mgmt.mib-2.system")
-            .map_err(|e| anyhow!("{e} (is mib-2 in the OID tree?)"))?;
+            .map_err(|e| e.hint("is mib-2 in the OID tree?"))?;
 
         let res = snmp.walk(top).await?;
 
         let o = res.extract_object(top, "sys")?;
         Ok(o)
     }
+
+    /**
+     * Use values obtained by some other means, such as from a capture file,
+     * rather than walking a live device.
+     */
+    pub fn from_walk(walk: &WalkedValues) -> Result<System> {
+        let top = walk
+            .tree
+            .oid_by_name("internet.mgmt.mib-2.system")
+            .map_err(|e| e.hint("is mib-2 in the OID tree?"))?;
+
+        walk.extract_object(top, "sys")
+    }
+
+    /**
+     * Change the administrative strings of the system group, all in one
+     * request.
+     */
+    pub async fn configure(snmp: &Client, change: &SystemChange) -> Result<()> {
+        change.validate()?;
+
+        let top = snmp
+            .tree
+            .oid_by_name("internet.mgmt.mib-2.system")
+            .map_err(|e| e.hint("is mib-2 in the OID tree?"))?;
+        let scalar = |name: &str| -> Result<Oid> {
+            let oid = snmp.tree.oid_by_name_under(top, name)?;
+            Ok(oid.child(0).unwrap().into())
+        };
+
+        let mut values = Vec::new();
+        for (name, value) in [
+            ("sysContact", &change.contact),
+            ("sysName", &change.name),
+            ("sysLocation", &change.location),
+        ] {
+            if let Some(value) = value {
+                values.push((
+                    scalar(name)?,
+                    Value(csnmp::ObjectValue::String(
+                        value.as_bytes().to_vec(),
+                    )),
+                ));
+            }
+        }
+
+        if !values.is_empty() {
+            snmp.set_multiple(&values).await?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Changes to the administrative strings of the system group, as made by
+ * System::configure().  Anything left as None is not changed.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemChange {
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub location: Option<String>,
+}
+
+impl SystemChange {
+    /**
+     * Check that each value is a DisplayString the agent will accept,
+     * without sending anything.
+     */
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("sysName", &self.name),
+            ("sysContact", &self.contact),
+            ("sysLocation", &self.location),
+        ] {
+            if let Some(value) = value {
+                check_string(name, value, 255)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == SystemChange::default()
+    }
+}
+
+/**
+ * A row of the interfaces table (ifTable).  Only the columns that every
+ * agent is likely to provide are included.
+ */
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Interface {
+    pub index: u32,
+    pub descr: String,
+    #[serde(rename = "Type")]
+    pub if_type: IfType,
+    pub mtu: i32,
+    /**
+     * An estimate of the bandwidth in bits per second.
+     */
+    pub speed: u32,
+    pub phys_address: PhysAddress,
+    pub admin_status: IfAdminStatus,
+    pub oper_status: IfOperStatus,
+    /**
+     * The uptime of the system when the interface last changed its
+     * operational state.
+     */
+    pub last_change: TimeStamp,
+    pub in_octets: u32,
+    pub in_discards: u32,
+    pub in_errors: u32,
+    pub out_octets: u32,
+    pub out_discards: u32,
+    pub out_errors: u32,
+}
+
+impl Interface {
+    /**
+     * Walk the interfaces table, keyed by ifIndex.
+     */
+    pub async fn from_client(
+        snmp: &Client,
+    ) -> Result<BTreeMap<u32, Interface>> {
+        let top = snmp
+            .tree
+            .oid_by_name("internet.mgmt.mib-2.interfaces")
+            .map_err(|e| e.hint("is IF-MIB in the OID tree?"))?;
+
+        let res = snmp.walk(top).await?;
+
+        Interface::extract(&res, top)
+    }
+
+    /**
+     * Use values obtained by some other means, such as from a capture file,
+     * rather than walking a live device.
+     */
+    pub fn from_walk(walk: &WalkedValues) -> Result<BTreeMap<u32, Interface>> {
+        let top = walk
+            .tree
+            .oid_by_name("internet.mgmt.mib-2.interfaces")
+            .map_err(|e| e.hint("is IF-MIB in the OID tree?"))?;
+
+        Interface::extract(walk, top)
+    }
+
+    fn extract(
+        walk: &WalkedValues,
+        top: Oid,
+    ) -> Result<BTreeMap<u32, Interface>> {
+        let tree = &walk.tree;
+        let table = tree.oid_by_name_under(top, "ifTable")?;
+
+        walk.extract_table(
+            tree.oid_by_name_under(top, "ifNumber")?,
+            tree.oid_by_name_under(table, "ifEntry")?,
+            "if",
+        )
+    }
 }
 
 #[derive(Deserialize_repr, PartialEq, Eq, Debug)]
@@ -383,13 +545,32 @@ pub enum IfType {
     Roe = 302,
 }
 
+/**
+ * Get a shared tree containing the base definitions and this MIB module,
+ * constructed on first use.
+ */
+pub fn tree() -> Arc<OidTree> {
+    static TREE: OnceLock<Arc<OidTree>> = OnceLock::new();
+    shared_tree(&TREE, &[populate])
+}
+
 pub fn populate(tree: &mut OidTree) -> Result<()> {
     add_from_instructions_under(
         tree,
+        "SNMPv2-SMI",
         "mgmt",
         tree.oid_by_name("internet.mgmt")?.as_slice().to_vec(),
+        &[("mib-2", "mgmt", 1)],
+    )?;
+
+    let mib2 = tree.oid_by_name("internet.mgmt.mib-2")?.as_slice().to_vec();
+
+    add_from_instructions_under(
+        tree,
+        "SNMPv2-MIB",
+        "mib-2",
+        mib2.clone(),
         &[
-            ("mib-2", "mgmt", 1),
             /*
              * System group:
              */
@@ -402,6 +583,15 @@ pub fn populate(tree: &mut OidTree) -> Result<()> {
             ("sysLocation", "system", 6),
             ("sysServices", "system", 7),
             ("sysOREntry", "system", 8),
+        ],
+    )?;
+
+    add_from_instructions_under(
+        tree,
+        "IF-MIB",
+        "mib-2",
+        mib2,
+        &[
             /*
              * Interfaces group:
              */
@@ -432,8 +622,36 @@ pub fn populate(tree: &mut OidTree) -> Result<()> {
             ("ifOutQLen", "ifEntry", 21),
             ("ifSpecific", "ifEntry", 22),
         ],
-    )
-    .map_err(|e| anyhow!("populate_mib2: {e}"))?;
+    )?;
+
+    add_from_instructions_under(
+        tree,
+        "IP-MIB",
+        "mib-2",
+        tree.oid_by_name("internet.mgmt.mib-2")?.as_slice().to_vec(),
+        &[
+            /*
+             * IP group:
+             */
+            ("ip", "mib-2", 4),
+            ("ipAddrTable", "ip", 20),
+            ("ipAddrEntry", "ipAddrTable", 1),
+            ("ipAdEntAddr", "ipAddrEntry", 1),
+            ("ipAdEntIfIndex", "ipAddrEntry", 2),
+            ("ipAdEntNetMask", "ipAddrEntry", 3),
+            ("ipAdEntBcastAddr", "ipAddrEntry", 4),
+            ("ipAdEntReasmMaxSize", "ipAddrEntry", 5),
+        ],
+    )?;
+
+    tree.set_table_index(
+        tree.oid_by_name("IF-MIB::ifEntry")?.as_slice(),
+        &[("ifIndex", IndexSyntax::Integer)],
+    )?;
+    tree.set_table_index(
+        tree.oid_by_name("IP-MIB::ipAddrEntry")?.as_slice(),
+        &[("ipAdEntAddr", IndexSyntax::IpAddress)],
+    )?;
 
     Ok(())
 }
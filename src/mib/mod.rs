@@ -3,25 +3,29 @@
  */
 
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 
 use crate::oidtree::OidTree;
-use anyhow::{bail, Result};
+use crate::{Error, Result};
 
 pub mod apc;
 pub mod cisco;
 pub mod mib_2;
 
 mod sublude {
-    pub(crate) use super::add_from_instructions_under;
-    pub(crate) use crate::oidtree::OidTree;
-    pub(crate) use crate::value::Value;
+    pub(crate) use super::{
+        add_from_instructions_under, check_range, check_string, invalid,
+        shared_tree,
+    };
+    pub(crate) use crate::oidtree::{IndexSyntax, OidTree};
+    pub(crate) use crate::value::{Scaled, Value, Varbind};
     pub(crate) use crate::walk::WalkedValues;
-    pub(crate) use crate::{Client, Oid};
     #[allow(unused_imports)]
-    pub(crate) use anyhow::{anyhow, bail, Result};
+    pub(crate) use crate::{Client, Error, Exception, Oid, Result};
     pub(crate) use serde::Deserialize;
     pub(crate) use serde_repr::Deserialize_repr;
-    pub(crate) use std::collections::BTreeMap;
+    pub(crate) use std::collections::{BTreeMap, BTreeSet};
+    pub(crate) use std::sync::{Arc, OnceLock};
     pub(crate) use std::time::Duration;
 }
 
@@ -35,12 +39,14 @@ pub fn base() -> OidTree {
      */
     let internet =
         tree.add_oid_root(&[1, 3, 6, 1], "internet").expect("internet");
+    tree.set_module(&internet, "SNMPv2-SMI").expect("internet module");
 
     /*
      * Base SNMPv2 definitions at the top of the tree:
      */
     add_from_instructions_under(
         &mut tree,
+        "SNMPv2-SMI",
         "internet",
         internet,
         &[
@@ -58,8 +64,42 @@ pub fn base() -> OidTree {
     tree
 }
 
+/**
+ * Get a shared tree that contains the base definitions and every MIB module
+ * bundled with this crate.  The tree is constructed on first use, and is
+ * suitable for passing to ClientBuilder::oid_tree().
+ */
+pub fn bundled() -> Arc<OidTree> {
+    static TREE: OnceLock<Arc<OidTree>> = OnceLock::new();
+    shared_tree(&TREE, &[mib_2::populate, apc::populate, cisco::populate])
+}
+
+/**
+ * Construct a tree from the base definitions and the provided MIB modules
+ * exactly once, and hand out references to it thereafter.
+ */
+pub(crate) fn shared_tree(
+    cell: &'static OnceLock<Arc<OidTree>>,
+    modules: &[fn(&mut OidTree) -> Result<()>],
+) -> Arc<OidTree> {
+    Arc::clone(cell.get_or_init(|| {
+        let mut tree = base();
+        for populate in modules {
+            populate(&mut tree).expect("populate bundled MIB module");
+        }
+        Arc::new(tree)
+    }))
+}
+
+/**
+ * Add a set of named nodes, all defined by the same MIB module, beneath an
+ * existing anchor node.  Each instruction is a tuple of (name, parent name,
+ * sub-identifier), where the parent is either the anchor or a node added by an
+ * earlier instruction.
+ */
 pub(crate) fn add_from_instructions_under(
     tree: &mut OidTree,
+    module: &str,
     anchor_name: &str,
     anchor_oid: Vec<u32>,
     instructions: &[(&str, &str, u32)],
@@ -70,13 +110,59 @@ pub(crate) fn add_from_instructions_under(
     for (ins, under, rel) in instructions {
         if let Some(under) = seen.get(under).cloned() {
             let new = tree.add_oid_under(&under, &[*rel], ins)?;
-            if seen.insert(ins, new).is_some() {
-                bail!("adding: duplicate? {ins:?} -> {{ {under:?} {rel} }}");
+            tree.set_module(&new, module)?;
+            if seen.insert(ins, new.clone()).is_some() {
+                return Err(Error::Tree {
+                    oid: new,
+                    message: format!("{module}: duplicate name {ins:?}"),
+                });
             }
         } else {
-            bail!("adding: could not find {{ {under:?} {rel} }} for {ins:?}");
+            return Err(Error::Tree {
+                oid: Vec::new(),
+                message: format!(
+                    "{module}: could not find {under:?} for {ins:?}"
+                ),
+            });
         }
     }
 
     Ok(())
 }
+
+pub(crate) fn invalid(name: &str, message: String) -> Error {
+    Error::InvalidSetting { name: name.to_string(), message }
+}
+
+pub(crate) fn check_range(
+    name: &str,
+    value: i64,
+    range: std::ops::RangeInclusive<i64>,
+) -> Result<()> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(invalid(
+            name,
+            format!(
+                "{value} is not within {} to {}",
+                range.start(),
+                range.end()
+            ),
+        ))
+    }
+}
+
+/**
+ * Check that a string is printable ASCII, as a DisplayString must be, and
+ * not too long for the column.
+ */
+pub(crate) fn check_string(name: &str, value: &str, max: usize) -> Result<()> {
+    if value.chars().any(|c| !c.is_ascii() || c.is_ascii_control()) {
+        Err(invalid(name, format!("{value:?} is not printable ASCII")))
+    } else if value.len() > max {
+        Err(invalid(name, format!("{value:?} is longer than {max}")))
+    } else {
+        Ok(())
+    }
+}
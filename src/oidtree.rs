@@ -3,9 +3,11 @@
  */
 
 use std::fmt::Display;
+use std::net::Ipv4Addr;
 
-use crate::Oid;
-use anyhow::{anyhow, bail, Result};
+use crate::{Error, Oid, Result};
+
+type SResult<T> = std::result::Result<T, String>;
 
 #[derive(Debug, Clone)]
 pub struct OidTreeEntry {
@@ -13,7 +15,9 @@ pub struct OidTreeEntry {
     value: u32,
     parent: Option<u64>,
     name: Option<String>,
+    module: Option<String>,
     root: bool,
+    index: Vec<IndexPart>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,36 +26,335 @@ pub struct OidTree {
     nodes: Vec<OidTreeEntry>,
 }
 
-fn split_name(name: &str) -> Result<Vec<&str>> {
+/**
+ * A single dot-separated component of an OID name, which may either be the
+ * name of a node in the tree or a numeric sub-identifier.
+ */
+#[derive(Debug, Clone, Copy)]
+enum NameComponent<'a> {
+    Name(&'a str),
+    Number(u32),
+}
+
+/**
+ * An OID name in any of the forms accepted by oid_by_name(): a name from a
+ * root (e.g., "internet.mgmt.mib-2.system"), a bare node name (e.g.,
+ * "sysName"), a module-qualified name (e.g., "SNMPv2-MIB::sysName"), any of
+ * these with a numeric instance suffix (e.g., "sysName.0"), or a fully
+ * numeric OID with or without a leading dot (e.g., ".1.3.6.1.2.1.1.5.0").
+ */
+struct ParsedName<'a> {
+    module: Option<&'a str>,
+    absolute: bool,
+    components: Vec<NameComponent<'a>>,
+}
+
+fn parse_name(name: &str) -> SResult<ParsedName<'_>> {
     if name.is_empty()
-        || name
-            .chars()
-            .any(|c| !c.is_ascii_alphanumeric() && c != '.' && c != '-')
+        || name.chars().any(|c| {
+            !c.is_ascii_alphanumeric() && c != '.' && c != '-' && c != ':'
+        })
     {
-        bail!("invalid OID name: {name:?}");
+        return Err("invalid OID name".into());
+    }
+
+    /*
+     * Split off the module name, if one was provided.  The module name is
+     * separated from the rest of the name by a double colon, and no other
+     * colons may appear.
+     */
+    let (module, rest) = match name.split_once("::") {
+        Some((module, rest)) => {
+            if module.is_empty()
+                || module.contains(':')
+                || module.contains('.')
+                || rest.contains(':')
+            {
+                return Err("invalid OID name".into());
+            }
+            (Some(module), rest)
+        }
+        None if name.contains(':') => return Err("invalid OID name".into()),
+        None => (None, name),
+    };
+
+    /*
+     * A leading dot means the name is relative to the top of the OID space,
+     * as produced by tools like snmpwalk.
+     */
+    let (absolute, rest) = match rest.strip_prefix('.') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if absolute && module.is_some() {
+        return Err("invalid OID name".into());
+    }
+
+    let components =
+        rest.split('.')
+            .map(|c| {
+                if c.is_empty() {
+                    Err("invalid OID name".into())
+                } else if c.chars().all(|c| c.is_ascii_digit()) {
+                    Ok(NameComponent::Number(c.parse().map_err(|_| {
+                        format!("invalid sub-identifier {c:?}")
+                    })?))
+                } else if c.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    Ok(NameComponent::Name(c))
+                } else {
+                    Err("invalid OID name".into())
+                }
+            })
+            .collect::<SResult<Vec<_>>>()?;
+
+    if absolute && !matches!(components[0], NameComponent::Number(_)) {
+        return Err("invalid OID name".into());
+    }
+
+    Ok(ParsedName { module, absolute, components })
+}
+
+/**
+ * The syntax of one component of the INDEX clause of a table entry, which
+ * determines how the instance sub-identifiers of a column value are decoded.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexSyntax {
+    /**
+     * An INTEGER or Unsigned32 value, encoded as a single sub-identifier.
+     */
+    Integer,
+    /**
+     * An IpAddress, encoded as four sub-identifiers.
+     */
+    IpAddress,
+    /**
+     * A MacAddress (or PhysAddress of the same size), encoded as six
+     * sub-identifiers.
+     */
+    MacAddress,
+    /**
+     * A fixed size OCTET STRING, encoded without a length.
+     */
+    FixedOctetString(usize),
+    /**
+     * A variable size OCTET STRING, encoded with a leading length.
+     */
+    OctetString,
+    /**
+     * An IMPLIED variable size OCTET STRING, which may only appear last in
+     * the INDEX clause and is encoded without a length.
+     */
+    ImpliedOctetString,
+    /**
+     * An OBJECT IDENTIFIER, encoded with a leading length.
+     */
+    ObjectIdentifier,
+    /**
+     * An IMPLIED OBJECT IDENTIFIER, which may only appear last in the INDEX
+     * clause and is encoded without a length.
+     */
+    ImpliedObjectIdentifier,
+}
+
+impl IndexSyntax {
+    fn is_implied(&self) -> bool {
+        matches!(
+            self,
+            IndexSyntax::ImpliedOctetString
+                | IndexSyntax::ImpliedObjectIdentifier
+        )
     }
 
-    let t = name.split('.').collect::<Vec<_>>();
-    if t.is_empty() {
-        bail!("invalid OID name: {name:?}")
+    /**
+     * Decode one index value from the front of the instance sub-identifiers,
+     * returning the value and whatever sub-identifiers remain.
+     */
+    fn decode<'a>(&self, inst: &'a [u32]) -> Option<(IndexValue, &'a [u32])> {
+        fn octets(inst: &[u32]) -> Option<Vec<u8>> {
+            inst.iter().map(|&b| u8::try_from(b).ok()).collect()
+        }
+
+        fn counted(inst: &[u32]) -> Option<(&[u32], &[u32])> {
+            let (&len, rest) = inst.split_first()?;
+            let len = usize::try_from(len).ok()?;
+            (rest.len() >= len).then(|| rest.split_at(len))
+        }
+
+        Some(match self {
+            IndexSyntax::Integer => {
+                let (&v, rest) = inst.split_first()?;
+                (IndexValue::Integer(v), rest)
+            }
+            IndexSyntax::IpAddress => {
+                let (v, rest) = inst.split_at_checked(4)?;
+                let v: [u8; 4] = octets(v)?.try_into().ok()?;
+                (IndexValue::IpAddress(Ipv4Addr::from(v)), rest)
+            }
+            IndexSyntax::MacAddress => {
+                let (v, rest) = inst.split_at_checked(6)?;
+                (IndexValue::MacAddress(octets(v)?.try_into().ok()?), rest)
+            }
+            IndexSyntax::FixedOctetString(n) => {
+                let (v, rest) = inst.split_at_checked(*n)?;
+                (IndexValue::OctetString(octets(v)?), rest)
+            }
+            IndexSyntax::OctetString => {
+                let (v, rest) = counted(inst)?;
+                (IndexValue::OctetString(octets(v)?), rest)
+            }
+            IndexSyntax::ImpliedOctetString => {
+                (IndexValue::OctetString(octets(inst)?), &[][..])
+            }
+            IndexSyntax::ObjectIdentifier => {
+                let (v, rest) = counted(inst)?;
+                (IndexValue::ObjectIdentifier(v.to_vec()), rest)
+            }
+            IndexSyntax::ImpliedObjectIdentifier => {
+                (IndexValue::ObjectIdentifier(inst.to_vec()), &[][..])
+            }
+        })
     }
+}
+
+#[derive(Debug, Clone)]
+struct IndexPart {
+    label: String,
+    syntax: IndexSyntax,
+}
+
+/**
+ * One decoded component of the instance part of a table column OID.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexValue {
+    Integer(u32),
+    IpAddress(Ipv4Addr),
+    MacAddress([u8; 6]),
+    OctetString(Vec<u8>),
+    ObjectIdentifier(Vec<u32>),
+}
 
-    Ok(t)
+impl Display for IndexValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn hex(
+            f: &mut std::fmt::Formatter<'_>,
+            buf: &[u8],
+        ) -> std::fmt::Result {
+            for (i, b) in buf.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(":")?;
+                }
+                write!(f, "{b:02x}")?;
+            }
+            Ok(())
+        }
+
+        match self {
+            IndexValue::Integer(v) => v.fmt(f),
+            IndexValue::IpAddress(ip) => ip.fmt(f),
+            IndexValue::MacAddress(mac) => hex(f, mac),
+            IndexValue::OctetString(buf) => {
+                /*
+                 * Strings that are entirely printable are shown in quotes;
+                 * anything else is shown as hexadecimal octets.
+                 */
+                if buf.iter().all(|b| (0x20..0x7f).contains(b)) {
+                    write!(f, "{:?}", std::str::from_utf8(buf).unwrap())
+                } else {
+                    hex(f, buf)
+                }
+            }
+            IndexValue::ObjectIdentifier(oid) => {
+                for (i, v) in oid.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    v.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
+/**
+ * The name of an OID, split into the object part (the path through named
+ * nodes in the tree) and the instance part (any trailing sub-identifiers that
+ * do not appear in the tree, such as a table row index or the ".0" of a
+ * scalar).  If the object is a column in a table with a known INDEX clause,
+ * the instance part is also available in decoded form.
+ *
+ * The alternate form of the Display implementation (i.e., "{:#}") omits all
+ * but the last component of the object part; e.g., "ifDescr[3]" rather than
+ * "internet.mgmt.mib-2.interfaces.ifTable.ifEntry.ifDescr[3]".
+ */
 pub struct OidName {
     components: Vec<String>,
+    instance: Vec<u32>,
+    index: Option<Vec<(String, IndexValue)>>,
 }
 
 impl OidName {
+    /**
+     * The last component of the object part of the name.
+     */
     pub fn basename(&self) -> &str {
         &self.components[self.components.len() - 1]
     }
+
+    /**
+     * The object part of the name, without any instance suffix.
+     */
+    pub fn object(&self) -> String {
+        self.components.join(".")
+    }
+
+    /**
+     * The sub-identifiers that follow the last named node in the tree.
+     */
+    pub fn instance(&self) -> &[u32] {
+        &self.instance
+    }
+
+    /**
+     * The instance part of the name decoded according to the INDEX clause of
+     * the table, as (label, value) pairs.  Returns None if the object is not
+     * a column in a table with a known INDEX clause, or if the instance could
+     * not be decoded.
+     */
+    pub fn index(&self) -> Option<&[(String, IndexValue)]> {
+        self.index.as_deref()
+    }
 }
 
 impl Display for OidName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.components.join(".").fmt(f)
+        if f.alternate() {
+            f.write_str(self.basename())?;
+        } else {
+            f.write_str(&self.object())?;
+        }
+
+        match self.index.as_deref() {
+            Some([(_, v)]) => write!(f, "[{v}]"),
+            Some(index) => {
+                f.write_str("[")?;
+                for (i, (label, v)) in index.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{label}={v}")?;
+                }
+                f.write_str("]")
+            }
+            None => {
+                for v in self.instance.iter() {
+                    write!(f, ".{v}")?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
@@ -62,74 +365,170 @@ impl Default for OidTree {
 }
 
 impl OidTree {
+    /**
+     * Locate an OID by name, relative to the provided parent OID.  The name
+     * may contain a mix of node names and numeric sub-identifiers, and may
+     * end with a numeric instance suffix.
+     */
     pub fn oid_by_name_under(&self, parent: Oid, name: &str) -> Result<Oid> {
-        let t = split_name(name)?;
+        let fail = |message: String| Error::UnknownName {
+            name: name.to_string(),
+            under: Some(Box::new(parent)),
+            message,
+        };
+
+        let parsed = parse_name(name).map_err(fail)?;
+        if parsed.module.is_some() || parsed.absolute {
+            return Err(fail("cannot be used relative to another OID".into()));
+        }
 
         /*
          * Find the parent OID in the tree:
          */
         let Ok(root) = self.find_oid(parent.as_slice()) else {
-            bail!("no name found for parent OID {parent}");
+            return Err(fail("parent OID is not in the tree".into()));
         };
 
         /*
          * Now, walk down the tree we've been provided and match nodes.
          */
-        let terminus = self
-            .walk_down_under(root, &t)
-            .map_err(|e| anyhow!("mapping OID {name:?} under {parent}: {e}"))?;
-
-        Ok(self.oid_for_node(terminus))
+        self.walk_down_under(Some(root), &parsed.components).map_err(fail)
     }
 
+    /**
+     * Locate an OID by name.  The name may be a path from a root of the tree
+     * (e.g., "internet.mgmt.mib-2.system"), a name that appears only once in
+     * the tree (e.g., "sysName"), or a name qualified with the MIB module in
+     * which it is defined (e.g., "SNMPv2-MIB::sysName").  Any of these forms
+     * may be followed by further names or numeric sub-identifiers, such as an
+     * instance suffix (e.g., "ifDescr.3").  A purely numeric OID, with or
+     * without a leading dot (e.g., ".1.3.6.1.2.1.1.5.0"), is also accepted.
+     */
     pub fn oid_by_name(&self, name: &str) -> Result<Oid> {
-        let t = split_name(name)?;
+        self.oid_by_name_inner(name).map_err(|message| Error::UnknownName {
+            name: name.to_string(),
+            under: None,
+            message,
+        })
+    }
 
-        /*
-         * Find a root entry in the tree with this name:
-         */
-        let root = self
+    fn oid_by_name_inner(&self, name: &str) -> SResult<Oid> {
+        let parsed = parse_name(name)?;
+
+        let NameComponent::Name(first) = parsed.components[0] else {
+            /*
+             * This name begins with a number, so we start at the very top of
+             * the OID space.
+             */
+            return self.walk_down_under(None, &parsed.components);
+        };
+
+        let start = if let Some(module) = parsed.module {
+            /*
+             * The name of the first component must be unique within the
+             * specified module.
+             */
+            self.find_unique_name(first, |n| {
+                n.module.as_deref() == Some(module)
+            })
+            .map_err(|e| format!("{e} in {module}"))?
+        } else if let Some(root) = self
             .nodes
             .iter()
-            .find(|n| n.root && n.name.as_deref() == Some(t[0]));
-        let Some(root) = root else {
-            bail!("could not find root node {:?}", t[0]);
+            .find(|n| n.root && n.name.as_deref() == Some(first))
+        {
+            /*
+             * Prefer a root entry in the tree with this name.
+             */
+            root
+        } else {
+            /*
+             * Otherwise, the name must appear exactly once in the tree.
+             */
+            self.find_unique_name(first, |_| true)?
         };
 
         /*
          * Now, walk down the tree we've been provided and match nodes.
          */
-        let terminus = self
-            .walk_down_under(root, &t[1..])
-            .map_err(|e| anyhow!("mapping OID {name:?}: {e}"))?;
+        self.walk_down_under(Some(start), &parsed.components[1..])
+    }
 
-        Ok(self.oid_for_node(terminus))
+    fn find_unique_name(
+        &self,
+        name: &str,
+        filter: impl Fn(&OidTreeEntry) -> bool,
+    ) -> SResult<&OidTreeEntry> {
+        let mut matches = self
+            .nodes
+            .iter()
+            .filter(|n| n.name.as_deref() == Some(name) && filter(n));
+
+        match (matches.next(), matches.next()) {
+            (Some(n), None) => Ok(n),
+            (Some(_), Some(_)) => Err(format!("name {name:?} is ambiguous")),
+            (None, _) => Err(format!("could not find {name:?}")),
+        }
     }
 
     /**
-     * Walk down the tree from a given node, using each component of the
-     * provided OID name to select the next node in the walk.
+     * Walk down the tree from a given node (or from the very top of the OID
+     * space, if no node is provided), using each component of the provided
+     * OID name to select the next node in the walk.  Once we encounter a
+     * number that does not appear in the tree, we have left the tree and any
+     * remaining components must also be numeric.
      */
-    fn walk_down_under<'a>(
-        &'a self,
-        mut prior: &'a OidTreeEntry,
-        names: &[&'a str],
-    ) -> Result<&'a OidTreeEntry> {
-        for &tt in names.iter() {
-            let next = self.nodes.iter().find(|n| {
-                !n.root
-                    && n.name.as_deref() == Some(tt)
-                    && n.parent == Some(prior.id)
-            });
-
-            if let Some(next) = next {
-                prior = next;
-            } else {
-                bail!("could not find {tt:?}");
+    fn walk_down_under(
+        &self,
+        start: Option<&OidTreeEntry>,
+        components: &[NameComponent],
+    ) -> SResult<Oid> {
+        let mut out = start
+            .map(|n| self.oid_for_node(n).as_slice().to_vec())
+            .unwrap_or_default();
+        let mut prior = start.map(|n| n.id);
+        let mut in_tree = true;
+
+        for c in components {
+            match c {
+                NameComponent::Name(tt) => {
+                    if !in_tree {
+                        return Err(format!(
+                            "name {tt:?} follows an unknown sub-identifier"
+                        ));
+                    }
+
+                    let Some(next) = self.nodes.iter().find(|n| {
+                        n.name.as_deref() == Some(tt) && n.parent == prior
+                    }) else {
+                        return Err(format!("could not find {tt:?}"));
+                    };
+
+                    out.push(next.value);
+                    prior = Some(next.id);
+                }
+                NameComponent::Number(num) => {
+                    out.push(*num);
+
+                    if in_tree {
+                        if let Some(next) = self
+                            .nodes
+                            .iter()
+                            .find(|n| n.value == *num && n.parent == prior)
+                        {
+                            prior = Some(next.id);
+                        } else {
+                            in_tree = false;
+                        }
+                    }
+                }
             }
         }
 
-        Ok(prior)
+        Ok(Oid(out
+            .as_slice()
+            .try_into()
+            .map_err(|e| format!("invalid OID {out:?}: {e}"))?))
     }
 
     /**
@@ -151,52 +550,134 @@ impl OidTree {
     }
 
     pub fn oid_name(&self, oid: Oid) -> Result<OidName> {
-        let oid = oid.as_slice();
+        let sub_ids = oid.as_slice();
 
         /*
-         * Try to find an oid entry for this oid.
+         * Walk down the tree as far as we can, keeping track of the deepest
+         * named node we pass through.  Anything beyond that node is the
+         * instance part of the name.
          */
-        let mut n = oid.len();
-        let mut out = Vec::new();
-        let mut anchor = loop {
-            if n == 0 {
-                /*
-                 * We give up.
-                 */
-                bail!("cannot do it");
-            }
+        let mut prior = None;
+        let mut anchor = None;
+        for (depth, &e) in sub_ids.iter().enumerate() {
+            let Some(next) =
+                self.nodes.iter().find(|n| n.parent == prior && n.value == e)
+            else {
+                break;
+            };
 
-            if let Ok(ent) = self.find_oid(&oid[0..n]) {
-                /*
-                 * Found an anchor!
-                 */
-                break ent;
-            } else {
-                out.push(oid[n - 1].to_string());
-                n -= 1;
+            if next.name.is_some() {
+                anchor = Some((depth + 1, next));
             }
+            prior = Some(next.id);
+        }
+        let Some((depth, anchor)) = anchor else {
+            return Err(Error::UnknownOid { oid: Box::new(oid) });
         };
+        let instance = sub_ids[depth..].to_vec();
 
+        let mut components = Vec::new();
+        let mut ent = anchor;
         loop {
-            if let Some(name) = anchor.name.as_deref() {
-                out.push(name.to_string());
+            if let Some(name) = ent.name.as_deref() {
+                components.push(name.to_string());
             } else {
-                out.push(anchor.value.to_string());
+                components.push(ent.value.to_string());
             }
 
-            if anchor.root {
+            if ent.root {
                 break;
             }
 
-            if let Some(parent) = anchor.parent {
-                anchor = self.nodes.iter().find(|n| n.id == parent).unwrap();
+            if let Some(parent) = ent.parent {
+                ent = self.nodes.iter().find(|n| n.id == parent).unwrap();
             } else {
                 break;
             }
         }
+        components.reverse();
 
-        out.reverse();
-        Ok(OidName { components: out })
+        /*
+         * If the named node is a column in a table entry with a known INDEX
+         * clause, try to decode the instance part of the OID.
+         */
+        let index = anchor
+            .parent
+            .filter(|_| !instance.is_empty())
+            .and_then(|parent| self.nodes.iter().find(|n| n.id == parent))
+            .filter(|entry| !entry.index.is_empty())
+            .and_then(|entry| {
+                let mut inst = instance.as_slice();
+                let mut out = Vec::new();
+                for part in entry.index.iter() {
+                    let (v, rest) = part.syntax.decode(inst)?;
+                    out.push((part.label.clone(), v));
+                    inst = rest;
+                }
+                inst.is_empty().then_some(out)
+            });
+
+        Ok(OidName { components, instance, index })
+    }
+
+    /**
+     * List the named nodes directly beneath an OID in the tree, such as the
+     * columns of a table entry, in OID order.
+     */
+    pub fn children(&self, oid: Oid) -> Result<Vec<Oid>> {
+        let parent = self
+            .find_oid(oid.as_slice())
+            .map_err(|_| Error::UnknownOid { oid: Box::new(oid) })?;
+
+        let mut values = self
+            .nodes
+            .iter()
+            .filter(|n| n.parent == Some(parent.id) && n.name.is_some())
+            .map(|n| n.value)
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+
+        values
+            .into_iter()
+            .map(|v| {
+                oid.child(v)
+                    .map(Oid::from)
+                    .ok_or_else(|| tree_error(oid.as_slice(), "OID too long"))
+            })
+            .collect()
+    }
+
+    /**
+     * Record the INDEX clause of a table entry, as a list of (label, syntax)
+     * pairs, so that the instance part of the OID of each column value can
+     * be decoded by oid_name().  The label is generally the name of the index
+     * column.
+     */
+    pub fn set_table_index(
+        &mut self,
+        entry: &[u32],
+        index: &[(&str, IndexSyntax)],
+    ) -> Result<()> {
+        if index.is_empty() {
+            return Err(tree_error(entry, "empty index for table entry"));
+        }
+        if index[..index.len() - 1].iter().any(|(_, syn)| syn.is_implied()) {
+            return Err(tree_error(
+                entry,
+                "IMPLIED may only be used for the last index",
+            ));
+        }
+
+        let ent = self.find_oid_mut(entry)?;
+        ent.index = index
+            .iter()
+            .map(|(label, syntax)| IndexPart {
+                label: label.to_string(),
+                syntax: *syntax,
+            })
+            .collect();
+
+        Ok(())
     }
 
     fn find_oid(&self, oid: &[u32]) -> Result<&OidTreeEntry> {
@@ -208,7 +689,7 @@ impl OidTree {
             prior = Some(if let Some(next) = next {
                 next.id
             } else {
-                bail!("could not find oid {oid:?}");
+                return Err(tree_error(oid, "could not find OID"));
             });
         }
         Ok(self.nodes.iter().find(|n| n.id == prior.unwrap()).unwrap())
@@ -223,7 +704,7 @@ impl OidTree {
             prior = Some(if let Some(next) = next {
                 next.id
             } else {
-                bail!("could not find oid {oid:?}");
+                return Err(tree_error(oid, "could not find OID"));
             });
         }
         Ok(self.nodes.iter_mut().find(|n| n.id == prior.unwrap()).unwrap())
@@ -236,7 +717,7 @@ impl OidTree {
         name: &str,
     ) -> Result<Vec<u32>> {
         if oid.is_empty() || name.is_empty() {
-            bail!("that wont work");
+            return Err(tree_error(parent, "empty OID or name"));
         }
 
         /*
@@ -260,7 +741,9 @@ impl OidTree {
                     value: e,
                     parent: prior,
                     name: None,
+                    module: None,
                     root: false,
+                    index: Vec::new(),
                 });
                 id
             });
@@ -279,13 +762,37 @@ impl OidTree {
         Ok(full_oid)
     }
 
+    /**
+     * Record the name of the MIB module that defines a named node, so that
+     * the node may be located with a module-qualified name like
+     * "SNMPv2-MIB::sysName".
+     */
+    pub fn set_module(&mut self, oid: &[u32], module: &str) -> Result<()> {
+        if module.is_empty()
+            || module.chars().any(|c| !c.is_ascii_alphanumeric() && c != '-')
+        {
+            return Err(tree_error(
+                oid,
+                &format!("invalid module name: {module:?}"),
+            ));
+        }
+
+        let ent = self.find_oid_mut(oid)?;
+        if ent.name.is_none() {
+            return Err(tree_error(oid, "cannot set module for unnamed OID"));
+        }
+        ent.module = Some(module.to_string());
+
+        Ok(())
+    }
+
     pub fn add_oid_root(
         &mut self,
         oid: &[u32],
         name: &str,
     ) -> Result<Vec<u32>> {
         if oid.is_empty() || name.is_empty() {
-            bail!("that wont work");
+            return Err(tree_error(oid, "empty OID or name"));
         }
 
         /*
@@ -309,7 +816,9 @@ impl OidTree {
                     value: e,
                     parent: prior,
                     name: None,
+                    module: None,
                     root: false,
+                    index: Vec::new(),
                 });
                 id
             });
@@ -325,3 +834,156 @@ impl OidTree {
         Ok(oid.to_vec())
     }
 }
+
+fn tree_error(oid: &[u32], message: &str) -> Error {
+    Error::Tree { oid: oid.to_vec(), message: message.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+     * A small tree in which two vendor modules each define a node named
+     * "status", and with tables indexed by an IpAddress and by an integer and a
+     * string, for exercising names without the bundled MIBs.
+     */
+    fn small_tree() -> OidTree {
+        let mut tree = OidTree::default();
+        let internet = tree.add_oid_root(&[1, 3, 6, 1], "internet").unwrap();
+        let mib2 = tree.add_oid_under(&internet, &[2, 1], "mib-2").unwrap();
+        let ip = tree.add_oid_under(&mib2, &[4], "ip").unwrap();
+        let table = tree.add_oid_under(&ip, &[20], "ipAddrTable").unwrap();
+        let entry = tree.add_oid_under(&table, &[1], "ipAddrEntry").unwrap();
+        tree.add_oid_under(&entry, &[1], "ipAdEntAddr").unwrap();
+        tree.add_oid_under(&entry, &[2], "ipAdEntIfIndex").unwrap();
+        tree.set_table_index(
+            &entry,
+            &[("ipAdEntAddr", IndexSyntax::IpAddress)],
+        )
+        .unwrap();
+        let ent =
+            tree.add_oid_under(&internet, &[4, 1], "enterprises").unwrap();
+
+        for (number, vendor, module) in
+            [(1000, "acme", "ACME-MIB"), (2000, "widget", "WIDGET-MIB")]
+        {
+            let vendor = tree.add_oid_under(&ent, &[number], vendor).unwrap();
+            let status = tree.add_oid_under(&vendor, &[1], "status").unwrap();
+            tree.set_module(&vendor, module).unwrap();
+            tree.set_module(&status, module).unwrap();
+        }
+
+        let acme = tree.oid_by_name("acme").unwrap();
+        let entry = tree
+            .add_oid_under(acme.as_slice(), &[2, 1], "acmePortEntry")
+            .unwrap();
+        tree.add_oid_under(&entry, &[3], "acmePortSpeed").unwrap();
+        tree.set_table_index(
+            &entry,
+            &[
+                ("acmeSlot", IndexSyntax::Integer),
+                ("acmePortName", IndexSyntax::OctetString),
+            ],
+        )
+        .unwrap();
+
+        tree
+    }
+
+    #[test]
+    fn oid_name_lookup() {
+        let tree = small_tree();
+        let lookup = |name| tree.oid_by_name(name).map(|oid| oid.to_string());
+
+        assert_eq!(lookup("mib-2").unwrap(), "1.3.6.1.2.1");
+        assert_eq!(lookup("acme").unwrap(), "1.3.6.1.4.1.1000");
+        assert_eq!(lookup("ACME-MIB::status").unwrap(), "1.3.6.1.4.1.1000.1");
+        assert_eq!(
+            lookup("WIDGET-MIB::status.0").unwrap(),
+            "1.3.6.1.4.1.2000.1.0"
+        );
+
+        /*
+         * Names may be mixed with numeric sub-identifiers, both within the tree
+         * and beyond it.
+         */
+        assert_eq!(lookup("acme.1.0").unwrap(), "1.3.6.1.4.1.1000.1.0");
+        assert_eq!(lookup("mib-2.4.20.1.1").unwrap(), "1.3.6.1.2.1.4.20.1.1");
+        assert_eq!(
+            lookup("internet.4.1.widget.status").unwrap(),
+            "1.3.6.1.4.1.2000.1"
+        );
+        assert_eq!(lookup(".1.3.6.1.2.1.4").unwrap(), "1.3.6.1.2.1.4");
+        assert_eq!(lookup("1.3.6.1.9.9").unwrap(), "1.3.6.1.9.9");
+
+        let message = |name| match tree.oid_by_name(name) {
+            Err(Error::UnknownName { name: n, under: None, message }) => {
+                assert_eq!(n, name);
+                message
+            }
+            other => panic!("unexpected result for {name:?}: {other:?}"),
+        };
+        assert_eq!(message("status"), r#"name "status" is ambiguous"#);
+        assert_eq!(message("status.0"), r#"name "status" is ambiguous"#);
+        assert_eq!(message("nosuch"), r#"could not find "nosuch""#);
+        assert_eq!(message("acme.nosuch"), r#"could not find "nosuch""#);
+        assert_eq!(
+            message("OTHER-MIB::status"),
+            r#"could not find "status" in OTHER-MIB"#
+        );
+        assert_eq!(
+            message("mib-2.99.ip"),
+            r#"name "ip" follows an unknown sub-identifier"#
+        );
+        for invalid in
+            ["", "acme..1", "acme.", ".acme", "ACME-MIB::.1.3", "a:b"]
+        {
+            assert_eq!(message(invalid), "invalid OID name");
+        }
+        assert_eq!(
+            tree.oid_by_name("nosuch").unwrap_err().to_string(),
+            r#"mapping OID "nosuch": could not find "nosuch""#
+        );
+    }
+
+    #[test]
+    fn oid_name_display() {
+        let tree = small_tree();
+        let name = |oid: &str| tree.oid_name(tree.oid_by_name(oid).unwrap());
+
+        let addr = name("ipAdEntAddr.10.0.0.1").unwrap();
+        assert_eq!(format!("{addr:#}"), "ipAdEntAddr[10.0.0.1]");
+        assert_eq!(
+            addr.to_string(),
+            "internet.2.mib-2.ip.ipAddrTable.ipAddrEntry.ipAdEntAddr[10.0.0.1]"
+        );
+        assert_eq!(addr.basename(), "ipAdEntAddr");
+        assert_eq!(addr.instance(), &[10, 0, 0, 1]);
+        let index = addr.index().unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[0].0, "ipAdEntAddr");
+        assert_eq!(index[0].1.to_string(), "10.0.0.1");
+
+        let speed = name("acmePortSpeed.3.4.101.116.104.48").unwrap();
+        assert_eq!(
+            format!("{speed:#}"),
+            r#"acmePortSpeed[acmeSlot=3, acmePortName="eth0"]"#
+        );
+        assert_eq!(
+            speed.object(),
+            "internet.4.enterprises.acme.2.acmePortEntry.acmePortSpeed"
+        );
+
+        /*
+         * An instance that does not match the INDEX clause is shown as plain
+         * sub-identifiers, as is the instance of a scalar.
+         */
+        let short = name("ipAdEntIfIndex.10.0.0").unwrap();
+        assert!(short.index().is_none());
+        assert_eq!(format!("{short:#}"), "ipAdEntIfIndex.10.0.0");
+        let status = name("ACME-MIB::status.0").unwrap();
+        assert_eq!(format!("{status:#}"), "status.0");
+        assert_eq!(status.to_string(), "internet.4.enterprises.acme.status.0");
+    }
+}
@@ -0,0 +1,406 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * A safety policy in front of outlet commands.  Pdu::send_command() will do
+ * whatever it is asked, including turning off the outlet that feeds the
+ * management network, or turning on enough outlets to trip a breaker.  A
+ * Policy looks at the state of the PDU before anything is sent, and refuses
+ * commands that would do either.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::mib::apc::{
+    BatchOptions, BatchReport, Group, LoadState, OutletAddress, OutletCommand,
+    Pdu, State,
+};
+use crate::{Client, Error, Result};
+
+/**
+ * How close to its limits a bank may be taken by turning outlets on.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadLimit {
+    /**
+     * Stay below the near overload threshold of the bank.
+     */
+    #[default]
+    NearOverload,
+    /**
+     * Stay below the overload threshold of the bank.
+     */
+    Overload,
+    /**
+     * Stay within the rating of the breaker for the bank.
+     */
+    BreakerRating,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /**
+     * Outlets that may not be turned off or rebooted unless the protection is
+     * explicitly overridden.
+     */
+    pub protected: BTreeSet<OutletAddress>,
+    pub limit: LoadLimit,
+    /**
+     * The current, in tenths of amps, that each outlet is expected to draw
+     * once it is turned on.  The PDU cannot know this for an outlet that is
+     * off, so it should be set to suit the equipment in the rack.
+     */
+    pub outlet_current: u32,
+}
+
+/**
+ * Options for Policy::send_commands().
+ */
+#[derive(Debug, Clone, Default)]
+pub struct GuardOptions {
+    pub batch: BatchOptions,
+    /**
+     * Allow protected outlets to be turned off or rebooted.
+     */
+    pub override_protection: bool,
+    /**
+     * Assess the commands and report what would happen, but send nothing.
+     */
+    pub dry_run: bool,
+}
+
+/**
+ * A reason for the policy to refuse a command.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    /**
+     * The outlet is protected, and the command would turn it off.
+     */
+    Protected,
+    /**
+     * The bank feeding the outlet is already in this load state.
+     */
+    BankLoadState { module: u32, bank: u32, state: LoadState },
+    /**
+     * Turning on this and the other outlets in the batch would take the
+     * current drawn by the bank, in tenths of amps, past the limit.
+     */
+    BankBudget { module: u32, bank: u32, projected: u32, limit: u32 },
+}
+
+impl std::fmt::Display for Refusal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Refusal::Protected => write!(f, "outlet is protected"),
+            Refusal::BankLoadState { module, bank, state } => {
+                write!(f, "module {module} bank {bank} is in state {state:?}")
+            }
+            Refusal::BankBudget { module, bank, projected, limit } => write!(
+                f,
+                "module {module} bank {bank} would draw {}.{} A, \
+                over the limit of {}.{} A",
+                projected / 10,
+                projected % 10,
+                limit / 10,
+                limit % 10,
+            ),
+        }
+    }
+}
+
+/**
+ * What the policy makes of one command.
+ */
+#[derive(Debug, Clone)]
+pub struct Assessed {
+    pub address: OutletAddress,
+    pub index: u32,
+    pub command: OutletCommand,
+    /**
+     * The state of the outlet before the command.
+     */
+    pub state: State,
+    pub refusals: Vec<Refusal>,
+}
+
+impl Assessed {
+    pub fn is_allowed(&self) -> bool {
+        self.refusals.is_empty()
+    }
+}
+
+/**
+ * The load on one bank that commands would turn outlets on in, in tenths of
+ * amps.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BankLoad {
+    pub state: LoadState,
+    pub current: u32,
+    pub projected: u32,
+    pub limit: u32,
+}
+
+/**
+ * The result of Policy::assess().
+ */
+#[derive(Debug, Clone)]
+pub struct Assessment {
+    pub commands: Vec<Assessed>,
+    /**
+     * Keyed by module and bank number.
+     */
+    pub banks: BTreeMap<(u32, u32), BankLoad>,
+}
+
+impl Assessment {
+    pub fn is_allowed(&self) -> bool {
+        self.commands.iter().all(Assessed::is_allowed)
+    }
+
+    fn reasons(&self) -> Vec<String> {
+        self.commands
+            .iter()
+            .flat_map(|c| {
+                c.refusals
+                    .iter()
+                    .map(|r| format!("{:?} on {}: {r}", c.command, c.address))
+            })
+            .collect()
+    }
+}
+
+/**
+ * The result of Policy::send_commands().  The report is None for a dry run.
+ */
+#[derive(Debug)]
+pub struct Guarded {
+    pub assessment: Assessment,
+    pub report: Option<BatchReport>,
+}
+
+impl Policy {
+    /**
+     * Fetch the outlet and bank tables, and work out whether the policy
+     * allows each command.  Nothing is sent, so this is also the dry run.
+     * If an outlet appears more than once, the last command for it is used.
+     */
+    pub async fn assess(
+        &self,
+        snmp: &Client,
+        commands: impl IntoIterator<Item = (OutletAddress, OutletCommand)>,
+        override_protection: bool,
+    ) -> Result<Assessment> {
+        let pdu = Pdu::fetch(
+            snmp,
+            &[
+                Group::OutletStatus,
+                Group::OutletProperties,
+                Group::BankConfig,
+                Group::BankProperties,
+                Group::BankStatus,
+            ],
+        )
+        .await?;
+        self.assess_with(&pdu, commands, override_protection)
+    }
+
+    /**
+     * Assess commands against tables that have already been fetched, which
+     * must include the outlet status and properties and the bank
+     * configuration, properties and status.
+     */
+    pub fn assess_with(
+        &self,
+        pdu: &Pdu,
+        commands: impl IntoIterator<Item = (OutletAddress, OutletCommand)>,
+        override_protection: bool,
+    ) -> Result<Assessment> {
+        let status = pdu
+            .outlet_status()?
+            .into_values()
+            .map(|s| (OutletAddress::new(s.module, s.number), s))
+            .collect::<BTreeMap<_, _>>();
+        let props = pdu.outlet_props()?;
+        let bank_config = pdu
+            .bank_config()?
+            .into_values()
+            .map(|b| ((b.module, b.number), b))
+            .collect::<BTreeMap<_, _>>();
+        let bank_props = pdu
+            .bank_props()?
+            .into_values()
+            .map(|b| ((b.module, b.number), b))
+            .collect::<BTreeMap<_, _>>();
+        let bank_status = pdu
+            .bank_status()?
+            .into_values()
+            .map(|b| ((b.module, b.number), b))
+            .collect::<BTreeMap<_, _>>();
+
+        let commands = commands.into_iter().collect::<BTreeMap<_, _>>();
+        let mut assessed = Vec::with_capacity(commands.len());
+        let mut turning_on: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+        for (address, command) in commands {
+            let Some(s) = status.get(&address) else {
+                return Err(Error::NoSuchOutlet {
+                    module: address.module,
+                    number: address.number,
+                });
+            };
+            let mut refusals = Vec::new();
+
+            if turns_off(command)
+                && !override_protection
+                && self.protected.contains(&address)
+            {
+                refusals.push(Refusal::Protected);
+            }
+
+            /*
+             * Only an outlet that is off adds to the load when turned on.  A
+             * reboot of an outlet that is on leaves the load as it was, but
+             * an outlet that is off is left on by a reboot, so that adds to
+             * the load too.
+             */
+            if turns_on(command) && s.state == State::Off {
+                let Some(p) = props.get(&s.index) else {
+                    return Err(Error::NoSuchOutlet {
+                        module: address.module,
+                        number: address.number,
+                    });
+                };
+                turning_on
+                    .entry((address.module, p.bank))
+                    .or_default()
+                    .push(assessed.len());
+            }
+
+            assessed.push(Assessed {
+                address,
+                index: s.index,
+                command,
+                state: s.state,
+                refusals,
+            });
+        }
+
+        let mut banks = BTreeMap::new();
+        for ((module, bank), outlets) in turning_on {
+            let key = (module, bank);
+            let (Some(config), Some(props), Some(status)) = (
+                bank_config.get(&key),
+                bank_props.get(&key),
+                bank_status.get(&key),
+            ) else {
+                return Err(Error::NoSuchBank { module, number: bank });
+            };
+
+            let (limit, stop) = match self.limit {
+                LoadLimit::NearOverload => (
+                    config.near_overload_current_threshold,
+                    Some(LoadState::NearOverload),
+                ),
+                LoadLimit::Overload => (
+                    config.overload_current_threshold,
+                    Some(LoadState::Overload),
+                ),
+                LoadLimit::BreakerRating => (props.breaker_rating, None),
+            };
+            let limit = limit.min(props.breaker_rating).saturating_mul(10);
+            let current = u32::try_from(status.current.raw()).unwrap_or(0);
+            let projected = current.saturating_add(
+                self.outlet_current.saturating_mul(outlets.len() as u32),
+            );
+
+            let mut refusals = Vec::new();
+            if stop.is_some_and(|stop| at_least(status.load_state, stop)) {
+                refusals.push(Refusal::BankLoadState {
+                    module,
+                    bank,
+                    state: status.load_state,
+                });
+            }
+            if projected > limit {
+                refusals.push(Refusal::BankBudget {
+                    module,
+                    bank,
+                    projected,
+                    limit,
+                });
+            }
+            for i in outlets {
+                assessed[i].refusals.extend(refusals.iter().cloned());
+            }
+
+            banks.insert(
+                key,
+                BankLoad {
+                    state: status.load_state,
+                    current,
+                    projected,
+                    limit,
+                },
+            );
+        }
+
+        Ok(Assessment { commands: assessed, banks })
+    }
+
+    /**
+     * Assess the commands and, if the policy allows every one of them and
+     * this is not a dry run, send them with Pdu::send_commands().  If any
+     * command is refused, none are sent, and Error::PolicyRefused describes
+     * why; a dry run instead returns the assessment either way.
+     */
+    pub async fn send_commands(
+        &self,
+        snmp: &Client,
+        commands: impl IntoIterator<Item = (OutletAddress, OutletCommand)>,
+        options: &GuardOptions,
+    ) -> Result<Guarded> {
+        let assessment =
+            self.assess(snmp, commands, options.override_protection).await?;
+
+        if options.dry_run {
+            return Ok(Guarded { assessment, report: None });
+        }
+        if !assessment.is_allowed() {
+            return Err(Error::PolicyRefused { reasons: assessment.reasons() });
+        }
+
+        let report = Pdu::send_commands(
+            snmp,
+            assessment.commands.iter().map(|c| (c.index, c.command)),
+            &options.batch,
+        )
+        .await?;
+        Ok(Guarded { assessment, report: Some(report) })
+    }
+}
+
+fn turns_off(command: OutletCommand) -> bool {
+    matches!(
+        command,
+        OutletCommand::ImmediateOff
+            | OutletCommand::ImmediateReboot
+            | OutletCommand::DelayedOff
+            | OutletCommand::DelayedReboot
+    )
+}
+
+fn turns_on(command: OutletCommand) -> bool {
+    matches!(
+        command,
+        OutletCommand::ImmediateOn
+            | OutletCommand::ImmediateReboot
+            | OutletCommand::DelayedOn
+            | OutletCommand::DelayedReboot
+    )
+}
+
+fn at_least(state: LoadState, stop: LoadState) -> bool {
+    state as i32 >= stop as i32
+}
@@ -0,0 +1,498 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * Bring the configuration of a PDU into line with a desired state, kept
+ * somewhere like version control.  The desired state is read with serde, so
+ * it may be written in TOML, JSON, or any other format with a serde
+ * implementation.  For example, in TOML:
+ *
+ * ```toml
+ * [system]
+ * name = "rack1-pdu"
+ * location = "rack1"
+ *
+ * [[outlets]]
+ * number = 1
+ * name = "web"
+ * power_on_time = 0
+ *
+ * [[banks]]
+ * number = 1
+ * near_overload_current_threshold = 12
+ * ```
+ *
+ * Anything the desired state does not mention is left alone.  Outlets and
+ * banks are on module 1 unless a module is given.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+
+use crate::mib::apc::{
+    BankConfigChange, Group, OutletAddress, OutletConfigChange, Pdu,
+};
+use crate::mib::mib_2::{System, SystemChange};
+use crate::{Client, Error, Result};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesiredState {
+    pub system: DesiredSystem,
+    pub outlets: Vec<DesiredOutlet>,
+    pub banks: Vec<DesiredBank>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesiredSystem {
+    pub name: Option<String>,
+    pub contact: Option<String>,
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesiredOutlet {
+    #[serde(default = "host_module")]
+    pub module: u32,
+    pub number: u32,
+    pub name: Option<String>,
+    pub power_on_time: Option<i32>,
+    pub power_off_time: Option<i32>,
+    pub reboot_duration: Option<u32>,
+    pub external_link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesiredBank {
+    #[serde(default = "host_module")]
+    pub module: u32,
+    pub number: u32,
+    pub low_load_current_threshold: Option<u32>,
+    pub near_overload_current_threshold: Option<u32>,
+    pub overload_current_threshold: Option<u32>,
+}
+
+fn host_module() -> u32 {
+    1
+}
+
+/**
+ * The thing a change applies to.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    System,
+    Outlet(OutletAddress),
+    Bank { module: u32, number: u32 },
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::System => write!(f, "system"),
+            Target::Outlet(a) => a.fmt(f),
+            Target::Bank { module, number } => {
+                write!(f, "module {module} bank {number}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    SysName,
+    SysContact,
+    SysLocation,
+    OutletName,
+    PowerOnTime,
+    PowerOffTime,
+    RebootDuration,
+    ExternalLink,
+    LowLoadCurrentThreshold,
+    NearOverloadCurrentThreshold,
+    OverloadCurrentThreshold,
+}
+
+impl Field {
+    /**
+     * The name of the field in the desired state document.
+     */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::SysName | Field::OutletName => "name",
+            Field::SysContact => "contact",
+            Field::SysLocation => "location",
+            Field::PowerOnTime => "power_on_time",
+            Field::PowerOffTime => "power_off_time",
+            Field::RebootDuration => "reboot_duration",
+            Field::ExternalLink => "external_link",
+            Field::LowLoadCurrentThreshold => "low_load_current_threshold",
+            Field::NearOverloadCurrentThreshold => {
+                "near_overload_current_threshold"
+            }
+            Field::OverloadCurrentThreshold => "overload_current_threshold",
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    Text(String),
+    Number(i64),
+}
+
+impl std::fmt::Display for Setting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Setting::Text(s) => write!(f, "{s:?}"),
+            Setting::Number(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+/**
+ * One value that differs between the device and the desired state.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub target: Target,
+    pub field: Field,
+    pub current: Setting,
+    pub desired: Setting,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {} -> {}",
+            self.target, self.field, self.current, self.desired
+        )
+    }
+}
+
+/**
+ * The changes needed to bring a device into the desired state, in the order
+ * they appear in the desired state.  A plan records the table index of each
+ * outlet and bank it changes, so it should be applied to the device it was
+ * made from, and before anything else reconfigures that device.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    changes: Vec<Change>,
+    system: SystemChange,
+    outlets: BTreeMap<OutletAddress, (u32, OutletConfigChange)>,
+    banks: BTreeMap<(u32, u32), (u32, BankConfigChange)>,
+}
+
+impl Plan {
+    /**
+     * Work out the changes needed, given the current system group and the
+     * outlet configuration, bank configuration and bank properties tables of
+     * the PDU (only those tables the desired state refers to are needed).
+     * Every desired value is checked as it would be when making the change,
+     * including that the bank thresholds will be in order and within the
+     * breaker rating, so that a plan which can be made is unlikely to be
+     * refused when applied.
+     */
+    pub fn new(
+        desired: &DesiredState,
+        system: &System,
+        pdu: &Pdu,
+    ) -> Result<Plan> {
+        let mut plan = Plan::default();
+
+        let ds = &desired.system;
+        SystemChange {
+            name: ds.name.clone(),
+            contact: ds.contact.clone(),
+            location: ds.location.clone(),
+        }
+        .validate()?;
+        let t = Target::System;
+        plan.system = SystemChange {
+            name: plan.text(t, Field::SysName, system.name(), &ds.name),
+            contact: plan.text(
+                t,
+                Field::SysContact,
+                system.contact(),
+                &ds.contact,
+            ),
+            location: plan.text(
+                t,
+                Field::SysLocation,
+                system.location(),
+                &ds.location,
+            ),
+        };
+
+        if !desired.outlets.is_empty() {
+            let config = pdu
+                .outlet_config()?
+                .into_values()
+                .map(|c| (OutletAddress::new(c.module, c.number), c))
+                .collect::<BTreeMap<_, _>>();
+            let mut seen = BTreeSet::new();
+
+            for d in &desired.outlets {
+                let address = OutletAddress::new(d.module, d.number);
+                let Some(c) = config.get(&address) else {
+                    return Err(Error::NoSuchOutlet {
+                        module: d.module,
+                        number: d.number,
+                    });
+                };
+                if !seen.insert(address) {
+                    return Err(duplicate("outlets", Target::Outlet(address)));
+                }
+                OutletConfigChange {
+                    name: d.name.clone(),
+                    power_on_time: d.power_on_time,
+                    power_off_time: d.power_off_time,
+                    reboot_duration: d.reboot_duration,
+                    external_link: d.external_link.clone(),
+                }
+                .validate()?;
+
+                let t = Target::Outlet(address);
+                let change = OutletConfigChange {
+                    name: plan.text(t, Field::OutletName, &c.name, &d.name),
+                    power_on_time: plan.number(
+                        t,
+                        Field::PowerOnTime,
+                        c.power_on_time,
+                        d.power_on_time,
+                    ),
+                    power_off_time: plan.number(
+                        t,
+                        Field::PowerOffTime,
+                        c.power_off_time,
+                        d.power_off_time,
+                    ),
+                    reboot_duration: plan.number(
+                        t,
+                        Field::RebootDuration,
+                        c.reboot_duration,
+                        d.reboot_duration,
+                    ),
+                    external_link: plan.text(
+                        t,
+                        Field::ExternalLink,
+                        &c.external_link,
+                        &d.external_link,
+                    ),
+                };
+                if !change.is_empty() {
+                    plan.outlets.insert(address, (c.index, change));
+                }
+            }
+        }
+
+        if !desired.banks.is_empty() {
+            let config = pdu
+                .bank_config()?
+                .into_values()
+                .map(|c| ((c.module, c.number), c))
+                .collect::<BTreeMap<_, _>>();
+            let props = pdu
+                .bank_props()?
+                .into_values()
+                .map(|p| ((p.module, p.number), p))
+                .collect::<BTreeMap<_, _>>();
+            let mut seen = BTreeSet::new();
+
+            for d in &desired.banks {
+                let bank = (d.module, d.number);
+                let (Some(c), Some(p)) = (config.get(&bank), props.get(&bank))
+                else {
+                    return Err(Error::NoSuchBank {
+                        module: d.module,
+                        number: d.number,
+                    });
+                };
+                let t = Target::Bank { module: d.module, number: d.number };
+                if !seen.insert(bank) {
+                    return Err(duplicate("banks", t));
+                }
+                let desired = BankConfigChange {
+                    low_load_current_threshold: d.low_load_current_threshold,
+                    near_overload_current_threshold: d
+                        .near_overload_current_threshold,
+                    overload_current_threshold: d.overload_current_threshold,
+                    ..Default::default()
+                };
+                desired.validate()?;
+                desired.check_thresholds(
+                    [
+                        c.low_load_current_threshold.into(),
+                        c.near_overload_current_threshold.into(),
+                        c.overload_current_threshold.into(),
+                    ],
+                    p.breaker_rating.into(),
+                )?;
+
+                let change = BankConfigChange {
+                    low_load_current_threshold: plan.number(
+                        t,
+                        Field::LowLoadCurrentThreshold,
+                        c.low_load_current_threshold,
+                        d.low_load_current_threshold,
+                    ),
+                    near_overload_current_threshold: plan.number(
+                        t,
+                        Field::NearOverloadCurrentThreshold,
+                        c.near_overload_current_threshold,
+                        d.near_overload_current_threshold,
+                    ),
+                    overload_current_threshold: plan.number(
+                        t,
+                        Field::OverloadCurrentThreshold,
+                        c.overload_current_threshold,
+                        d.overload_current_threshold,
+                    ),
+                    ..Default::default()
+                };
+                if change != BankConfigChange::default() {
+                    plan.banks.insert(bank, (c.index, change));
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /**
+     * Fetch the current state of the device and work out the changes needed.
+     * Only the tables the desired state refers to are fetched.
+     */
+    pub async fn fetch(snmp: &Client, desired: &DesiredState) -> Result<Plan> {
+        let system = System::from_client(snmp).await?;
+
+        let mut groups = Vec::new();
+        if !desired.outlets.is_empty() {
+            groups.push(Group::OutletConfig);
+        }
+        if !desired.banks.is_empty() {
+            groups.extend([Group::BankConfig, Group::BankProperties]);
+        }
+        let pdu = Pdu::fetch(snmp, &groups).await?;
+
+        Plan::new(desired, &system, &pdu)
+    }
+
+    /**
+     * Make the changes in the plan.  The changes to each target are made in
+     * one request, so each target is either changed completely or not at
+     * all; if a request fails, the targets before it will have been changed
+     * and those after it will not.
+     */
+    pub async fn apply(&self, snmp: &Client) -> Result<()> {
+        if !self.system.is_empty() {
+            System::configure(snmp, &self.system).await?;
+        }
+        for (index, change) in self.outlets.values() {
+            Pdu::configure_outlet(snmp, *index, change).await?;
+        }
+        for (index, change) in self.banks.values() {
+            Pdu::configure_bank(snmp, *index, change).await?;
+        }
+        Ok(())
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /**
+     * Record a change to a string if the desired value differs from the
+     * current one, returning the value to set.
+     */
+    fn text(
+        &mut self,
+        target: Target,
+        field: Field,
+        current: &str,
+        desired: &Option<String>,
+    ) -> Option<String> {
+        let desired = desired.as_ref().filter(|d| *d != current)?;
+        self.changes.push(Change {
+            target,
+            field,
+            current: Setting::Text(current.to_string()),
+            desired: Setting::Text(desired.clone()),
+        });
+        Some(desired.clone())
+    }
+
+    /**
+     * Record a change to a number if the desired value differs from the
+     * current one, returning the value to set.
+     */
+    fn number<T>(
+        &mut self,
+        target: Target,
+        field: Field,
+        current: T,
+        desired: Option<T>,
+    ) -> Option<T>
+    where
+        T: Copy + PartialEq + Into<i64>,
+    {
+        let desired = desired.filter(|d| *d != current)?;
+        self.changes.push(Change {
+            target,
+            field,
+            current: Setting::Number(current.into()),
+            desired: Setting::Number(desired.into()),
+        });
+        Some(desired)
+    }
+}
+
+fn duplicate(name: &str, target: Target) -> Error {
+    Error::InvalidSetting {
+        name: name.to_string(),
+        message: format!("{target} appears more than once"),
+    }
+}
+
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in &self.changes {
+            writeln!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Work out the changes needed to bring the device into the desired state
+ * and, unless this is a dry run, make them.  The plan is returned either
+ * way, so that the changes can be reported.
+ */
+pub async fn reconcile(
+    snmp: &Client,
+    desired: &DesiredState,
+    dry_run: bool,
+) -> Result<Plan> {
+    let plan = Plan::fetch(snmp, desired).await?;
+    if !dry_run {
+        plan.apply(snmp).await?;
+    }
+    Ok(plan)
+}
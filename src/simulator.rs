@@ -0,0 +1,659 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * An in-process SNMPv2c agent that answers GET, GETNEXT, GETBULK and SET
+ * requests from an in-memory store of values.  The store is usually seeded from
+ * a recorded walk (see the "capture" module), so that clients can be exercised
+ * end to end without access to real hardware.
+ *
+ * Device behaviour, such as an outlet changing state some time after a command
+ * is written, is modelled with SET hooks.  Misbehaving agents are modelled by
+ * injecting faults that apply to subsequent requests.
+ */
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use csnmp::{
+    message::{
+        BindingValue, ErrorStatus, InnerPdu, Snmp2cMessage, Snmp2cPdu,
+        VariableBinding, VERSION_VALUE,
+    },
+    ObjectValue,
+};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::{
+    mib::apc::{CommandPending, DeviceCommand, OutletCommand, State},
+    oidtree::OidTree,
+    value::Value,
+    walk::WalkedValues,
+    Error, Oid, Result,
+};
+
+/**
+ * A fault to inject into the handling of a single request.  Faults are queued
+ * with Simulator::inject() and consumed in order, one per request received.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /**
+     * Do not respond at all, so that the client times out.
+     */
+    Drop,
+    /**
+     * Wait before responding normally.
+     */
+    Delay(Duration),
+    /**
+     * Respond with an error status and index (counting varbinds from 1), and
+     * with the request varbinds echoed back unchanged.
+     */
+    Error(ErrorStatus, u32),
+    /**
+     * Respond with a datagram that does not decode as an SNMP message.
+     */
+    Malformed,
+}
+
+/**
+ * A hook is called for each varbind in a SET request that passes the basic
+ * checks (the object exists and the new value has the same type as the old
+ * one), before any of the values in the request are stored.  A hook may reject
+ * the SET by returning an error status, or model the side effects of the write
+ * through the provided Agent handle.
+ */
+pub type SetHook =
+    Box<dyn Fn(&Agent, Oid, &Value) -> SResult<(), ErrorStatus> + Send + Sync>;
+
+type SResult<T, E> = std::result::Result<T, E>;
+
+struct Inner {
+    values: Mutex<BTreeMap<Oid, Value>>,
+    faults: Mutex<VecDeque<Fault>>,
+    hooks: Vec<SetHook>,
+    community: Vec<u8>,
+    max_bindings: Option<usize>,
+}
+
+/**
+ * A cheaply cloned handle to the state of a running simulator, used by SET
+ * hooks and tests to inspect and modify the store.
+ */
+#[derive(Clone)]
+pub struct Agent(Arc<Inner>);
+
+impl Agent {
+    pub fn get(&self, oid: Oid) -> Option<Value> {
+        self.0.values.lock().unwrap().get(&oid).cloned()
+    }
+
+    pub fn set(&self, oid: Oid, value: Value) {
+        self.0.values.lock().unwrap().insert(oid, value);
+    }
+
+    pub fn remove(&self, oid: Oid) -> Option<Value> {
+        self.0.values.lock().unwrap().remove(&oid)
+    }
+
+    /**
+     * Store a value once the delay has elapsed, without blocking the caller.
+     * Must be called from within a tokio runtime, which is always the case for
+     * SET hooks.
+     */
+    pub fn set_after(&self, delay: Duration, oid: Oid, value: Value) {
+        let agent = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            agent.set(oid, value);
+        });
+    }
+
+    /**
+     * Take a snapshot of the entire store.
+     */
+    pub fn values(&self) -> BTreeMap<Oid, Value> {
+        self.0.values.lock().unwrap().clone()
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.0.faults.lock().unwrap().push_back(fault);
+    }
+
+    fn next_fault(&self) -> Option<Fault> {
+        self.0.faults.lock().unwrap().pop_front()
+    }
+
+    fn get_one(&self, values: &BTreeMap<Oid, Value>, oid: Oid) -> BindingValue {
+        if let Some(v) = values.get(&oid) {
+            return BindingValue::Value(v.0.clone());
+        }
+
+        /*
+         * If any instance of the object exists, report a missing instance
+         * rather than a missing object.
+         */
+        match oid.parent() {
+            Some(parent)
+                if values.range(Oid(parent)..).next().is_some_and(
+                    |(k, _)| parent.is_prefix_of_or_equal(&k.0),
+                ) =>
+            {
+                BindingValue::NoSuchInstance
+            }
+            _ => BindingValue::NoSuchObject,
+        }
+    }
+
+    fn get_next(
+        &self,
+        values: &BTreeMap<Oid, Value>,
+        oid: Oid,
+    ) -> VariableBinding {
+        use std::ops::Bound;
+
+        match values.range((Bound::Excluded(oid), Bound::Unbounded)).next() {
+            Some((k, v)) => VariableBinding {
+                name: k.0,
+                value: BindingValue::Value(v.0.clone()),
+            },
+            None => VariableBinding {
+                name: oid.0,
+                value: BindingValue::EndOfMibView,
+            },
+        }
+    }
+
+    fn respond(&self, pdu: &Snmp2cPdu) -> Option<InnerPdu> {
+        let resp = match pdu {
+            Snmp2cPdu::GetRequest(req) => {
+                let values = self.0.values.lock().unwrap();
+                InnerPdu {
+                    variable_bindings: req
+                        .variable_bindings
+                        .iter()
+                        .map(|vb| VariableBinding {
+                            name: vb.name,
+                            value: self.get_one(&values, Oid(vb.name)),
+                        })
+                        .collect(),
+                    ..req.clone()
+                }
+            }
+            Snmp2cPdu::GetNextRequest(req) => {
+                let values = self.0.values.lock().unwrap();
+                InnerPdu {
+                    variable_bindings: req
+                        .variable_bindings
+                        .iter()
+                        .map(|vb| self.get_next(&values, Oid(vb.name)))
+                        .collect(),
+                    ..req.clone()
+                }
+            }
+            Snmp2cPdu::GetBulkRequest(req) => {
+                let values = self.0.values.lock().unwrap();
+                let nr = (req.non_repeaters as usize)
+                    .min(req.variable_bindings.len());
+                let (nonrep, rep) = req.variable_bindings.split_at(nr);
+
+                let mut out: Vec<VariableBinding> = nonrep
+                    .iter()
+                    .map(|vb| self.get_next(&values, Oid(vb.name)))
+                    .collect();
+
+                let mut last: Vec<Oid> =
+                    rep.iter().map(|vb| Oid(vb.name)).collect();
+                for _ in 0..req.max_repetitions {
+                    if last.is_empty() {
+                        break;
+                    }
+                    let mut done = true;
+                    for oid in last.iter_mut() {
+                        let vb = self.get_next(&values, *oid);
+                        if !matches!(vb.value, BindingValue::EndOfMibView) {
+                            done = false;
+                        }
+                        *oid = Oid(vb.name);
+                        out.push(vb);
+                    }
+                    if done {
+                        break;
+                    }
+                }
+
+                InnerPdu {
+                    request_id: req.request_id,
+                    error_status: ErrorStatus::NoError,
+                    error_index: 0,
+                    variable_bindings: out,
+                }
+            }
+            Snmp2cPdu::SetRequest(req) => self.set_request(req),
+            _ => return None,
+        };
+
+        match (self.0.max_bindings, pdu) {
+            (Some(max), Snmp2cPdu::GetBulkRequest(req))
+                if resp.variable_bindings.len() > max =>
+            {
+                Some(InnerPdu {
+                    error_status: ErrorStatus::TooBig,
+                    error_index: 0,
+                    variable_bindings: req.variable_bindings.clone(),
+                    ..resp
+                })
+            }
+            _ => Some(resp),
+        }
+    }
+
+    fn set_request(&self, req: &InnerPdu) -> InnerPdu {
+        let fail = |status, index: usize| InnerPdu {
+            request_id: req.request_id,
+            error_status: status,
+            error_index: index as u32,
+            variable_bindings: req.variable_bindings.clone(),
+        };
+
+        let mut new = Vec::with_capacity(req.variable_bindings.len());
+        {
+            let values = self.0.values.lock().unwrap();
+            for (i, vb) in req.variable_bindings.iter().enumerate() {
+                let BindingValue::Value(val) = &vb.value else {
+                    return fail(ErrorStatus::WrongType, i + 1);
+                };
+                let Some(old) = values.get(&Oid(vb.name)) else {
+                    return fail(ErrorStatus::NotWritable, i + 1);
+                };
+                if std::mem::discriminant(&old.0) != std::mem::discriminant(val)
+                {
+                    return fail(ErrorStatus::WrongType, i + 1);
+                }
+                new.push((Oid(vb.name), Value(val.clone())));
+            }
+        }
+
+        /*
+         * Hooks are called without holding the store lock, so that they may
+         * use the agent handle freely.
+         */
+        for (i, (oid, val)) in new.iter().enumerate() {
+            for hook in self.0.hooks.iter() {
+                if let Err(status) = hook(self, *oid, val) {
+                    return fail(status, i + 1);
+                }
+            }
+        }
+
+        let mut values = self.0.values.lock().unwrap();
+        for (oid, val) in new {
+            values.insert(oid, val);
+        }
+
+        InnerPdu {
+            error_status: ErrorStatus::NoError,
+            error_index: 0,
+            ..req.clone()
+        }
+    }
+}
+
+pub struct SimulatorBuilder {
+    bind_address: SocketAddr,
+    community: Vec<u8>,
+    values: BTreeMap<Oid, Value>,
+    hooks: Vec<SetHook>,
+    max_bindings: Option<usize>,
+}
+
+impl SimulatorBuilder {
+    /**
+     * The address on which to listen; by default, an ephemeral port on the
+     * IPv4 loopback address.
+     */
+    pub fn bind_address(&mut self, addr: SocketAddr) -> &mut Self {
+        self.bind_address = addr;
+        self
+    }
+
+    /**
+     * Requests with any other community are silently ignored.
+     */
+    pub fn community<C: AsRef<[u8]>>(&mut self, community: C) -> &mut Self {
+        self.community = community.as_ref().to_vec();
+        self
+    }
+
+    pub fn value(&mut self, oid: Oid, value: Value) -> &mut Self {
+        self.values.insert(oid, value);
+        self
+    }
+
+    /**
+     * Seed the store with the values from a walk, whether of a live device or
+     * loaded from a capture.
+     */
+    pub fn walk(&mut self, walk: &WalkedValues) -> &mut Self {
+        self.values.extend(walk.iter().map(|(k, v)| (*k, v.clone())));
+        self
+    }
+
+    /**
+     * Seed the store from a capture in "snmpwalk" format.
+     */
+    pub fn capture(
+        &mut self,
+        input: &str,
+        tree: &OidTree,
+    ) -> Result<&mut Self> {
+        self.values.extend(crate::capture::parse_snmpwalk(input, tree)?);
+        Ok(self)
+    }
+
+    /**
+     * Respond to any GETBULK request that would produce more than this many
+     * variable bindings with tooBig, as an agent with a small maximum message
+     * size would.
+     */
+    pub fn max_bindings(&mut self, max: usize) -> &mut Self {
+        self.max_bindings = Some(max);
+        self
+    }
+
+    pub fn on_set(&mut self, hook: SetHook) -> &mut Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /**
+     * Bind the socket and begin answering requests in a background task.  The
+     * seeded values and hooks are moved out of the builder into the running
+     * simulator.
+     */
+    pub async fn start(&mut self) -> Result<Simulator> {
+        let target = self.bind_address;
+        let sock = UdpSocket::bind(target)
+            .await
+            .map_err(|source| Error::Socket { target, source })?;
+        let addr = sock
+            .local_addr()
+            .map_err(|source| Error::Socket { target, source })?;
+
+        let agent = Agent(Arc::new(Inner {
+            values: Mutex::new(std::mem::take(&mut self.values)),
+            faults: Default::default(),
+            hooks: std::mem::take(&mut self.hooks),
+            community: self.community.clone(),
+            max_bindings: self.max_bindings,
+        }));
+
+        let task = tokio::spawn(serve(sock, agent.clone()));
+
+        Ok(Simulator { addr, agent, task })
+    }
+}
+
+/**
+ * A running simulated agent.  The agent stops when this object is dropped.
+ */
+pub struct Simulator {
+    addr: SocketAddr,
+    agent: Agent,
+    task: JoinHandle<()>,
+}
+
+impl Simulator {
+    pub fn builder() -> SimulatorBuilder {
+        SimulatorBuilder {
+            bind_address: SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+            community: b"public".to_vec(),
+            values: Default::default(),
+            hooks: Default::default(),
+            max_bindings: None,
+        }
+    }
+
+    /**
+     * The address on which the simulator is listening, for use with
+     * ClientBuilder::port() and ClientBuilder::build().
+     */
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    pub fn get(&self, oid: Oid) -> Option<Value> {
+        self.agent.get(oid)
+    }
+
+    pub fn set(&self, oid: Oid, value: Value) {
+        self.agent.set(oid, value)
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.agent.inject(fault)
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(sock: UdpSocket, agent: Agent) {
+    let mut buf = vec![0u8; 65536];
+
+    loop {
+        let Ok((len, peer)) = sock.recv_from(&mut buf).await else {
+            continue;
+        };
+
+        let Ok(msg) = Snmp2cMessage::try_from_bytes(&buf[..len]) else {
+            continue;
+        };
+        if msg.community != agent.0.community {
+            continue;
+        }
+
+        let fault = agent.next_fault();
+        let Some(mut resp) = (match &fault {
+            None | Some(Fault::Delay(_)) => agent.respond(&msg.pdu),
+            Some(Fault::Drop) => continue,
+            Some(Fault::Malformed) => {
+                sock.send_to(b"\x30\x03\x02\x01", peer).await.ok();
+                continue;
+            }
+            Some(Fault::Error(..)) => match &msg.pdu {
+                Snmp2cPdu::GetRequest(req)
+                | Snmp2cPdu::GetNextRequest(req)
+                | Snmp2cPdu::SetRequest(req) => Some(req.clone()),
+                Snmp2cPdu::GetBulkRequest(req) => Some(InnerPdu {
+                    request_id: req.request_id,
+                    error_status: ErrorStatus::NoError,
+                    error_index: 0,
+                    variable_bindings: req.variable_bindings.clone(),
+                }),
+                _ => None,
+            },
+        }) else {
+            continue;
+        };
+
+        match fault {
+            Some(Fault::Delay(d)) => tokio::time::sleep(d).await,
+            Some(Fault::Error(status, index)) => {
+                resp.error_status = status;
+                resp.error_index = index;
+            }
+            _ => (),
+        }
+
+        let out = Snmp2cMessage {
+            version: VERSION_VALUE,
+            community: msg.community,
+            pdu: Snmp2cPdu::Response(resp),
+        };
+        if let Ok(bytes) = out.to_bytes() {
+            sock.send_to(&bytes, peer).await.ok();
+        }
+    }
+}
+
+/**
+ * A SET hook that models the outlet commands of an APC rPDU2 device: writing
+ * rPDU2OutletSwitchedControlCommand marks the command as pending, and once the
+ * delay has elapsed the outlet state is updated and the command is no longer
+ * pending.  A reboot turns the outlet off, then on again after a further delay.
+ * Writing rPDU2DeviceControlCommand does the same for every outlet of the
+ * device's module.
+ */
+pub fn apc_outlet_commands(tree: &OidTree, delay: Duration) -> Result<SetHook> {
+    let top = tree.oid_by_name(
+        "internet.private.enterprises.apc.products.hardware.rPDU2",
+    )?;
+    let ctl = tree.oid_by_name_under(
+        top,
+        "rPDU2Outlet.rPDU2OutletSwitched.\
+        rPDU2OutletSwitchedControlTable.rPDU2OutletSwitchedControlEntry",
+    )?;
+    let status = tree.oid_by_name_under(
+        top,
+        "rPDU2Outlet.rPDU2OutletSwitched.\
+        rPDU2OutletSwitchedStatusTable.rPDU2OutletSwitchedStatusEntry",
+    )?;
+    let device = tree.oid_by_name_under(
+        top,
+        "rPDU2Device.rPDU2DeviceControlTable.rPDU2DeviceControlEntry",
+    )?;
+    let command =
+        tree.oid_by_name_under(ctl, "rPDU2OutletSwitchedControlCommand")?;
+    let state =
+        tree.oid_by_name_under(status, "rPDU2OutletSwitchedStatusState")?;
+    let pending = tree
+        .oid_by_name_under(status, "rPDU2OutletSwitchedStatusCommandPending")?;
+    let outlet_module =
+        tree.oid_by_name_under(status, "rPDU2OutletSwitchedStatusModule")?;
+    let device_command =
+        tree.oid_by_name_under(device, "rPDU2DeviceControlCommand")?;
+    let device_module =
+        tree.oid_by_name_under(device, "rPDU2DeviceControlModule")?;
+
+    let int = |i: i32| Value(ObjectValue::Integer(i));
+    let row = |oid: Oid, column: Oid| match oid.relative_to(column)?.as_slice()
+    {
+        &[index] => Some(index),
+        _ => None,
+    };
+
+    let outlet = move |agent: &Agent, index: u32, cmd: i32| {
+        let child = |oid: Oid| Oid(oid.child(index).unwrap());
+        let (state, pending, command) =
+            (child(state), child(pending), child(command));
+        if agent.get(state).is_none() {
+            return Err(ErrorStatus::NoCreation);
+        }
+
+        let target = match cmd {
+            c if c == OutletCommand::ImmediateOn as i32
+                || c == OutletCommand::DelayedOn as i32 =>
+            {
+                vec![State::On]
+            }
+            c if c == OutletCommand::ImmediateOff as i32
+                || c == OutletCommand::DelayedOff as i32 =>
+            {
+                vec![State::Off]
+            }
+            c if c == OutletCommand::ImmediateReboot as i32
+                || c == OutletCommand::DelayedReboot as i32 =>
+            {
+                vec![State::Off, State::On]
+            }
+            c if c == OutletCommand::CancelPendingCommand as i32 => {
+                agent.set(pending, int(CommandPending::No as i32));
+                return Ok(());
+            }
+            _ => return Err(ErrorStatus::WrongValue),
+        };
+
+        agent.set(pending, int(CommandPending::Yes as i32));
+        let agent = agent.clone();
+        tokio::spawn(async move {
+            for s in target {
+                tokio::time::sleep(delay).await;
+                agent.set(state, int(s as i32));
+            }
+            let last = match agent.get(state).map(|v| v.0) {
+                Some(ObjectValue::Integer(s)) if s == State::On as i32 => {
+                    OutletCommand::ImmediateOn
+                }
+                _ => OutletCommand::ImmediateOff,
+            };
+            agent.set(command, int(last as i32));
+            agent.set(pending, int(CommandPending::No as i32));
+        });
+
+        Ok(())
+    };
+
+    Ok(Box::new(move |agent, oid, val| {
+        if let Some(index) = row(oid, command) {
+            let ObjectValue::Integer(cmd) = val.0 else {
+                return Err(ErrorStatus::WrongType);
+            };
+            return outlet(agent, index, cmd);
+        }
+
+        let Some(index) = row(oid, device_command) else {
+            return Ok(());
+        };
+        let ObjectValue::Integer(cmd) = val.0 else {
+            return Err(ErrorStatus::WrongType);
+        };
+
+        /*
+         * Each device command but the last corresponds to an outlet command,
+         * though they are not numbered alike.
+         */
+        let cmd = match cmd {
+            c if c == DeviceCommand::NoCommand as i32 => return Ok(()),
+            c if (DeviceCommand::ImmediateAllOn as i32
+                ..=DeviceCommand::CancelAllPendingCommands as i32)
+                .contains(&c) =>
+            {
+                [
+                    OutletCommand::ImmediateOn,
+                    OutletCommand::ImmediateOff,
+                    OutletCommand::ImmediateReboot,
+                    OutletCommand::DelayedOn,
+                    OutletCommand::DelayedOff,
+                    OutletCommand::DelayedReboot,
+                    OutletCommand::CancelPendingCommand,
+                ][c as usize - 1]
+            }
+            _ => return Err(ErrorStatus::WrongValue),
+        };
+
+        let module = agent.get(Oid(device_module.child(index).unwrap()));
+        for (oid, m) in agent.values() {
+            if let Some(i) = row(oid, outlet_module) {
+                if module.as_ref().is_none_or(|module| *module == m) {
+                    outlet(agent, i, cmd as i32)?;
+                }
+            }
+        }
+
+        Ok(())
+    }))
+}
@@ -0,0 +1,391 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * Types for the common textual conventions of SNMPv2-TC and
+ * INET-ADDRESS-MIB, which can be used as fields in structures extracted from
+ * walked values so that each MIB module need not decode them by hand.
+ *
+ * Some conventions need no type of their own: a TruthValue deserialises as a
+ * bool, and an IpAddress as a std::net::Ipv4Addr (or IpAddr).
+ */
+
+use std::fmt::{self, Display};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use csnmp::ObjectValue;
+use serde::de::{Error as _, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_repr::Deserialize_repr;
+
+use crate::value::Value;
+
+/**
+ * The octets of an OCTET STRING, such as a PhysAddress, that is not expected
+ * to be text.
+ */
+struct OctetsVisitor(&'static str);
+
+impl Visitor<'_> for OctetsVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(
+        self,
+        v: &[u8],
+    ) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+}
+
+fn octets<'de, D: Deserializer<'de>>(
+    d: D,
+    expecting: &'static str,
+) -> Result<Vec<u8>, D::Error> {
+    d.deserialize_bytes(OctetsVisitor(expecting))
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, octets: &[u8]) -> fmt::Result {
+    for (i, o) in octets.iter().enumerate() {
+        if i > 0 {
+            f.write_str(":")?;
+        }
+        write!(f, "{o:02x}")?;
+    }
+    Ok(())
+}
+
+/**
+ * A PhysAddress: a media-level address of any length, displayed as
+ * colon-separated hexadecimal octets.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysAddress(pub Vec<u8>);
+
+impl<'de> Deserialize<'de> for PhysAddress {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        octets(d, "a PhysAddress").map(PhysAddress)
+    }
+}
+
+impl Display for PhysAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+/**
+ * A MacAddress: an IEEE 802 address of exactly six octets.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl<'de> Deserialize<'de> for MacAddress {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let buf = octets(d, "a MacAddress of six octets")?;
+        <[u8; 6]>::try_from(buf.as_slice()).map(MacAddress).map_err(|_| {
+            D::Error::invalid_length(buf.len(), &"a MacAddress of six octets")
+        })
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+/**
+ * A DateAndTime: a calendar date and time of day, as eight octets, or as
+ * eleven octets if the offset from UTC is known.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateAndTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minutes: u8,
+    /**
+     * Up to 60, to allow for a leap second.
+     */
+    pub seconds: u8,
+    pub deci_seconds: u8,
+    /**
+     * Minutes east of UTC, if known.
+     */
+    pub utc_offset: Option<i16>,
+}
+
+impl DateAndTime {
+    fn from_octets(buf: &[u8]) -> Result<DateAndTime, String> {
+        if buf.len() != 8 && buf.len() != 11 {
+            return Err(format!("{} octets rather than 8 or 11", buf.len()));
+        }
+
+        let dt = DateAndTime {
+            year: u16::from_be_bytes([buf[0], buf[1]]),
+            month: buf[2],
+            day: buf[3],
+            hour: buf[4],
+            minutes: buf[5],
+            seconds: buf[6],
+            deci_seconds: buf[7],
+            utc_offset: match buf.get(8..11) {
+                None => None,
+                Some(&[dir, hours, minutes]) => {
+                    if hours > 14 || minutes > 59 {
+                        return Err(format!(
+                            "bad offset from UTC {hours}:{minutes}"
+                        ));
+                    }
+                    let offset = i16::from(hours) * 60 + i16::from(minutes);
+                    match dir {
+                        b'+' => Some(offset),
+                        b'-' => Some(-offset),
+                        _ => {
+                            return Err(format!(
+                                "bad direction from UTC {dir:#x}"
+                            ))
+                        }
+                    }
+                }
+                Some(_) => unreachable!(),
+            },
+        };
+
+        if !(1..=12).contains(&dt.month)
+            || !(1..=31).contains(&dt.day)
+            || dt.hour > 23
+            || dt.minutes > 59
+            || dt.seconds > 60
+            || dt.deci_seconds > 9
+        {
+            return Err(format!("out of range: {dt}"));
+        }
+        Ok(dt)
+    }
+
+    /**
+     * The point in time, which can be determined only if the offset from UTC
+     * is known.
+     */
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let offset = i64::from(self.utc_offset?) * 60;
+
+        /*
+         * Count days since the epoch with Howard Hinnant's days_from_civil()
+         * algorithm.
+         */
+        let (m, d) = (i64::from(self.month), i64::from(self.day));
+        let y = i64::from(self.year) - i64::from(m <= 2);
+        let era = y.div_euclid(400);
+        let yoe = y.rem_euclid(400);
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        let secs = days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minutes) * 60
+            + i64::from(self.seconds)
+            - offset;
+        let secs = u64::try_from(secs).ok()?;
+        Some(
+            UNIX_EPOCH
+                + Duration::from_secs(secs)
+                + Duration::from_millis(u64::from(self.deci_seconds) * 100),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for DateAndTime {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let buf = octets(d, "a DateAndTime")?;
+        DateAndTime::from_octets(&buf).map_err(|e| {
+            D::Error::invalid_value(Unexpected::Bytes(&buf), &e.as_str())
+        })
+    }
+}
+
+/**
+ * In the style of ISO 8601; e.g., "2024-03-14T15:09:26.5+10:00".
+ */
+impl Display for DateAndTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minutes,
+            self.seconds,
+            self.deci_seconds
+        )?;
+        match self.utc_offset {
+            Some(o) => {
+                let sign = if o < 0 { '-' } else { '+' };
+                let o = o.unsigned_abs();
+                write!(f, "{sign}{:02}:{:02}", o / 60, o % 60)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/**
+ * A TimeTicks value: a duration in hundredths of a second.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeTicks(pub Duration);
+
+/**
+ * A TimeStamp: the value of sysUpTime when something happened, as a duration
+ * since the management system last started.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeStamp(pub Duration);
+
+fn ticks<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let t = u32::deserialize(d)?;
+    Ok(Duration::from_millis(u64::from(t) * 10))
+}
+
+impl<'de> Deserialize<'de> for TimeTicks {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        ticks(d).map(TimeTicks)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeStamp {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        ticks(d).map(TimeStamp)
+    }
+}
+
+/**
+ * The type of address in an accompanying InetAddress.
+ */
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum InetAddressType {
+    Unknown = 0,
+    Ipv4 = 1,
+    Ipv6 = 2,
+    Ipv4z = 3,
+    Ipv6z = 4,
+    Dns = 16,
+}
+
+/**
+ * The octets of an InetAddress, which can only be interpreted along with the
+ * InetAddressType in another column.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InetAddress(pub Vec<u8>);
+
+impl<'de> Deserialize<'de> for InetAddress {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        octets(d, "an InetAddress").map(InetAddress)
+    }
+}
+
+/**
+ * An InetAddress, interpreted according to its InetAddressType.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InetAddr {
+    Unknown,
+    Ip(IpAddr),
+    /**
+     * An address qualified by the index of the zone (e.g., the interface of
+     * an IPv6 link-local address) that it belongs to.
+     */
+    Zoned(IpAddr, u32),
+    Dns(String),
+}
+
+impl InetAddress {
+    /**
+     * Interpret the octets according to the type of address.  Returns None if
+     * the length does not suit the type.
+     */
+    pub fn decode(&self, kind: InetAddressType) -> Option<InetAddr> {
+        let b = self.0.as_slice();
+        let zone = |z: &[u8]| u32::from_be_bytes(z.try_into().unwrap());
+        Some(match kind {
+            InetAddressType::Unknown if b.is_empty() => InetAddr::Unknown,
+            InetAddressType::Ipv4 => InetAddr::Ip(
+                Ipv4Addr::from(<[u8; 4]>::try_from(b).ok()?).into(),
+            ),
+            InetAddressType::Ipv6 => InetAddr::Ip(
+                Ipv6Addr::from(<[u8; 16]>::try_from(b).ok()?).into(),
+            ),
+            InetAddressType::Ipv4z if b.len() == 8 => InetAddr::Zoned(
+                Ipv4Addr::from(<[u8; 4]>::try_from(&b[..4]).unwrap()).into(),
+                zone(&b[4..]),
+            ),
+            InetAddressType::Ipv6z if b.len() == 20 => InetAddr::Zoned(
+                Ipv6Addr::from(<[u8; 16]>::try_from(&b[..16]).unwrap()).into(),
+                zone(&b[16..]),
+            ),
+            InetAddressType::Dns => {
+                InetAddr::Dns(String::from_utf8(b.to_vec()).ok()?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/**
+ * The status of a conceptual row, as used to create and delete rows.
+ */
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum RowStatus {
+    Active = 1,
+    NotInService = 2,
+    NotReady = 3,
+    CreateAndGo = 4,
+    CreateAndWait = 5,
+    Destroy = 6,
+}
+
+impl RowStatus {
+    /**
+     * The INTEGER to SET; e.g., CreateAndGo to create a row, or Destroy to
+     * delete one.
+     */
+    pub fn to_value(&self) -> Value {
+        Value(ObjectValue::Integer(*self as i32))
+    }
+}
+
+/**
+ * Where a conceptual row is kept, and so whether it survives a restart.
+ */
+#[derive(Deserialize_repr, PartialEq, Eq, Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum StorageType {
+    Other = 1,
+    Volatile = 2,
+    NonVolatile = 3,
+    Permanent = 4,
+    ReadOnly = 5,
+}
+
+impl StorageType {
+    /**
+     * The INTEGER to SET when creating a row.
+     */
+    pub fn to_value(&self) -> Value {
+        Value(ObjectValue::Integer(*self as i32))
+    }
+}
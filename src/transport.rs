@@ -0,0 +1,320 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * A minimal SNMPv2c request/response transport.  Unlike the csnmp client, this
+ * reports the error status of a response rather than ignoring it, and keeps
+ * variable binding exceptions in the results so that callers can decide what
+ * to do with them.
+ */
+
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+use csnmp::message::{
+    BindingValue, BulkPdu, ErrorStatus, InnerPdu, Snmp2cMessage, Snmp2cPdu,
+    VariableBinding, VERSION_VALUE,
+};
+use tokio::{net::UdpSocket, sync::Mutex, time::Instant};
+
+use crate::{
+    value::{Value, Varbind},
+    Error, Exception, Oid, Result,
+};
+
+pub(crate) struct Transport {
+    /*
+     * Requests are serialised on the socket, so that concurrent callers do not
+     * receive (and discard) each other's responses.
+     */
+    socket: Mutex<UdpSocket>,
+    target: SocketAddr,
+    community: Vec<u8>,
+    timeout: Duration,
+    retries: usize,
+    next_request_id: AtomicI32,
+}
+
+impl Transport {
+    pub(crate) async fn new(
+        bind: SocketAddr,
+        target: SocketAddr,
+        community: Vec<u8>,
+        timeout: Duration,
+        retries: usize,
+    ) -> Result<Transport> {
+        let socket = UdpSocket::bind(bind)
+            .await
+            .map_err(|source| Error::Socket { target, source })?;
+
+        Ok(Transport {
+            socket: Mutex::new(socket),
+            target,
+            community,
+            timeout,
+            retries,
+            next_request_id: AtomicI32::new(1),
+        })
+    }
+
+    fn protocol(&self, oid: Option<Oid>, message: String) -> Error {
+        Error::Protocol { target: self.target, oid: oid.map(Box::new), message }
+    }
+
+    /**
+     * Send a request and wait for the matching response, retrying on timeout.
+     * A response with a non-zero error status is reported as an error.
+     */
+    async fn request(
+        &self,
+        make: impl Fn(i32) -> Snmp2cPdu,
+        bindings: &[VariableBinding],
+    ) -> Result<Vec<VariableBinding>> {
+        let first = bindings.first().map(|vb| Oid(vb.name));
+        let request_id = self
+            .next_request_id
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                Some(id.checked_add(1).unwrap_or(1))
+            })
+            .unwrap();
+
+        let msg = Snmp2cMessage {
+            version: VERSION_VALUE,
+            community: self.community.clone(),
+            pdu: make(request_id),
+        };
+        let bytes =
+            msg.to_bytes().map_err(|e| self.protocol(first, e.to_string()))?;
+
+        let socket = self.socket.lock().await;
+        let mut buf = vec![0u8; 65536];
+
+        for _ in 0..=self.retries {
+            socket.send_to(&bytes, self.target).await.map_err(|source| {
+                Error::Socket { target: self.target, source }
+            })?;
+
+            let deadline = Instant::now() + self.timeout;
+            let resp = loop {
+                let Ok(res) = tokio::time::timeout_at(
+                    deadline,
+                    socket.recv_from(&mut buf),
+                )
+                .await
+                else {
+                    break None;
+                };
+                let (len, from) = res.map_err(|source| Error::Socket {
+                    target: self.target,
+                    source,
+                })?;
+                if from != self.target {
+                    continue;
+                }
+
+                let msg = Snmp2cMessage::try_from_bytes(&buf[..len]).map_err(
+                    |e| self.protocol(first, format!("undecodable: {e}")),
+                )?;
+                if msg.pdu.request_id() != request_id {
+                    /*
+                     * This is likely a late response to an earlier request
+                     * that we gave up on.
+                     */
+                    continue;
+                }
+
+                break Some(msg.pdu);
+            };
+
+            let Some(pdu) = resp else {
+                continue;
+            };
+            let Snmp2cPdu::Response(resp) = pdu else {
+                return Err(self.protocol(first, "not a response".into()));
+            };
+
+            if resp.error_status != ErrorStatus::NoError {
+                let oid = usize::try_from(resp.error_index)
+                    .ok()
+                    .and_then(|i| i.checked_sub(1))
+                    .and_then(|i| bindings.get(i))
+                    .map(|vb| Oid(vb.name));
+                return Err(Error::Agent {
+                    status: resp.error_status,
+                    index: resp.error_index,
+                    oid: oid.map(Box::new),
+                });
+            }
+
+            return Ok(resp.variable_bindings);
+        }
+
+        Err(Error::Timeout { target: self.target, oid: first.map(Box::new) })
+    }
+
+    /**
+     * Check that a response contains exactly the OIDs that were requested, in
+     * order, as is required for GET and SET.
+     */
+    fn check_names(
+        &self,
+        req: &[VariableBinding],
+        resp: &[VariableBinding],
+    ) -> Result<()> {
+        if req.len() != resp.len() {
+            return Err(self.protocol(
+                req.first().map(|vb| Oid(vb.name)),
+                format!("{} bindings for {} requested", resp.len(), req.len()),
+            ));
+        }
+        for (q, p) in req.iter().zip(resp.iter()) {
+            if q.name != p.name {
+                return Err(self.protocol(
+                    Some(Oid(q.name)),
+                    format!("response for {} instead", p.name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn get(
+        &self,
+        oids: &[Oid],
+    ) -> Result<Vec<VariableBinding>> {
+        let req = unspecified(oids);
+        let resp = self
+            .request(
+                |request_id| {
+                    Snmp2cPdu::GetRequest(inner(request_id, req.clone()))
+                },
+                &req,
+            )
+            .await?;
+        self.check_names(&req, &resp)?;
+        Ok(resp)
+    }
+
+    pub(crate) async fn get_next(
+        &self,
+        oids: &[Oid],
+    ) -> Result<Vec<VariableBinding>> {
+        let req = unspecified(oids);
+        let resp = self
+            .request(
+                |request_id| {
+                    Snmp2cPdu::GetNextRequest(inner(request_id, req.clone()))
+                },
+                &req,
+            )
+            .await?;
+        if resp.len() != req.len() {
+            return Err(self.protocol(
+                oids.first().copied(),
+                format!("{} bindings for {} requested", resp.len(), req.len()),
+            ));
+        }
+        Ok(resp)
+    }
+
+    pub(crate) async fn get_bulk(
+        &self,
+        oids: &[Oid],
+        non_repeaters: u32,
+        max_repetitions: u32,
+    ) -> Result<Vec<VariableBinding>> {
+        let req = unspecified(oids);
+        self.request(
+            |request_id| {
+                Snmp2cPdu::GetBulkRequest(BulkPdu {
+                    request_id,
+                    non_repeaters,
+                    max_repetitions,
+                    variable_bindings: req.clone(),
+                })
+            },
+            &req,
+        )
+        .await
+    }
+
+    pub(crate) async fn set(
+        &self,
+        values: &[(Oid, Value)],
+    ) -> Result<Vec<VariableBinding>> {
+        let req = values
+            .iter()
+            .map(|(oid, val)| VariableBinding {
+                name: oid.0,
+                value: BindingValue::Value(val.0.clone()),
+            })
+            .collect::<Vec<_>>();
+        let resp = self
+            .request(
+                |request_id| {
+                    Snmp2cPdu::SetRequest(inner(request_id, req.clone()))
+                },
+                &req,
+            )
+            .await?;
+        self.check_names(&req, &resp)?;
+        Ok(resp)
+    }
+
+    pub(crate) fn target(&self) -> SocketAddr {
+        self.target
+    }
+}
+
+fn unspecified(oids: &[Oid]) -> Vec<VariableBinding> {
+    oids.iter()
+        .map(|oid| VariableBinding {
+            name: oid.0,
+            value: BindingValue::Unspecified,
+        })
+        .collect()
+}
+
+fn inner(request_id: i32, variable_bindings: Vec<VariableBinding>) -> InnerPdu {
+    InnerPdu {
+        request_id,
+        error_status: ErrorStatus::NoError,
+        error_index: 0,
+        variable_bindings,
+    }
+}
+
+/**
+ * Convert a binding from a response into a value or an exception.  An
+ * unspecified value is not valid in a response, but is treated as a missing
+ * instance rather than rejecting the whole response.
+ */
+pub(crate) fn varbind(vb: VariableBinding) -> (Oid, Varbind) {
+    (
+        Oid(vb.name),
+        match vb.value {
+            BindingValue::Value(v) => Varbind::Value(Value(v)),
+            BindingValue::NoSuchObject => {
+                Varbind::Exception(Exception::NoSuchObject)
+            }
+            BindingValue::NoSuchInstance | BindingValue::Unspecified => {
+                Varbind::Exception(Exception::NoSuchInstance)
+            }
+            BindingValue::EndOfMibView => {
+                Varbind::Exception(Exception::EndOfMibView)
+            }
+        },
+    )
+}
+
+/**
+ * Take the value from a binding, treating an exception as an error.
+ */
+pub(crate) fn binding_value(vb: VariableBinding) -> Result<(Oid, Value)> {
+    let (oid, vb) = varbind(vb);
+    Ok((oid, vb.into_result(oid)?))
+}
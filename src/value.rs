@@ -6,9 +6,9 @@ use std::ops::Deref;
 use std::result::Result as SResult;
 
 use csnmp::ObjectValue;
-use serde::de::value::U32Deserializer;
+use serde::de::value::{I32Deserializer, U32Deserializer};
 use serde::de::{DeserializeSeed, Error, SeqAccess, Unexpected};
-use serde::Deserializer;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Clone, PartialEq, Eq)]
 #[repr(transparent)]
@@ -22,6 +22,53 @@ impl Deref for Value {
     }
 }
 
+/**
+ * The outcome for a single OID in a GET or GETBULK response: either a value,
+ * or the exception that the agent returned in its place because, for
+ * example, the object or the particular instance does not exist.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Varbind {
+    Value(Value),
+    Exception(crate::Exception),
+}
+
+impl Varbind {
+    pub fn value(&self) -> Option<&Value> {
+        match self {
+            Varbind::Value(v) => Some(v),
+            Varbind::Exception(_) => None,
+        }
+    }
+
+    pub fn into_value(self) -> Option<Value> {
+        match self {
+            Varbind::Value(v) => Some(v),
+            Varbind::Exception(_) => None,
+        }
+    }
+
+    pub fn exception(&self) -> Option<crate::Exception> {
+        match self {
+            Varbind::Value(_) => None,
+            Varbind::Exception(e) => Some(*e),
+        }
+    }
+
+    /**
+     * Treat an exception as an error, for callers that require a value.
+     */
+    pub fn into_result(self, oid: crate::Oid) -> crate::Result<Value> {
+        match self {
+            Varbind::Value(v) => Ok(v),
+            Varbind::Exception(exception) => {
+                Err(crate::Error::Exception { oid: Box::new(oid), exception })
+            }
+        }
+    }
+}
+
 impl<'de> serde::de::IntoDeserializer<'de> for &'de Value {
     type Deserializer = ValueDeserializer<'de>;
 
@@ -85,6 +132,42 @@ impl ValueDeserializer<'_> {
             )),
         }
     }
+
+    fn as_f64(&self) -> SResult<f64, serde::de::value::Error> {
+        match &self.0 {
+            ObjectValue::Opaque(buf) => opaque_float(buf).ok_or_else(|| {
+                serde::de::value::Error::invalid_value(
+                    Unexpected::Bytes(buf),
+                    &"an Opaque float or double",
+                )
+            }),
+            ObjectValue::Counter64(u) => Ok(*u as f64),
+            _ => self.as_i64().map(|i| i as f64).map_err(|_| {
+                serde::de::value::Error::invalid_value(
+                    Unexpected::Other("other SNMP type"),
+                    &"a number",
+                )
+            }),
+        }
+    }
+}
+
+/**
+ * Decode the net-snmp encodings of a float or double in an Opaque, as used by
+ * the UCD-SNMP extensions: the BER encoding of the number with an
+ * application-specific tag (0x9f 0x78 for a float, 0x9f 0x79 for a double),
+ * followed by the IEEE 754 value in network byte order.
+ */
+fn opaque_float(buf: &[u8]) -> Option<f64> {
+    match buf {
+        [0x9f, 0x78, 0x04, rest @ ..] => {
+            Some(f32::from_be_bytes(rest.try_into().ok()?).into())
+        }
+        [0x9f, 0x79, 0x08, rest @ ..] => {
+            Some(f64::from_be_bytes(rest.try_into().ok()?))
+        }
+        _ => None,
+    }
 }
 
 impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
@@ -96,23 +179,49 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
     {
         match &self.0 {
             ObjectValue::Integer(_) => self.deserialize_i32(v),
+            /*
+             * An OCTET STRING need not be text (e.g., a MAC address), and
+             * should not fail to decode when it is being ignored.
+             */
+            ObjectValue::String(buf) if std::str::from_utf8(buf).is_err() => {
+                self.deserialize_bytes(v)
+            }
             ObjectValue::String(_) => self.deserialize_str(v),
             ObjectValue::ObjectId(_) => self.deserialize_seq(v),
             ObjectValue::Counter32(_)
             | ObjectValue::Unsigned32(_)
             | ObjectValue::TimeTicks(_) => self.deserialize_u32(v),
             ObjectValue::Counter64(_) => self.deserialize_u64(v),
+            ObjectValue::Opaque(buf) if opaque_float(buf).is_some() => {
+                self.deserialize_f64(v)
+            }
             ObjectValue::IpAddress(_) | ObjectValue::Opaque(_) => {
                 self.deserialize_bytes(v)
             }
         }
     }
 
-    fn deserialize_bool<V>(self, _v: V) -> SResult<V::Value, Self::Error>
+    /**
+     * A bool is a TruthValue: true(1) or false(2).
+     */
+    fn deserialize_bool<V>(self, v: V) -> SResult<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(serde::de::value::Error::custom("no bool support"))
+        match &self.0 {
+            ObjectValue::Integer(1) => v.visit_bool(true),
+            ObjectValue::Integer(2) => v.visit_bool(false),
+            ObjectValue::Integer(i) => {
+                Err(serde::de::value::Error::invalid_value(
+                    Unexpected::Signed((*i).into()),
+                    &"a TruthValue",
+                ))
+            }
+            _ => Err(serde::de::value::Error::invalid_value(
+                Unexpected::Other("other SNMP value"),
+                &"a TruthValue",
+            )),
+        }
     }
 
     fn deserialize_i8<V>(self, v: V) -> SResult<V::Value, Self::Error>
@@ -171,18 +280,23 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
         v.visit_u64(self.as_u64()?)
     }
 
-    fn deserialize_f32<V>(self, _v: V) -> SResult<V::Value, Self::Error>
+    fn deserialize_f32<V>(self, v: V) -> SResult<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(serde::de::value::Error::custom("no f32 support"))
+        self.deserialize_f64(v)
     }
 
-    fn deserialize_f64<V>(self, _v: V) -> SResult<V::Value, Self::Error>
+    /**
+     * A floating point number is either an Opaque float or double, or an
+     * integer of any kind.  An integer in fixed units, such as tenths of an
+     * amp, should instead be deserialised as a Scaled value.
+     */
+    fn deserialize_f64<V>(self, v: V) -> SResult<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(serde::de::value::Error::custom("no f64 support"))
+        v.visit_f64(self.as_f64()?)
     }
 
     fn deserialize_char<V>(self, _v: V) -> SResult<V::Value, Self::Error>
@@ -205,6 +319,11 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
                     )
                 })?)
             }
+            /*
+             * In dotted form, so that an IpAddress can be deserialised as a
+             * std::net::Ipv4Addr:
+             */
+            ObjectValue::IpAddress(ip) => v.visit_string(ip.to_string()),
             _ => Err(serde::de::value::Error::invalid_value(
                 Unexpected::Other("other SNMP value"),
                 &"a valid UTF-8 string",
@@ -227,9 +346,10 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
             ObjectValue::String(buf) | ObjectValue::Opaque(buf) => {
                 v.visit_bytes(buf)
             }
+            ObjectValue::IpAddress(ip) => v.visit_bytes(&ip.octets()),
             _ => Err(serde::de::value::Error::invalid_value(
                 Unexpected::Other("other SNMP value"),
-                &"an opaque or a string",
+                &"an opaque, a string or an IpAddress",
             )),
         }
     }
@@ -241,11 +361,15 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
         self.deserialize_bytes(v)
     }
 
-    fn deserialize_option<V>(self, _v: V) -> SResult<V::Value, Self::Error>
+    /**
+     * A value that is present is always Some; a column missing from a row
+     * is None.
+     */
+    fn deserialize_option<V>(self, v: V) -> SResult<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(serde::de::value::Error::custom("no option support"))
+        v.visit_some(self)
     }
 
     fn deserialize_unit<V>(self, _v: V) -> SResult<V::Value, Self::Error>
@@ -269,12 +393,12 @@ impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
     fn deserialize_newtype_struct<V>(
         self,
         _name: &'static str,
-        _v: V,
+        v: V,
     ) -> SResult<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(serde::de::value::Error::custom("no newtype struct support"))
+        v.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, v: V) -> SResult<V::Value, Self::Error>
@@ -404,3 +528,428 @@ impl std::fmt::Debug for Value {
         }
     }
 }
+
+/**
+ * A value of the SMI BITS syntax: an OCTET STRING in which bit 0 is the most
+ * significant bit of the first octet, bit 8 the most significant bit of the
+ * second, and so on.  A flags type of its own can be deserialised through
+ * this with #[serde(from = "Bits")].
+ */
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Bits(Vec<u8>);
+
+impl Bits {
+    pub fn from_octets(octets: Vec<u8>) -> Bits {
+        Bits(octets)
+    }
+
+    pub fn octets(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn contains(&self, bit: u32) -> bool {
+        let (octet, mask) = Bits::locate(bit);
+        self.0.get(octet).is_some_and(|o| o & mask != 0)
+    }
+
+    /**
+     * Set a bit, growing the value by as many octets as it needs.
+     */
+    pub fn insert(&mut self, bit: u32) {
+        let (octet, mask) = Bits::locate(bit);
+        if self.0.len() <= octet {
+            self.0.resize(octet + 1, 0);
+        }
+        self.0[octet] |= mask;
+    }
+
+    pub fn remove(&mut self, bit: u32) {
+        let (octet, mask) = Bits::locate(bit);
+        if let Some(o) = self.0.get_mut(octet) {
+            *o &= !mask;
+        }
+    }
+
+    /**
+     * The numbers of the bits that are set, in ascending order.
+     */
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, o)| {
+            (0..8)
+                .filter(move |b| o & (0x80 >> b) != 0)
+                .map(move |b| i as u32 * 8 + b)
+        })
+    }
+
+    /**
+     * The OCTET STRING to SET.
+     */
+    pub fn to_value(&self) -> Value {
+        Value(ObjectValue::String(self.0.clone()))
+    }
+
+    fn locate(bit: u32) -> (usize, u8) {
+        ((bit / 8) as usize, 0x80 >> (bit % 8))
+    }
+}
+
+impl FromIterator<u32> for Bits {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Bits {
+        let mut bits = Bits::default();
+        for bit in iter {
+            bits.insert(bit);
+        }
+        bits
+    }
+}
+
+impl<'de> Deserialize<'de> for Bits {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> SResult<Bits, D::Error> {
+        struct BitsVisitor;
+
+        impl serde::de::Visitor<'_> for BitsVisitor {
+            type Value = Bits;
+
+            fn expecting(
+                &self,
+                f: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                f.write_str("a BITS value")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> SResult<Bits, E> {
+                Ok(Bits(v.to_vec()))
+            }
+        }
+
+        d.deserialize_bytes(BitsVisitor)
+    }
+}
+
+/**
+ * A BITS value decoded into a set of named bits.  Each bit that is set is
+ * deserialised from its number, so an enum that derives Deserialize_repr with
+ * the bit numbers as discriminants will do.  Bits that the type does not name
+ * are kept rather than treated as an error, as agents may set bits defined
+ * after the type was written.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitSet<T: Ord> {
+    pub set: std::collections::BTreeSet<T>,
+    pub unknown: std::collections::BTreeSet<u32>,
+    /**
+     * The number of octets in the value that was read, which is the least
+     * number that to_bits() produces.  Agents may reject a value shorter
+     * than the one they report, even if the missing octets would be zero.
+     */
+    pub octets: usize,
+}
+
+impl<T: Ord> Default for BitSet<T> {
+    fn default() -> Self {
+        BitSet {
+            set: Default::default(),
+            unknown: Default::default(),
+            octets: 0,
+        }
+    }
+}
+
+impl<T: Ord> BitSet<T> {
+    pub fn contains(&self, bit: &T) -> bool {
+        self.set.contains(bit)
+    }
+}
+
+impl<T: Ord + Copy + Into<u32>> BitSet<T> {
+    /**
+     * Encode the set for a SET request, including any unknown bits that were
+     * set when it was read, padded with zero octets to the length it was read
+     * with.
+     */
+    pub fn to_bits(&self) -> Bits {
+        let mut bits = self
+            .set
+            .iter()
+            .map(|t| (*t).into())
+            .chain(self.unknown.iter().copied())
+            .collect::<Bits>();
+        if bits.0.len() < self.octets {
+            bits.0.resize(self.octets, 0);
+        }
+        bits
+    }
+
+    pub fn to_value(&self) -> Value {
+        self.to_bits().to_value()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for BitSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> BitSet<T> {
+        BitSet {
+            set: iter.into_iter().collect(),
+            unknown: Default::default(),
+            octets: 0,
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for BitSet<T>
+where
+    T: Ord + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(d: D) -> SResult<Self, D::Error> {
+        let bits = Bits::deserialize(d)?;
+
+        let mut out = BitSet { octets: bits.0.len(), ..Default::default() };
+        for bit in bits.iter() {
+            let de = U32Deserializer::<serde::de::value::Error>::new(bit);
+            match T::deserialize(de) {
+                Ok(t) => {
+                    out.set.insert(t);
+                }
+                Err(_) => {
+                    out.unknown.insert(bit);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/**
+ * An enumerated INTEGER that may hold a value the enum does not list, such as
+ * one added by newer firmware.  Any enum that derives Deserialize_repr can be
+ * wrapped this way, so that an unexpected value does not prevent the rest of
+ * a row from being decoded.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum OrUnknown<T> {
+    Known(T),
+    Unknown(i32),
+}
+
+impl<T> OrUnknown<T> {
+    pub fn known(&self) -> Option<&T> {
+        match self {
+            OrUnknown::Known(t) => Some(t),
+            OrUnknown::Unknown(_) => None,
+        }
+    }
+
+    pub fn into_known(self) -> Option<T> {
+        match self {
+            OrUnknown::Known(t) => Some(t),
+            OrUnknown::Unknown(_) => None,
+        }
+    }
+}
+
+impl<T> From<T> for OrUnknown<T> {
+    fn from(t: T) -> Self {
+        OrUnknown::Known(t)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OrUnknown<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> SResult<Self, D::Error> {
+        let i = i32::deserialize(d)?;
+        let de = I32Deserializer::<serde::de::value::Error>::new(i);
+        Ok(match T::deserialize(de) {
+            Ok(t) => OrUnknown::Known(t),
+            Err(_) => OrUnknown::Unknown(i),
+        })
+    }
+}
+
+/**
+ * An integer in units of 10^-DECIMALS, as agents often report quantities
+ * that are not whole numbers; e.g., APC PDUs report current in tenths of
+ * amps, which is a Scaled<1>.  The integer is kept as received, so that it
+ * can be compared and written back exactly.  DECIMALS may be at most 18, as
+ * 10^19 does not fit in an i64; converting or displaying a Scaled with more
+ * does not compile.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Scaled<const DECIMALS: u32>(pub i64);
+
+impl<const DECIMALS: u32> Scaled<DECIMALS> {
+    const DIVISOR: i64 = {
+        assert!(DECIMALS <= 18, "10^DECIMALS must fit in an i64");
+        10i64.pow(DECIMALS)
+    };
+
+    /**
+     * The nearest scaled integer to a value, such as a threshold to SET.
+     */
+    pub fn from_value(value: f64) -> Scaled<DECIMALS> {
+        Scaled((value * Self::DIVISOR as f64).round() as i64)
+    }
+
+    pub fn raw(&self) -> i64 {
+        self.0
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / Self::DIVISOR as f64
+    }
+}
+
+impl<const DECIMALS: u32> From<Scaled<DECIMALS>> for f64 {
+    fn from(s: Scaled<DECIMALS>) -> f64 {
+        s.value()
+    }
+}
+
+/**
+ * Exactly, with DECIMALS digits after the point; e.g., "9.5".
+ */
+impl<const DECIMALS: u32> std::fmt::Display for Scaled<DECIMALS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (d, abs) = (Self::DIVISOR.unsigned_abs(), self.0.unsigned_abs());
+        let sign = if self.0 < 0 { "-" } else { "" };
+        if DECIMALS == 0 {
+            return write!(f, "{sign}{abs}");
+        }
+        write!(
+            f,
+            "{sign}{}.{:0width$}",
+            abs / d,
+            abs % d,
+            width = DECIMALS as usize
+        )
+    }
+}
+
+impl<'de, const DECIMALS: u32> Deserialize<'de> for Scaled<DECIMALS> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> SResult<Self, D::Error> {
+        i64::deserialize(d).map(Scaled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::de::IntoDeserializer;
+    use serde_repr::Deserialize_repr;
+
+    use super::*;
+    use crate::capture::parse_snmpwalk;
+    use crate::mib;
+
+    /*
+     * The values of a capture of scalars under the experimental arc, such as
+     * "iso.3.6.1.3.1.N.0", by N.
+     */
+    fn experimental(capture: &str) -> BTreeMap<u32, Value> {
+        parse_snmpwalk(capture, &mib::bundled())
+            .unwrap()
+            .into_iter()
+            .map(|(oid, value)| match oid.as_slice() {
+                [1, 3, 6, 1, 3, 1, n, 0] => (*n, value),
+                _ => panic!("unexpected OID {oid}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bits_flags() {
+        #[derive(
+            Deserialize_repr, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug,
+        )]
+        #[repr(u32)]
+        enum Capability {
+            Auto = 0,
+            Half10 = 1,
+            Full10 = 2,
+            Half100 = 3,
+            Full100 = 4,
+            Full1000 = 5,
+        }
+
+        impl From<Capability> for u32 {
+            fn from(c: Capability) -> u32 {
+                c as u32
+            }
+        }
+
+        #[derive(Deserialize)]
+        #[serde(from = "Bits")]
+        struct Flags(u16);
+
+        impl From<Bits> for Flags {
+            fn from(bits: Bits) -> Flags {
+                Flags(bits.iter().fold(0, |f, b| f | 1 << b))
+            }
+        }
+
+        /*
+         * Bits 0, 4 and 5 are set in the first octet, and bit 9 in the second.
+         */
+        let capture = "\
+            iso.3.6.1.3.1.1.0 = Hex-STRING: 8C 40\n\
+            iso.3.6.1.3.1.2.0 = Hex-STRING: 40 00 00\n";
+        let values = experimental(capture);
+        let value = &values[&1];
+
+        let caps = BitSet::<Capability>::deserialize(value.into_deserializer())
+            .unwrap();
+        assert_eq!(
+            caps.set.iter().copied().collect::<Vec<_>>(),
+            [Capability::Auto, Capability::Full100, Capability::Full1000]
+        );
+        assert_eq!(caps.unknown.iter().copied().collect::<Vec<_>>(), [9]);
+        assert!(!caps.contains(&Capability::Half10));
+        assert_eq!(&caps.to_value(), value);
+
+        let flags = Flags::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(flags.0, 0b10_0011_0001);
+
+        let bits = [Capability::Half10, Capability::Full1000]
+            .into_iter()
+            .collect::<BitSet<_>>()
+            .to_bits();
+        assert_eq!(bits.octets(), [0x44]);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), [1, 5]);
+        assert_eq!([9].into_iter().collect::<Bits>().octets(), [0x00, 0x40]);
+
+        /*
+         * Trailing zero octets are kept when the set is written back.
+         */
+        let mut caps =
+            BitSet::<Capability>::deserialize(values[&2].into_deserializer())
+                .unwrap();
+        assert_eq!(caps.octets, 3);
+        assert_eq!(caps.to_value(), values[&2]);
+        caps.set.insert(Capability::Auto);
+        assert_eq!(caps.to_bits().octets(), [0xc0, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn floating_point() {
+        let capture = "\
+            iso.3.6.1.3.1.1.0 = Opaque: Float: 0.5\n\
+            iso.3.6.1.3.1.2.0 = Opaque: Double: 1.25\n\
+            iso.3.6.1.3.1.3.0 = INTEGER: 3\n\
+            iso.3.6.1.3.1.4.0 = INTEGER: -5\n\
+            iso.3.6.1.3.1.5.0 = OPAQUE: 01 02 03\n";
+        let values = experimental(capture);
+        let value = |n| values[&n].into_deserializer();
+
+        assert_eq!(f32::deserialize(value(1)).unwrap(), 0.5);
+        assert_eq!(f64::deserialize(value(2)).unwrap(), 1.25);
+        assert_eq!(f64::deserialize(value(3)).unwrap(), 3.0);
+        assert!(f64::deserialize(value(5)).is_err());
+        assert!(Scaled::<1>::deserialize(value(1)).is_err());
+
+        let negative = Scaled::<2>::deserialize(value(4)).unwrap();
+        assert_eq!(negative, Scaled(-5));
+        assert_eq!(negative.to_string(), "-0.05");
+        assert_eq!(Scaled::<2>::from_value(-0.05), negative);
+        assert_eq!(Scaled::<0>(7).to_string(), "7");
+        assert_eq!(Scaled::<18>(-1).to_string(), "-0.000000000000000001");
+        assert_eq!(Scaled::<18>(i64::MAX).to_string(), "9.223372036854775807");
+    }
+}
@@ -3,14 +3,619 @@
  */
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, VecDeque},
     ops::Bound,
     sync::Arc,
+    time::Duration,
 };
 
-use crate::{value::Value, Oid};
-use anyhow::{anyhow, bail, Result};
-use serde::{de::value::MapDeserializer, Deserialize};
+use crate::{
+    extract::{self, ColumnReport, Extraction, Field, TablePlan, TableShape},
+    transport::{self, Transport},
+    value::{Value, Varbind},
+    Error, Exception, Oid, Result,
+};
+use csnmp::message::{ErrorStatus, VariableBinding};
+use futures::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/**
+ * Options that control how a subtree is walked.  The defaults match the
+ * behaviour of net-snmp's "snmpbulkwalk": GETBULK with a generous repetition
+ * count and no limits.
+ */
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /**
+     * The max-repetitions value for each GETBULK request.  Some agents (e.g.,
+     * older APC network management cards) struggle with large responses, so
+     * this may need to be lowered.
+     */
+    pub max_repetitions: u32,
+    /**
+     * Use GETNEXT, one OID at a time, rather than GETBULK; for agents with a
+     * broken GETBULK implementation.
+     */
+    pub getnext: bool,
+    /**
+     * If a GETBULK request fails with tooBig or times out, halve the
+     * repetition count for the rest of the walk and try again, until it
+     * reaches one.
+     */
+    pub adaptive: bool,
+    /**
+     * Fail the walk if it would return more than this many values.
+     */
+    pub max_varbinds: Option<usize>,
+    /**
+     * Fail the walk if it does not complete within this time.
+     */
+    pub deadline: Option<Duration>,
+    /**
+     * Rather than failing the walk, skip over any value with an OID that does
+     * not follow the previous one.  The walk still fails if an entire
+     * response makes no progress.
+     */
+    pub allow_non_increasing: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            max_repetitions: 63,
+            getnext: false,
+            adaptive: true,
+            max_varbinds: None,
+            deadline: None,
+            allow_non_increasing: false,
+        }
+    }
+}
+
+/**
+ * Issues the GETNEXT or GETBULK requests for a walk, honouring the deadline
+ * and adapting the repetition count as described by the walk options.
+ */
+struct Fetcher<'a> {
+    transport: &'a Transport,
+    options: WalkOptions,
+    reps: u32,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Fetcher<'a> {
+    fn new(transport: &'a Transport, options: &WalkOptions) -> Fetcher<'a> {
+        Fetcher {
+            transport,
+            options: options.clone(),
+            reps: options.max_repetitions.max(1),
+            deadline: options.deadline.map(|d| Instant::now() + d),
+        }
+    }
+
+    /**
+     * Request the values that follow each of the provided OIDs.  For
+     * GETBULK, the response is interleaved: the successor of each OID in
+     * turn, then the next successor of each, and so on.
+     */
+    async fn fetch(
+        &mut self,
+        oids: &[Oid],
+        top: Oid,
+        last: Option<Oid>,
+    ) -> Result<Vec<VariableBinding>> {
+        loop {
+            let req = async {
+                if self.options.getnext {
+                    self.transport.get_next(oids).await
+                } else {
+                    self.transport.get_bulk(oids, 0, self.reps).await
+                }
+            };
+            let res = match self.deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, req)
+                    .await
+                    .map_err(|_| Error::WalkDeadline {
+                        top: Box::new(top),
+                        last: last.map(Box::new),
+                    })?,
+                None => req.await,
+            };
+
+            match res {
+                Ok(res) => return Ok(res),
+                Err(e)
+                    if self.options.adaptive
+                        && !self.options.getnext
+                        && self.reps > 1
+                        && (e.is_timeout()
+                            || e.agent_status()
+                                == Some(ErrorStatus::TooBig)) =>
+                {
+                    self.reps /= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /**
+     * Account for another value, failing if there are now too many.
+     */
+    fn count(&self, top: Oid, count: usize) -> Result<()> {
+        match self.options.max_varbinds {
+            Some(limit) if count > limit => {
+                Err(Error::TooManyValues { top: Box::new(top), limit })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/**
+ * A walk of the subtree beneath an OID, which fetches values from the agent
+ * one response at a time.  Values are buffered until they are consumed, and
+ * any failure is held until the values before it have been consumed.
+ */
+struct Walker<'a> {
+    fetcher: Fetcher<'a>,
+    top: Oid,
+    last: Oid,
+    count: usize,
+    buffer: VecDeque<(Oid, Value)>,
+    error: Option<Error>,
+    done: bool,
+}
+
+impl<'a> Walker<'a> {
+    fn new(
+        transport: &'a Transport,
+        top: Oid,
+        options: &WalkOptions,
+    ) -> Walker<'a> {
+        Walker {
+            fetcher: Fetcher::new(transport, options),
+            top,
+            last: top,
+            count: 0,
+            buffer: VecDeque::new(),
+            error: None,
+            done: false,
+        }
+    }
+
+    /**
+     * Take the next value, making further requests as needed.  After the
+     * last value, any failure is returned once and then the walk is over.
+     */
+    async fn next(&mut self) -> Option<Result<(Oid, Value)>> {
+        loop {
+            if let Some(v) = self.buffer.pop_front() {
+                return Some(Ok(v));
+            }
+            if self.done {
+                return self.error.take().map(Err);
+            }
+            if let Err(e) = self.fill().await {
+                self.error = Some(e);
+                self.done = true;
+            }
+        }
+    }
+
+    /**
+     * Make one request, adding whatever values it returns to the buffer.
+     */
+    async fn fill(&mut self) -> Result<()> {
+        let res = self
+            .fetcher
+            .fetch(
+                &[self.last],
+                self.top,
+                (self.last != self.top).then_some(self.last),
+            )
+            .await?;
+
+        if res.is_empty() {
+            self.done = true;
+            return Ok(());
+        }
+
+        let mut progress = false;
+        for vb in res {
+            let (oid, vb) = transport::varbind(vb);
+            if matches!(vb, Varbind::Exception(Exception::EndOfMibView))
+                || !self.top.is_prefix_of_or_equal(&oid)
+            {
+                self.done = true;
+                return Ok(());
+            }
+            if oid <= self.last {
+                if self.fetcher.options.allow_non_increasing {
+                    continue;
+                }
+                return Err(Error::NonIncreasing {
+                    previous: Box::new(self.last),
+                    next: Box::new(oid),
+                });
+            }
+
+            self.count += 1;
+            self.fetcher.count(self.top, self.count)?;
+            self.buffer.push_back((oid, vb.into_result(oid)?));
+            self.last = oid;
+            progress = true;
+        }
+
+        if !progress {
+            /*
+             * A response made up entirely of values we have skipped would see
+             * us make the same request forever.
+             */
+            return Err(Error::NonIncreasing {
+                previous: Box::new(self.last),
+                next: Box::new(self.last),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Walk the subtree beneath an OID according to the provided options.
+ */
+pub(crate) async fn walk(
+    transport: &Transport,
+    top: Oid,
+    options: &WalkOptions,
+) -> Result<BTreeMap<Oid, Value>> {
+    let mut w = Walker::new(transport, top, options);
+    let mut values = BTreeMap::new();
+    while let Some(v) = w.next().await {
+        let (oid, val) = v?;
+        values.insert(oid, val);
+    }
+    Ok(values)
+}
+
+/**
+ * Walk the subtree beneath an OID, producing each value as it arrives.
+ */
+pub(crate) fn walk_stream<'a>(
+    transport: &'a Transport,
+    top: Oid,
+    options: &WalkOptions,
+) -> impl Stream<Item = Result<(Oid, Value)>> + 'a {
+    stream::unfold(Walker::new(transport, top, options), |mut w| async move {
+        w.next().await.map(|v| (v, w))
+    })
+}
+
+/**
+ * One column of a table being walked row by row.
+ */
+struct Column {
+    oid: Oid,
+    name: String,
+    last: Oid,
+    done: bool,
+}
+
+impl Column {
+    /**
+     * The index of the last row seen in this column, or zero if none has
+     * been seen yet.
+     */
+    fn index(&self) -> u32 {
+        self.last.relative_to(self.oid).and_then(|rel| rel.get(0)).unwrap_or(0)
+    }
+}
+
+/**
+ * A walk of the columns of a table in parallel, in the manner of net-snmp's
+ * "snmptable", so that complete rows are available as the walk progresses
+ * rather than only once every column has been walked.
+ */
+struct TableWalker<'a> {
+    fetcher: Fetcher<'a>,
+    tree: &'a crate::oidtree::OidTree,
+    entry: Oid,
+    columns: Vec<Column>,
+    count: usize,
+    rows: BTreeMap<u32, Vec<RowValue>>,
+    buffer: VecDeque<(u32, Vec<RowValue>)>,
+    error: Option<Error>,
+    done: bool,
+}
+
+/**
+ * A value in a row, with the name of its column.
+ */
+struct RowValue {
+    name: String,
+    oid: Oid,
+    value: Value,
+}
+
+impl<'a> TableWalker<'a> {
+    fn new(
+        transport: &'a Transport,
+        tree: &'a crate::oidtree::OidTree,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        options: &WalkOptions,
+    ) -> Result<TableWalker<'a>> {
+        let columns = tree
+            .children(table_entry)?
+            .into_iter()
+            .map(|oid| {
+                let n = tree.oid_name(oid)?;
+                let Some(name) = n.basename().strip_prefix(strip_name_prefix)
+                else {
+                    return Err(Error::TableStructure {
+                        oid: Box::new(oid),
+                        message: format!(
+                            "name {n} not prefixed with {strip_name_prefix:?}"
+                        ),
+                    });
+                };
+                Ok(Column {
+                    oid,
+                    name: name.to_string(),
+                    last: oid,
+                    done: false,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if columns.is_empty() {
+            return Err(Error::TableStructure {
+                oid: Box::new(table_entry),
+                message: "no columns in the OID tree".into(),
+            });
+        }
+
+        Ok(TableWalker {
+            fetcher: Fetcher::new(transport, options),
+            tree,
+            entry: table_entry,
+            columns,
+            count: 0,
+            rows: BTreeMap::new(),
+            buffer: VecDeque::new(),
+            error: None,
+            done: false,
+        })
+    }
+
+    /**
+     * Take the next complete row, making further requests as needed.  After
+     * the last row, any failure is returned once and then the walk is over.
+     */
+    async fn next(&mut self) -> Option<Result<(u32, Vec<RowValue>)>> {
+        loop {
+            if let Some(row) = self.buffer.pop_front() {
+                return Some(Ok(row));
+            }
+            if self.done {
+                return self.error.take().map(Err);
+            }
+            if let Err(e) = self.fill().await {
+                self.error = Some(e);
+                self.done = true;
+            }
+        }
+    }
+
+    /**
+     * Make one request for the columns that have not yet been walked to the
+     * end, and move any rows that are now complete to the buffer.
+     */
+    async fn fill(&mut self) -> Result<()> {
+        let active = (0..self.columns.len())
+            .filter(|&i| !self.columns[i].done)
+            .collect::<Vec<_>>();
+        if active.is_empty() {
+            self.buffer.extend(std::mem::take(&mut self.rows));
+            self.done = true;
+            return Ok(());
+        }
+
+        let oids =
+            active.iter().map(|&i| self.columns[i].last).collect::<Vec<_>>();
+        let res = self.fetcher.fetch(&oids, self.entry, None).await?;
+        if res.is_empty() {
+            self.columns.iter_mut().for_each(|c| c.done = true);
+        }
+
+        let mut progress = false;
+        for (n, vb) in res.into_iter().enumerate() {
+            let col = &mut self.columns[active[n % active.len()]];
+            if col.done {
+                continue;
+            }
+
+            let (oid, vb) = transport::varbind(vb);
+            if matches!(vb, Varbind::Exception(Exception::EndOfMibView))
+                || !col.oid.is_prefix_of_or_equal(&oid)
+            {
+                col.done = true;
+                progress = true;
+                continue;
+            }
+            if oid <= col.last {
+                if self.fetcher.options.allow_non_increasing {
+                    continue;
+                }
+                return Err(Error::NonIncreasing {
+                    previous: Box::new(col.last),
+                    next: Box::new(oid),
+                });
+            }
+
+            let rel = oid.relative_to(col.oid).expect("must be a child of oid");
+            if rel.len() != 1 || rel.get(0).unwrap() == 0 {
+                return Err(Error::TableStructure {
+                    oid: Box::new(oid),
+                    message: format!("unexpected {rel} under {}", col.oid),
+                });
+            }
+            let i = rel.get(0).unwrap();
+
+            self.count += 1;
+            self.fetcher.count(self.entry, self.count)?;
+            let val = vb.into_result(oid)?;
+            self.rows.entry(i).or_default().push(RowValue {
+                name: col.name.clone(),
+                oid,
+                value: val,
+            });
+            col.last = oid;
+            progress = true;
+        }
+
+        if !progress && self.columns.iter().any(|c| !c.done) {
+            let last = self.columns[active[0]].last;
+            return Err(Error::NonIncreasing {
+                previous: Box::new(last),
+                next: Box::new(last),
+            });
+        }
+
+        /*
+         * Each column is walked in index order, so once every column that is
+         * still being walked has passed a particular index, the row at that
+         * index is complete.
+         */
+        match self.columns.iter().filter(|c| !c.done).map(Column::index).min() {
+            Some(bound) => {
+                let rest = self.rows.split_off(&(bound + 1));
+                self.buffer.extend(std::mem::replace(&mut self.rows, rest));
+            }
+            None => {
+                self.buffer.extend(std::mem::take(&mut self.rows));
+                self.done = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Walk the columns of a table in parallel, producing each row as soon as it
+ * is complete.
+ */
+pub(crate) fn table_stream<'a, T>(
+    transport: &'a Transport,
+    tree: &'a crate::oidtree::OidTree,
+    table_entry: Oid,
+    strip_name_prefix: &str,
+    options: &WalkOptions,
+) -> impl Stream<Item = Result<(u32, T)>> + 'a
+where
+    T: for<'de> Deserialize<'de> + 'a,
+{
+    let w = TableWalker::new(
+        transport,
+        tree,
+        table_entry,
+        strip_name_prefix,
+        options,
+    );
+
+    stream::unfold(Some(w), |w| async move {
+        let mut w = match w? {
+            Ok(w) => w,
+            Err(e) => return Some((Err(e), None)),
+        };
+
+        let row = w.next().await?.and_then(|(idx, row)| {
+            let fields = row
+                .iter()
+                .map(|v| Field {
+                    name: v.name.as_str().into(),
+                    group: None,
+                    oid: v.oid,
+                    value: &v.value,
+                })
+                .collect();
+            let t = extract::decode(
+                w.tree,
+                w.entry,
+                Some(idx),
+                None,
+                fields,
+                None,
+            )?;
+            Ok((idx, t))
+        });
+        Some((row, Some(Ok(w))))
+    })
+}
+
+/**
+ * A walk that failed part of the way through, with the values that were
+ * received before the failure.
+ */
+pub struct PartialWalk {
+    pub values: WalkedValues,
+    pub error: Error,
+}
+
+impl std::fmt::Debug for PartialWalk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartialWalk")
+            .field("values", &self.values.len())
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl std::fmt::Display for PartialWalk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (after {} values)", self.error, self.values.len())
+    }
+}
+
+impl std::error::Error for PartialWalk {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PartialWalk> for Error {
+    fn from(p: PartialWalk) -> Self {
+        p.error
+    }
+}
+
+/**
+ * How the value of one OID differs between two sets of walked values.  The
+ * values of a change are boxed to keep the two of them from doubling the
+ * size of every entry.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Added(Value),
+    Removed(Value),
+    Changed { old: Box<Value>, new: Box<Value> },
+}
+
+/**
+ * One entry in the result of WalkedValues::diff().
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub oid: Oid,
+    /**
+     * The name of the OID in the OID tree, if it has one, in the short form;
+     * e.g., "ifOperStatus[3]".
+     */
+    pub name: Option<String>,
+    pub difference: Difference,
+}
 
 pub struct WalkedValues {
     pub(crate) values: BTreeMap<Oid, Value>,
@@ -18,6 +623,136 @@ pub struct WalkedValues {
 }
 
 impl WalkedValues {
+    /**
+     * Assemble a set of values obtained by some means other than a walk,
+     * such as a capture file, for use with the extraction routines.
+     */
+    pub fn from_values(
+        tree: Arc<crate::oidtree::OidTree>,
+        values: impl IntoIterator<Item = (Oid, Value)>,
+    ) -> WalkedValues {
+        WalkedValues { values: values.into_iter().collect(), tree }
+    }
+
+    /**
+     * Collect the values produced by Client::walk_stream().  If the walk
+     * fails, the values received before the failure are returned along with
+     * the error.
+     */
+    pub async fn from_stream(
+        tree: Arc<crate::oidtree::OidTree>,
+        stream: impl Stream<Item = Result<(Oid, Value)>>,
+    ) -> std::result::Result<WalkedValues, PartialWalk> {
+        let mut stream = std::pin::pin!(stream);
+        let mut values = BTreeMap::new();
+        while let Some(v) = stream.next().await {
+            match v {
+                Ok((oid, val)) => {
+                    values.insert(oid, val);
+                }
+                Err(error) => {
+                    let values = WalkedValues { values, tree };
+                    return Err(PartialWalk { values, error });
+                }
+            }
+        }
+        Ok(WalkedValues { values, tree })
+    }
+
+    /**
+     * Load values from a capture in the format produced by "snmpwalk -On",
+     * or by save().
+     */
+    pub fn load<R: std::io::Read>(
+        mut r: R,
+        tree: Arc<crate::oidtree::OidTree>,
+    ) -> Result<WalkedValues> {
+        let mut input = String::new();
+        r.read_to_string(&mut input).map_err(Error::Io)?;
+
+        let values = crate::capture::parse_snmpwalk(&input, &tree)?;
+        Ok(WalkedValues { values, tree })
+    }
+
+    /**
+     * Save these values in the format produced by "snmpwalk -On", so that
+     * they may be loaded again later with load().
+     */
+    pub fn save<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        crate::capture::write_snmpwalk(&mut w, self.values.iter())
+            .and_then(|()| w.flush())
+            .map_err(Error::Io)
+    }
+
+    /**
+     * Add the values from another walk, such as of a different subtree, to
+     * these.  Where both contain a value for the same OID, the other value is
+     * kept.
+     */
+    pub fn merge(&mut self, other: WalkedValues) {
+        self.values.extend(other.values);
+    }
+
+    /**
+     * Compare these values with a later set, such as the next poll of the
+     * same subtree, and report every OID that was added, removed or changed,
+     * in OID order.
+     */
+    pub fn diff(&self, newer: &WalkedValues) -> Vec<DiffEntry> {
+        let mut differences = BTreeMap::new();
+        for (oid, old) in self.values.iter() {
+            match newer.values.get(oid) {
+                None => {
+                    differences.insert(*oid, Difference::Removed(old.clone()));
+                }
+                Some(new) if new != old => {
+                    differences.insert(
+                        *oid,
+                        Difference::Changed {
+                            old: Box::new(old.clone()),
+                            new: Box::new(new.clone()),
+                        },
+                    );
+                }
+                Some(_) => (),
+            }
+        }
+        for (oid, new) in newer.values.iter() {
+            if !self.values.contains_key(oid) {
+                differences.insert(*oid, Difference::Added(new.clone()));
+            }
+        }
+
+        differences
+            .into_iter()
+            .map(|(oid, difference)| DiffEntry {
+                oid,
+                name: newer.tree.oid_name(oid).ok().map(|n| format!("{n:#}")),
+                difference,
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Oid, &Value)> {
+        self.values.iter()
+    }
+
+    pub fn get(&self, oid: &Oid) -> Option<&Value> {
+        self.values.get(oid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn tree(&self) -> &crate::oidtree::OidTree {
+        &self.tree
+    }
+
     pub fn extract_object<T>(
         &self,
         root: Oid,
@@ -26,8 +761,60 @@ impl WalkedValues {
     where
         T: for<'de> Deserialize<'de>,
     {
-        let map = self
-            .values
+        let fields = self.object_fields(root, strip_name_prefix)?;
+        extract::decode(&self.tree, root, None, None, fields, None)
+    }
+
+    /**
+     * Like extract_object(), but leave out any value that cannot be decoded
+     * rather than failing.  The object still cannot be extracted if a field
+     * that is not optional is left out.
+     */
+    pub fn extract_object_lenient<T>(
+        &self,
+        root: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<Extraction<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let fields = self.object_fields(root, strip_name_prefix)?;
+        let mut errors = Vec::new();
+        let value = extract::decode(
+            &self.tree,
+            root,
+            None,
+            None,
+            fields,
+            Some(&mut errors),
+        )?;
+        Ok(Extraction { value, errors, skipped: BTreeMap::new() })
+    }
+
+    /**
+     * Compare the objects under a root with the fields of a structure, as a
+     * debugging aid for when extract_object() does not do what is expected.
+     */
+    pub fn object_column_report<T>(
+        &self,
+        root: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<ColumnReport>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let fields = self.object_fields(root, strip_name_prefix)?;
+        Ok(ColumnReport::new::<T>(
+            fields.iter().map(|f| f.name.as_ref()).collect(),
+        ))
+    }
+
+    fn object_fields(
+        &self,
+        root: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<Vec<Field<'_>>> {
+        self.values
             .range(range_for_oid(root))
             .filter(|(oid, _)| {
                 let rel =
@@ -41,17 +828,26 @@ impl WalkedValues {
                 rel.len() == 2 && rel.get(1).unwrap() == 0
             })
             .map(|(oid, val)| {
-                let n = self.tree.oid_name(oid.parent().unwrap().into())?;
+                let col = oid.parent().unwrap().into();
+                let n = self.column_name(col)?;
                 let Some(n) = n.basename().strip_prefix(strip_name_prefix)
                 else {
-                    bail!("name {n} not prefixed with {strip_name_prefix:?}");
+                    return Err(Error::TableStructure {
+                        oid: Box::new(col),
+                        message: format!(
+                            "name {n} not prefixed with {strip_name_prefix:?}"
+                        ),
+                    });
                 };
 
-                Ok((n.to_string(), val))
+                Ok(Field {
+                    name: n.to_string().into(),
+                    group: None,
+                    oid: *oid,
+                    value: val,
+                })
             })
-            .collect::<Result<HashMap<String, &Value>>>()?;
-
-        Ok(T::deserialize(MapDeserializer::new(map.into_iter()))?)
+            .collect()
     }
 
     pub fn extract_table<T>(
@@ -63,62 +859,406 @@ impl WalkedValues {
     where
         T: for<'de> Deserialize<'de>,
     {
-        /*
-         * Get the size of the table from the results:
-         */
+        self.extract_table_with(
+            table_size,
+            table_entry,
+            strip_name_prefix,
+            &TableShape::default(),
+        )
+    }
+
+    /**
+     * Like extract_table(), but with the index of each row and any further
+     * tables joined into it decoded as the shape describes.
+     */
+    pub fn extract_table_with<T>(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        shape: &TableShape,
+    ) -> Result<BTreeMap<u32, T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.shaped_rows(table_size, table_entry, strip_name_prefix, shape)?
+            .into_iter()
+            .map(|(idx, fields)| {
+                let row = extract::decode(
+                    &self.tree,
+                    table_entry,
+                    Some(idx),
+                    shape.index_field.as_deref(),
+                    fields,
+                    None,
+                )?;
+                Ok((idx, row))
+            })
+            .collect::<Result<_>>()
+    }
+
+    /**
+     * Like extract_table_with(), but produce the rows in index order, for a
+     * structure that holds its own index.
+     */
+    pub fn extract_rows<T>(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        shape: &TableShape,
+    ) -> Result<Vec<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let rows = self.extract_table_with(
+            table_size,
+            table_entry,
+            strip_name_prefix,
+            shape,
+        )?;
+        Ok(rows.into_values().collect())
+    }
+
+    /**
+     * Like extract_table(), but leave out any value that cannot be decoded
+     * rather than failing, and skip any row that cannot be decoded without
+     * it.  The table must still have the expected structure.
+     */
+    pub fn extract_table_lenient<T>(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<Extraction<BTreeMap<u32, T>>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.extract_table_lenient_with(
+            table_size,
+            table_entry,
+            strip_name_prefix,
+            &TableShape::default(),
+        )
+    }
+
+    /**
+     * Like extract_table_lenient(), but with the rows shaped as for
+     * extract_table_with().  A value that cannot be decoded in a joined
+     * table is left out in the same way.
+     */
+    pub fn extract_table_lenient_with<T>(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        shape: &TableShape,
+    ) -> Result<Extraction<BTreeMap<u32, T>>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let rows = self.shaped_rows(
+            table_size,
+            table_entry,
+            strip_name_prefix,
+            shape,
+        )?;
+        Ok(self.decode_lenient(table_entry, shape.index_field.as_deref(), rows))
+    }
+
+    /**
+     * Like extract_table(), but with the columns resolved in advance by a
+     * plan, which is much cheaper when the same table is extracted from
+     * many walks.
+     */
+    pub fn extract_planned<T>(
+        &self,
+        plan: &TablePlan<T>,
+    ) -> Result<BTreeMap<u32, T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        self.planned_rows(plan)?
+            .into_iter()
+            .map(|(idx, fields)| {
+                let row = extract::decode(
+                    &self.tree,
+                    plan.table_entry,
+                    Some(idx),
+                    None,
+                    fields,
+                    None,
+                )?;
+                Ok((idx, row))
+            })
+            .collect::<Result<_>>()
+    }
+
+    /**
+     * Like extract_table_lenient(), but with the columns resolved in advance
+     * by a plan.
+     */
+    pub fn extract_planned_lenient<T>(
+        &self,
+        plan: &TablePlan<T>,
+    ) -> Result<Extraction<BTreeMap<u32, T>>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let rows = self.planned_rows(plan)?;
+        Ok(self.decode_lenient(plan.table_entry, None, rows))
+    }
+
+    fn decode_lenient<T>(
+        &self,
+        table_entry: Oid,
+        index_field: Option<&str>,
+        rows: BTreeMap<u32, Vec<Field<'_>>>,
+    ) -> Extraction<BTreeMap<u32, T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mut out = Extraction {
+            value: BTreeMap::new(),
+            errors: Vec::new(),
+            skipped: BTreeMap::new(),
+        };
+        for (idx, fields) in rows {
+            match extract::decode(
+                &self.tree,
+                table_entry,
+                Some(idx),
+                index_field,
+                fields,
+                Some(&mut out.errors),
+            ) {
+                Ok(row) => {
+                    out.value.insert(idx, row);
+                }
+                Err(e) => {
+                    out.skipped.insert(idx, e.to_string());
+                }
+            }
+        }
+        out
+    }
+
+    /**
+     * Compare the columns walked in a table with the fields of a structure,
+     * as a debugging aid for when extract_table() does not do what is
+     * expected.  A column is counted if it has a value in any row.
+     */
+    pub fn column_report<T>(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<ColumnReport>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let rows =
+            self.table_rows(table_size, table_entry, strip_name_prefix)?;
+        Ok(ColumnReport::new::<T>(
+            rows.values().flatten().map(|f| f.name.as_ref()).collect(),
+        ))
+    }
+
+    /**
+     * Collect the values in each row of a table, checking that the table has
+     * the expected structure and that every row is present.
+     */
+    fn table_rows(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+    ) -> Result<BTreeMap<u32, Vec<Field<'_>>>> {
+        let size = self.table_size(table_size)?;
+        let out = self.entry_rows(table_entry, strip_name_prefix, None)?;
+        check_rows(table_entry, size, &out)?;
+        Ok(out)
+    }
+
+    /**
+     * Collect the values in each row of a table as table_rows() does, but
+     * find the field for each value by its column number in the plan.
+     */
+    fn planned_rows<'a, T>(
+        &'a self,
+        plan: &'a TablePlan<T>,
+    ) -> Result<BTreeMap<u32, Vec<Field<'a>>>> {
+        let size = self.table_size(plan.table_size)?;
+
+        let entry = plan.table_entry;
+        let mut out: BTreeMap<u32, Vec<Field<'_>>> = BTreeMap::new();
+        for (oid, val) in self.values.range(range_for_oid(entry)) {
+            let rel = oid.relative_to(entry).expect("must be a child of oid");
+            if rel.len() != 2 || rel.get(1).unwrap() == 0 {
+                return Err(Error::TableStructure {
+                    oid: Box::new(*oid),
+                    message: format!("unexpected {rel} under {entry}"),
+                });
+            }
+
+            let (col, i) = (rel.get(0).unwrap(), rel.get(1).unwrap());
+            let Some(field) = plan.column(col) else {
+                return Err(Error::UnknownOid {
+                    oid: Box::new(oid.parent().unwrap().into()),
+                });
+            };
+            let row = out.entry(i).or_default();
+            if let Some(name) = field {
+                row.push(Field {
+                    name: name.into(),
+                    group: None,
+                    oid: *oid,
+                    value: val,
+                });
+            }
+        }
+
+        check_rows(entry, size, &out)?;
+        Ok(out)
+    }
+
+    /**
+     * Get the size of a table from the results.
+     */
+    fn table_size(&self, table_size: Oid) -> Result<u32> {
         let Some(size) = self.values.get(&table_size.child(0).unwrap().into())
         else {
-            bail!("could not locate table size at {table_size})");
+            return Err(Error::MissingTableSize { oid: Box::new(table_size) });
         };
 
-        let size: u32 = if let Some(i) = size.as_i32() {
-            i.try_into()
-                .map_err(|e| anyhow!("invalid size {i} at {table_size}: {e}"))?
-        } else {
-            bail!("invalid size {size:?} at {table_size}");
-        };
+        size.as_i32().and_then(|i| u32::try_from(i).ok()).ok_or_else(|| {
+            Error::InvalidTableSize {
+                oid: Box::new(table_size),
+                value: Box::new(size.clone()),
+            }
+        })
+    }
 
-        /*
-         * Collect entries from the table:
-         */
-        let mut out: BTreeMap<u32, HashMap<String, &Value>> = BTreeMap::new();
+    /**
+     * Collect the rows of a table as table_rows() does, then join the values
+     * in each of the groups in the shape into the row with the same index.
+     * Values in a group for which the table has no row are ignored.
+     */
+    fn shaped_rows(
+        &self,
+        table_size: Oid,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        shape: &TableShape,
+    ) -> Result<BTreeMap<u32, Vec<Field<'_>>>> {
+        let mut rows =
+            self.table_rows(table_size, table_entry, strip_name_prefix)?;
+
+        for g in shape.groups.iter() {
+            let group = g.field.as_deref();
+            for (i, fields) in
+                self.entry_rows(g.entry, &g.strip_name_prefix, group)?
+            {
+                let Some(row) = rows.get_mut(&i) else {
+                    continue;
+                };
+                for f in fields {
+                    if f.group.is_none()
+                        && row
+                            .iter()
+                            .any(|r| r.group.is_none() && r.name == f.name)
+                    {
+                        continue;
+                    }
+                    row.push(f);
+                }
+            }
+        }
+
+        Ok(rows)
+    }
+
+    /**
+     * Collect the values in each row of a table that has any, checking that
+     * the table has the expected structure.
+     */
+    fn entry_rows(
+        &self,
+        table_entry: Oid,
+        strip_name_prefix: &str,
+        group: Option<&str>,
+    ) -> Result<BTreeMap<u32, Vec<Field<'_>>>> {
+        let mut out: BTreeMap<u32, Vec<Field<'_>>> = BTreeMap::new();
         for (oid, val) in self.values.range(range_for_oid(table_entry)) {
             let rel =
                 oid.relative_to(table_entry).expect("must be a child of oid");
             if rel.len() != 2 || rel.get(1).unwrap() == 0 {
-                bail!("unusual table structure: {rel} under {oid}?");
+                return Err(Error::TableStructure {
+                    oid: Box::new(*oid),
+                    message: format!("unexpected {rel} under {table_entry}"),
+                });
             }
 
-            let n = self.tree.oid_name(oid.parent().unwrap().into())?;
+            let col = oid.parent().unwrap().into();
+            let n = self.column_name(col)?;
             let Some(n) = n.basename().strip_prefix(strip_name_prefix) else {
-                bail!("name {n} not prefixed with {strip_name_prefix:?}");
+                return Err(Error::TableStructure {
+                    oid: Box::new(col),
+                    message: format!(
+                        "name {n} not prefixed with {strip_name_prefix:?}"
+                    ),
+                });
             };
 
             let i = rel.get(1).unwrap();
-            let map = out.entry(i).or_default();
-            if map.insert(n.to_string(), val).is_some() {
-                bail!("duplicate {n:?}[{i}] value?");
+            let row = out.entry(i).or_default();
+            if row.iter().any(|f| f.name == n) {
+                return Err(Error::TableStructure {
+                    oid: Box::new(*oid),
+                    message: format!("duplicate {n:?}[{i}] value"),
+                });
             }
+            row.push(Field {
+                name: n.to_string().into(),
+                group: group.map(str::to_string),
+                oid: *oid,
+                value: val,
+            });
         }
 
-        for i in 1..=size {
-            if !out.contains_key(&i) {
-                bail!("table is missing index {i}?");
-            }
+        Ok(out)
+    }
+
+    /**
+     * Look up the name of an object or table column, which must be a named
+     * node in the tree rather than an instance beneath one.
+     */
+    fn column_name(&self, oid: Oid) -> Result<crate::oidtree::OidName> {
+        let n = self.tree.oid_name(oid)?;
+        if !n.instance().is_empty() {
+            return Err(Error::UnknownOid { oid: Box::new(oid) });
         }
+        Ok(n)
+    }
+}
 
-        /*
-         * Deserialise the results!
-         */
-        out.into_iter()
-            .map(|(idx, map)| {
-                Ok((
-                    idx,
-                    T::deserialize(MapDeserializer::new(map.into_iter()))?,
-                ))
-            })
-            .collect::<Result<_>>()
+/**
+ * Check that every row of a table of the given size is present.
+ */
+fn check_rows<F>(
+    table_entry: Oid,
+    size: u32,
+    rows: &BTreeMap<u32, F>,
+) -> Result<()> {
+    for i in 1..=size {
+        if !rows.contains_key(&i) {
+            return Err(Error::MissingRow {
+                table: Box::new(table_entry),
+                index: i,
+            });
+        }
     }
+    Ok(())
 }
 
 /**
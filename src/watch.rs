@@ -0,0 +1,342 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+/*!
+ * Changes between successive polls of a device, as typed events: an outlet
+ * turning on or off, a bank moving into a different load state, the device
+ * rebooting, and so on.  For changes to values that have no typed event,
+ * WalkedValues::diff() compares any two walks.
+ */
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use futures::{stream, Stream};
+use tokio::time::Instant;
+
+use crate::mib::apc::{
+    CommandPending, Group, LoadState, OutletAddress, Pdu, State,
+};
+use crate::mib::mib_2::System;
+use crate::{Client, Error, Result};
+
+/**
+ * The groups watch() fetches unless told otherwise: enough to report outlet
+ * state, pending commands and bank load states.
+ */
+pub const DEFAULT_GROUPS: [Group; 2] = [Group::OutletStatus, Group::BankStatus];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /**
+     * The uptime of the device is less than the previous poll would lead us
+     * to expect, so it has restarted since that poll.
+     */
+    Rebooted {
+        old_uptime: Duration,
+        new_uptime: Duration,
+    },
+    /**
+     * One of the administrative strings of the system group changed; the
+     * field is "name", "contact" or "location".
+     */
+    SystemChanged {
+        field: &'static str,
+        old: String,
+        new: String,
+    },
+    OutletState {
+        address: OutletAddress,
+        old: State,
+        new: State,
+    },
+    CommandPending {
+        address: OutletAddress,
+        old: CommandPending,
+        new: CommandPending,
+    },
+    /**
+     * The load state of a metered outlet changed.
+     */
+    OutletLoadState {
+        address: OutletAddress,
+        old: LoadState,
+        new: LoadState,
+    },
+    BankLoadState {
+        module: u32,
+        bank: u32,
+        old: LoadState,
+        new: LoadState,
+    },
+}
+
+/**
+ * How far the uptime of a device may fall short of what the time between two
+ * polls leads us to expect before we decide that it has restarted.  This
+ * allows for the time taken to fetch the system group, plus a little for
+ * every minute between polls to allow for clock drift.
+ */
+const UPTIME_SLACK: Duration = Duration::from_secs(2);
+
+/**
+ * The additional slack allowed for each minute between polls is a tenth of
+ * a second: this is the divisor that gives it from the time between polls.
+ */
+const UPTIME_DRIFT_DIVISOR: u32 = 600;
+
+/**
+ * Compare two polls of the system group.  If the time between the polls is
+ * known, the device has restarted if its uptime is less than the old uptime
+ * plus that time (less some slack), even if the uptime did not go backwards;
+ * and an uptime that went backwards only because the counter wrapped around
+ * (after about 497 days) is not reported as a reboot.  Otherwise, only an
+ * uptime that went backwards is reported.
+ */
+pub fn system_events(
+    old: &System,
+    new: &System,
+    elapsed: Option<Duration>,
+) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    let (old_uptime, new_uptime) = (old.uptime(), new.uptime());
+    let rebooted = match elapsed {
+        Some(elapsed) => {
+            /*
+             * sysUpTime counts hundredths of a second in 32 bits.
+             */
+            let wrap = Duration::from_millis(10 << 32);
+            let slack = UPTIME_SLACK + elapsed / UPTIME_DRIFT_DIVISOR;
+            let expected = old_uptime + elapsed;
+            if expected + slack >= wrap {
+                /*
+                 * The counter may or may not have wrapped by now.  If it has
+                 * not, it has gone backwards only if the device restarted;
+                 * if it has, it should be about the expected uptime less the
+                 * wrap.
+                 */
+                new_uptime < old_uptime
+                    && new_uptime + slack < expected.saturating_sub(wrap)
+            } else {
+                new_uptime + slack < expected
+            }
+        }
+        None => new_uptime < old_uptime,
+    };
+    if rebooted {
+        events.push(Event::Rebooted { old_uptime, new_uptime });
+    }
+
+    for (field, o, n) in [
+        ("name", old.name(), new.name()),
+        ("contact", old.contact(), new.contact()),
+        ("location", old.location(), new.location()),
+    ] {
+        if o != n {
+            events.push(Event::SystemChanged {
+                field,
+                old: o.to_string(),
+                new: n.to_string(),
+            });
+        }
+    }
+
+    events
+}
+
+/**
+ * Compare two polls of a PDU.  Only tables that were fetched in both polls
+ * are compared, and outlets or banks that appear in only one poll are
+ * ignored.
+ */
+pub fn pdu_events(old: &Pdu, new: &Pdu) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+
+    if let (Some(old), Some(new)) =
+        (fetched(old.outlet_status())?, fetched(new.outlet_status())?)
+    {
+        let old = old
+            .into_values()
+            .map(|s| (OutletAddress::new(s.module, s.number), s))
+            .collect::<BTreeMap<_, _>>();
+        for n in new.into_values() {
+            let address = OutletAddress::new(n.module, n.number);
+            let Some(o) = old.get(&address) else {
+                continue;
+            };
+            if o.state != n.state {
+                events.push(Event::OutletState {
+                    address,
+                    old: o.state,
+                    new: n.state,
+                });
+            }
+            if o.command_pending != n.command_pending {
+                events.push(Event::CommandPending {
+                    address,
+                    old: o.command_pending,
+                    new: n.command_pending,
+                });
+            }
+        }
+    }
+
+    if let (Some(old), Some(new)) = (
+        fetched(old.outlet_metered_status())?,
+        fetched(new.outlet_metered_status())?,
+    ) {
+        let old = old
+            .into_values()
+            .map(|m| (OutletAddress::new(m.module, m.number), m.state))
+            .collect::<BTreeMap<_, _>>();
+        for n in new.into_values() {
+            let address = OutletAddress::new(n.module, n.number);
+            match old.get(&address) {
+                Some(o) if *o != n.state => {
+                    events.push(Event::OutletLoadState {
+                        address,
+                        old: *o,
+                        new: n.state,
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    if let (Some(old), Some(new)) =
+        (fetched(old.bank_status())?, fetched(new.bank_status())?)
+    {
+        let old = old
+            .into_values()
+            .map(|b| ((b.module, b.number), b.load_state))
+            .collect::<BTreeMap<_, _>>();
+        for n in new.into_values() {
+            match old.get(&(n.module, n.number)) {
+                Some(o) if *o != n.load_state => {
+                    events.push(Event::BankLoadState {
+                        module: n.module,
+                        bank: n.number,
+                        old: *o,
+                        new: n.load_state,
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+/**
+ * A table that was not fetched is not an error when looking for changes.
+ */
+fn fetched<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(t) => Ok(Some(t)),
+        Err(Error::NotFetched { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+struct Poll {
+    at: Instant,
+    system: System,
+    pdu: Pdu,
+}
+
+struct Watcher<'a> {
+    snmp: &'a Client,
+    groups: Vec<Group>,
+    period: Duration,
+    /*
+     * Created on the first poll, because tokio::time::interval() needs a
+     * runtime, and the stream may be built outside one.
+     */
+    interval: Option<tokio::time::Interval>,
+    previous: Option<Poll>,
+    events: VecDeque<Event>,
+}
+
+impl Watcher<'_> {
+    async fn poll(&mut self) -> Result<()> {
+        let period = self.period;
+        let interval = self.interval.get_or_insert_with(|| {
+            let mut i = tokio::time::interval(period);
+            i.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            i
+        });
+        interval.tick().await;
+
+        /*
+         * The time of the poll is taken as soon as we have the uptime, so
+         * that the time taken to fetch the PDU tables does not count.
+         */
+        let system = System::from_client(self.snmp).await?;
+        let at = Instant::now();
+        let pdu = Pdu::fetch(self.snmp, &self.groups).await?;
+        let poll = Poll { at, system, pdu };
+
+        if let Some(prev) = &self.previous {
+            /*
+             * Work out all of the events before queueing any, so that if the
+             * comparison fails, the next poll is compared against the same
+             * previous poll without any events being reported twice.
+             */
+            let elapsed = poll.at.duration_since(prev.at);
+            let mut events =
+                system_events(&prev.system, &poll.system, Some(elapsed));
+            events.extend(pdu_events(&prev.pdu, &poll.pdu)?);
+            self.events.extend(events);
+        }
+        self.previous = Some(poll);
+        Ok(())
+    }
+}
+
+/**
+ * Poll the system group and the provided groups of PDU tables at an
+ * interval, producing an event for each change between one poll and the
+ * next.  The first poll happens straight away, and provides the state that
+ * the second is compared against.  A poll that fails produces an error, and
+ * the next poll is compared against the last one that succeeded; the stream
+ * never ends of its own accord.
+ *
+ * The interval must not be zero.  The stream must be polled from within a
+ * Tokio runtime, but it may be created outside one.
+ */
+pub fn watch<'a>(
+    snmp: &'a Client,
+    groups: &[Group],
+    interval: Duration,
+) -> Result<impl Stream<Item = Result<Event>> + 'a> {
+    if interval.is_zero() {
+        return Err(Error::InvalidSetting {
+            name: "watch interval".into(),
+            message: "must not be zero".into(),
+        });
+    }
+
+    let w = Watcher {
+        snmp,
+        groups: groups.to_vec(),
+        period: interval,
+        interval: None,
+        previous: None,
+        events: VecDeque::new(),
+    };
+
+    Ok(stream::unfold(w, |mut w| async move {
+        loop {
+            if let Some(e) = w.events.pop_front() {
+                return Some((Ok(e), w));
+            }
+            if let Err(e) = w.poll().await {
+                return Some((Err(e), w));
+            }
+        }
+    }))
+}
@@ -0,0 +1,58 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use std::collections::BTreeMap;
+
+use serde::{de::IntoDeserializer, Deserialize};
+
+use sandgate::{csnmp::ObjectValue, mib, value::Value, walk::WalkedValues};
+
+/*
+ * The values of a capture of scalars under the experimental arc, such as
+ * "iso.3.6.1.3.1.N.0", by N.
+ */
+fn experimental(walk: &WalkedValues) -> BTreeMap<u32, &Value> {
+    walk.iter()
+        .map(|(oid, value)| match oid.as_slice() {
+            [1, 3, 6, 1, 3, 1, n, 0] => (*n, value),
+            _ => panic!("unexpected OID {oid}"),
+        })
+        .collect()
+}
+
+#[test]
+fn capture_round_trip() {
+    let capture = "\
+        .1.3.6.1.3.1.1.0 = INTEGER: -7\n\
+        .1.3.6.1.3.1.2.0 = STRING: \"say \\\"hi\\\" \\\\ bye\"\n\
+        .1.3.6.1.3.1.3.0 = STRING: \"first\r\nsecond\nthird\"\r\n\
+        .1.3.6.1.3.1.4.0 = STRING: \"\"\n\
+        .1.3.6.1.3.1.5.0 = Hex-STRING: 00 C0 B7 D4 1A 2B\n\
+        .1.3.6.1.3.1.6.0 = OID: .1.3.6.1.2.1.1\n\
+        .1.3.6.1.3.1.7.0 = IpAddress: 192.0.2.7\n\
+        .1.3.6.1.3.1.8.0 = Counter32: 4000000000\n\
+        .1.3.6.1.3.1.9.0 = Gauge32: 12\n\
+        .1.3.6.1.3.1.10.0 = Timeticks: (8640123) 1 day, 0:00:01.23\n\
+        .1.3.6.1.3.1.11.0 = OPAQUE:\n\
+        .1.3.6.1.3.1.12.0 = Opaque: Float: 1.5\n\
+        .1.3.6.1.3.1.13.0 = OPAQUE: 01 02 03\n\
+        .1.3.6.1.3.1.14.0 = Counter64: 18446744073709551615\n";
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    assert_eq!(walk.len(), 14);
+
+    let mut saved = Vec::new();
+    walk.save(&mut saved).unwrap();
+    let again = WalkedValues::load(saved.as_slice(), mib::bundled()).unwrap();
+    assert!(walk.diff(&again).is_empty(), "{:?}", walk.diff(&again));
+
+    let values = experimental(&again);
+    let value = |n| values[&n].into_deserializer();
+    assert_eq!(String::deserialize(value(2)).unwrap(), r#"say "hi" \ bye"#);
+    assert_eq!(
+        String::deserialize(value(3)).unwrap(),
+        "first\r\nsecond\nthird"
+    );
+    assert!(matches!(&**values[&11], ObjectValue::Opaque(b) if b.is_empty()));
+    assert_eq!(f64::deserialize(value(12)).unwrap(), 1.5);
+}
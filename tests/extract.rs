@@ -0,0 +1,343 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use serde::Deserialize;
+
+use sandgate::{
+    extract::{TableGroup, TablePlan, TableShape},
+    mib::{
+        self,
+        apc::{
+            BankStatus, CommandPending, Group, OutletCommand, OutletStatus,
+            State,
+        },
+    },
+    value::{OrUnknown, Scaled},
+    walk::WalkedValues,
+    Error,
+};
+
+const OUTLETS: &str = include_str!("data/rpdu2-outlets.txt");
+
+/*
+ * Outlet 2 of a capture reporting a state that is not in the MIB.
+ */
+fn bad_state(capture: &str) -> String {
+    capture.replace(
+        "StatusState.2 = INTEGER: off(1)",
+        "StatusState.2 = INTEGER: 9",
+    )
+}
+
+#[test]
+fn scaled_bank_current() {
+    /*
+     * APC reports the current drawn by a bank in tenths of amps:
+     */
+    let walk = WalkedValues::load(OUTLETS.as_bytes(), mib::bundled()).unwrap();
+    let tree = walk.tree();
+    let banks = walk
+        .extract_table::<BankStatus>(
+            tree.oid_by_name("rPDU2BankTableSize").unwrap(),
+            tree.oid_by_name("rPDU2BankStatusEntry").unwrap(),
+            "rPDU2BankStatus",
+        )
+        .unwrap();
+    assert_eq!(banks[&1].current.value(), 9.5);
+    assert_eq!(banks[&1].peak_current.to_string(), "11.0");
+}
+
+#[test]
+fn lenient_extraction() {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Status {
+        state: OrUnknown<State>,
+        command_pending: Option<CommandPending>,
+    }
+
+    let capture = bad_state(OUTLETS).replace(
+        "StatusCommandPending.1 = INTEGER: commandPendingFalse(2)",
+        "StatusCommandPending.1 = INTEGER: 7",
+    );
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let tree = walk.tree();
+    let (size, entry, prefix) = Group::OutletStatus.table_oids(tree).unwrap();
+
+    let e = walk.extract_table::<OutletStatus>(size, entry, prefix);
+    assert!(matches!(e, Err(Error::DecodeField(_))));
+
+    /*
+     * An unknown state is kept, and a value that cannot be decoded into an
+     * optional field is left out.
+     */
+    let status =
+        walk.extract_table_lenient::<Status>(size, entry, prefix).unwrap();
+    assert_eq!(status.value[&1].state, OrUnknown::Known(State::On));
+    assert_eq!(status.value[&2].state, OrUnknown::Unknown(9));
+    assert_eq!(status.value[&1].command_pending, None);
+    assert_eq!(status.value[&2].command_pending, Some(CommandPending::No));
+    assert_eq!(status.errors.len(), 1);
+    assert_eq!(status.errors[0].field, "CommandPending");
+    assert_eq!(
+        status.errors[0].oid,
+        tree.oid_by_name("rPDU2OutletSwitchedStatusCommandPending.1").unwrap()
+    );
+    assert!(status.skipped.is_empty());
+    assert!(!status.is_complete());
+
+    /*
+     * A row that cannot be decoded without the value is skipped.
+     */
+    let status = walk
+        .extract_table_lenient::<OutletStatus>(size, entry, prefix)
+        .unwrap();
+    assert!(status.value.is_empty());
+    assert_eq!(status.errors.len(), 2);
+    assert_eq!(status.skipped.keys().copied().collect::<Vec<_>>(), [1, 2]);
+    assert!(status.skipped[&2].contains("missing field `State`"));
+}
+
+#[test]
+fn extraction_diagnostics() {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    #[allow(unused)]
+    struct Status {
+        state: State,
+        colour: Option<String>,
+    }
+
+    let capture = bad_state(OUTLETS);
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let tree = walk.tree();
+    let (size, entry, prefix) = Group::OutletStatus.table_oids(tree).unwrap();
+
+    let Err(Error::DecodeField(fe)) =
+        walk.extract_table::<Status>(size, entry, prefix)
+    else {
+        panic!("expected a field error");
+    };
+    assert_eq!(
+        fe.oid,
+        tree.oid_by_name("rPDU2OutletSwitchedStatusState.2").unwrap()
+    );
+    assert_eq!(fe.name.as_deref(), Some("rPDU2OutletSwitchedStatusState[2]"));
+    assert_eq!(fe.index, Some(2));
+    assert_eq!(fe.field, "State");
+    assert_eq!(format!("{:?}", fe.value), "9");
+    assert!(Error::DecodeField(fe)
+        .to_string()
+        .contains("rPDU2OutletSwitchedStatusState[2]"));
+
+    let report = walk.column_report::<Status>(size, entry, prefix).unwrap();
+    assert_eq!(
+        report.ignored,
+        ["CommandPending", "ExternalLink", "Index", "Module", "Name", "Number"]
+    );
+    assert_eq!(report.missing, ["Colour"]);
+
+    /*
+     * A column that is not text can be ignored.
+     */
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Descr {
+        descr: String,
+    }
+
+    let interfaces = tree.oid_by_name("interfaces").unwrap();
+    let ifs = walk
+        .extract_table::<Descr>(
+            tree.oid_by_name_under(interfaces, "ifNumber").unwrap(),
+            tree.oid_by_name("ifEntry").unwrap(),
+            "if",
+        )
+        .unwrap();
+    assert_eq!(ifs[&1].descr, "eth0");
+}
+
+#[test]
+fn table_shapes() {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Outlet {
+        outlet: u32,
+        name: String,
+        power_on_time: i32,
+        command: OutletCommand,
+        status: Status,
+        metered: Option<Metered>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Status {
+        index: u32,
+        state: State,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Metered {
+        current: Scaled<1>,
+    }
+
+    /*
+     * An agent that has no index column in the configuration table, and
+     * meters only the first outlet:
+     */
+    let capture = OUTLETS
+        .lines()
+        .filter(|l| !l.contains("SwitchedConfigIndex"))
+        .filter(|l| !(l.contains("MeteredStatus") && l.contains(".2 = ")))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let tree = walk.tree();
+    let table = |group: Group| group.table_oids(tree).unwrap();
+    let (size, entry, config) = table(Group::OutletConfig);
+    let (_, status, prefix) = table(Group::OutletStatus);
+    let (_, metered, metered_prefix) = table(Group::OutletMeteredStatus);
+    let (_, control, control_prefix) = table(Group::OutletControl);
+    let shape = TableShape {
+        index_field: Some("Outlet".into()),
+        groups: vec![
+            TableGroup::nested("Status", status, prefix),
+            TableGroup::nested("Metered", metered, metered_prefix),
+            TableGroup::flattened(control, control_prefix),
+        ],
+    };
+
+    let outlets =
+        walk.extract_rows::<Outlet>(size, entry, config, &shape).unwrap();
+    assert_eq!(outlets.len(), 2);
+    let (web, db) = (&outlets[0], &outlets[1]);
+    assert_eq!((web.outlet, web.name.as_str()), (1, "web"));
+    assert_eq!((db.outlet, db.name.as_str()), (2, "db"));
+    assert_eq!(db.power_on_time, 15);
+    assert_eq!(web.command, OutletCommand::ImmediateOn);
+    assert_eq!(db.command, OutletCommand::ImmediateOff);
+    assert_eq!((db.status.index, db.status.state), (2, State::Off));
+    assert_eq!(web.metered.as_ref().unwrap().current.value(), 1.2);
+    assert!(db.metered.is_none());
+
+    /*
+     * The index column is ignored in favour of the index of the row:
+     */
+    #[derive(Deserialize)]
+    struct Indexed {
+        #[serde(rename = "Index")]
+        index: u32,
+    }
+
+    let shape =
+        TableShape { index_field: Some("Index".into()), ..Default::default() };
+    let rows = walk
+        .extract_table_with::<Indexed>(size, status, prefix, &shape)
+        .unwrap();
+    assert_eq!(rows[&2].index, 2);
+
+    /*
+     * A value in a nested group that cannot be decoded is named by the path
+     * to its field:
+     */
+    let capture = bad_state(&capture);
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let shape = TableShape {
+        groups: vec![TableGroup::nested("Status", status, prefix)],
+        ..Default::default()
+    };
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    #[allow(unused)]
+    struct Nested {
+        status: Status,
+    }
+
+    let Err(Error::DecodeField(fe)) =
+        walk.extract_table_with::<Nested>(size, entry, config, &shape)
+    else {
+        panic!("expected a field error");
+    };
+    assert_eq!(fe.field, "Status.State");
+    assert_eq!(fe.index, Some(2));
+
+    /*
+     * Lenient extraction takes a shape too, and skips only the row with the
+     * bad value.
+     */
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Lenient {
+        outlet: u32,
+        status: Status,
+    }
+
+    let shape = TableShape { index_field: Some("Outlet".into()), ..shape };
+    let ex = walk
+        .extract_table_lenient_with::<Lenient>(size, entry, config, &shape)
+        .unwrap();
+    assert_eq!(ex.value.keys().collect::<Vec<_>>(), [&1]);
+    assert_eq!(
+        (ex.value[&1].outlet, ex.value[&1].status.state),
+        (1, State::On)
+    );
+    assert_eq!(ex.errors.len(), 1);
+    assert_eq!(ex.errors[0].field, "Status.State");
+    assert!(ex.skipped.contains_key(&2));
+}
+
+#[test]
+fn planned_extraction() {
+    let walk = WalkedValues::load(OUTLETS.as_bytes(), mib::bundled()).unwrap();
+    let tree = walk.tree();
+    let (size, entry, prefix) = Group::OutletStatus.table_oids(tree).unwrap();
+
+    let plan =
+        TablePlan::<OutletStatus>::new(tree, size, entry, prefix).unwrap();
+    let planned = walk.extract_planned(&plan).unwrap();
+    let unplanned =
+        walk.extract_table::<OutletStatus>(size, entry, prefix).unwrap();
+    assert_eq!(format!("{planned:?}"), format!("{unplanned:?}"));
+
+    /*
+     * The plan does not depend on the values, so it can be used with the
+     * next poll; failures are reported as for an unplanned extraction.
+     */
+    let capture = bad_state(OUTLETS);
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let Err(Error::DecodeField(fe)) = walk.extract_planned(&plan) else {
+        panic!("expected a field error");
+    };
+    assert_eq!(fe.name.as_deref(), Some("rPDU2OutletSwitchedStatusState[2]"));
+    assert_eq!((fe.index, fe.field.as_str()), (Some(2), "State"));
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Status {
+        state: OrUnknown<State>,
+    }
+
+    let plan = TablePlan::<Status>::new(tree, size, entry, prefix).unwrap();
+    let status = walk.extract_planned_lenient(&plan).unwrap();
+    assert!(status.is_complete());
+    assert_eq!(status.value[&2].state, OrUnknown::Unknown(9));
+
+    let capture = OUTLETS
+        .lines()
+        .filter(|l| !l.contains("SwitchedStatusState.2"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let status = walk.extract_planned_lenient(&plan).unwrap();
+    assert_eq!(status.value.keys().copied().collect::<Vec<_>>(), [1]);
+    assert!(status.skipped[&2].contains("missing field `State`"));
+
+    assert!(matches!(
+        TablePlan::<Status>::new(tree, size, entry, "rPDU2Bank"),
+        Err(Error::TableStructure { .. })
+    ));
+}
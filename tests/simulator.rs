@@ -0,0 +1,1161 @@
+/*
+ * Copyright 2024 Oxide Computer Company
+ */
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use serde::{de::IntoDeserializer, Deserialize};
+
+use sandgate::{
+    audit::{AuditContext, AuditRecord, AuditSink, JsonLinesSink, MemorySink},
+    csnmp::message::ErrorStatus,
+    futures::{StreamExt, TryStreamExt},
+    mib::{
+        self,
+        apc::{
+            BankConfigChange, BatchOptions, CommandOutcome, CommandPending,
+            DeviceCommand, Group, LoadState, OutletAddress, OutletCommand,
+            OutletConfigChange, OutletMap, OutletStatus, Pdu, State,
+        },
+        mib_2::{IfOperStatus, IfType, Interface, System},
+    },
+    policy::{GuardOptions, LoadLimit, Policy, Refusal},
+    reconcile::{reconcile, DesiredState, Field, Plan, Setting, Target},
+    simulator::{self, Fault, Simulator, SimulatorBuilder},
+    tc::{
+        DateAndTime, InetAddr, InetAddress, InetAddressType, MacAddress,
+        RowStatus, StorageType, TimeStamp,
+    },
+    value::{Scaled, Value},
+    walk::{Difference, WalkOptions, WalkedValues},
+    watch::{self, watch, Event},
+    Client, Error, Exception,
+};
+
+const OUTLETS: &str = include_str!("data/rpdu2-outlets.txt");
+const CHAIN: &str = include_str!("data/rpdu2-chain.txt");
+
+/*
+ * The values of a capture of scalars under the experimental arc, such as
+ * "iso.3.6.1.3.1.N.0", by N.
+ */
+fn experimental(walk: &WalkedValues) -> BTreeMap<u32, &Value> {
+    walk.iter()
+        .map(|(oid, value)| match oid.as_slice() {
+            [1, 3, 6, 1, 3, 1, n, 0] => (*n, value),
+            _ => panic!("unexpected OID {oid}"),
+        })
+        .collect()
+}
+
+async fn start() -> (Simulator, Client) {
+    start_with(|_| ()).await
+}
+
+async fn start_with(
+    configure: impl FnOnce(&mut SimulatorBuilder),
+) -> (Simulator, Client) {
+    start_capture(OUTLETS, configure).await
+}
+
+async fn start_capture(
+    capture: &str,
+    configure: impl FnOnce(&mut SimulatorBuilder),
+) -> (Simulator, Client) {
+    let tree = mib::bundled();
+
+    let mut b = Simulator::builder();
+    b.capture(capture, &tree).unwrap().on_set(
+        simulator::apc_outlet_commands(&tree, Duration::from_millis(100))
+            .unwrap(),
+    );
+    configure(&mut b);
+    let sim = b.start().await.unwrap();
+
+    let client = Client::builder()
+        .port(sim.addr().port())
+        .timeout(Duration::from_millis(500))
+        .oid_tree(tree)
+        .build(sim.addr().ip())
+        .await
+        .unwrap();
+
+    (sim, client)
+}
+
+#[tokio::test]
+async fn walk_outlet_tables() {
+    let (_sim, client) = start().await;
+
+    let pdu = Pdu::from_client(&client).await.unwrap();
+    let status = pdu.outlet_status().unwrap();
+    assert_eq!(status.len(), 2);
+    assert_eq!(status[&1].name, "web");
+    assert_eq!(status[&1].state, State::On);
+    assert_eq!(status[&2].state, State::Off);
+
+    let control = pdu.outlet_control().unwrap();
+    assert_eq!(control[&2].command, OutletCommand::ImmediateOff);
+}
+
+#[tokio::test]
+async fn joined_outlets() {
+    let (sim, client) = start().await;
+
+    let outlets = Pdu::from_client(&client).await.unwrap().outlets().unwrap();
+    assert_eq!(outlets.len(), 2);
+    assert!(outlets.inconsistencies().is_empty());
+
+    let db = outlets.by_name("db").unwrap();
+    assert_eq!((db.index, db.module, db.number, db.bank), (2, 1, 2, 1));
+    assert_eq!(db.status.state, State::Off);
+    assert_eq!(db.config.power_on_time, 15);
+    assert_eq!(db.metered.as_ref().unwrap().energy, Scaled(211));
+    assert_eq!(outlets.by_module(1, 1).unwrap().name, "web");
+    assert_eq!(outlets.by_number(1).unwrap().name, "web");
+    assert!(outlets.by_module(2, 1).is_none());
+
+    /*
+     * Rename an outlet in only one of the tables, as if it were renamed
+     * part of the way through a walk.
+     */
+    let tree = client.tree();
+    let web = tree.oid_by_name("rPDU2OutletSwitchedControlName.1").unwrap();
+    let db = tree.oid_by_name("rPDU2OutletSwitchedControlName.2").unwrap();
+    sim.set(db, sim.agent().values()[&web].clone());
+
+    let outlets =
+        Pdu::fetch(&client, &Group::OUTLETS).await.unwrap().outlets().unwrap();
+    assert_eq!(outlets.len(), 1);
+    assert_eq!(outlets.inconsistencies().len(), 1);
+    assert_eq!(outlets.inconsistencies()[0].index, 2);
+}
+
+#[tokio::test]
+async fn daisy_chain_addressing() {
+    let (_sim, client) = start_capture(CHAIN, |_| ()).await;
+
+    let map = OutletMap::fetch(&client).await.unwrap();
+    assert_eq!(map.index(OutletAddress::new(2, 1)).unwrap(), 3);
+    assert_eq!(map.address(4), Some(OutletAddress::new(2, 2)));
+
+    let b2 = OutletAddress::new(2, 2);
+    Pdu::send_command_at(&client, b2, OutletCommand::ImmediateOff)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let polled = Pdu::poll_outlets_at(&client, &[OutletAddress::new(1, 2), b2])
+        .await
+        .unwrap();
+    assert_eq!(polled[&OutletAddress::new(1, 2)].state, Some(State::On));
+    assert_eq!(polled[&b2].state, Some(State::Off));
+
+    let e = Pdu::send_command_at(
+        &client,
+        OutletAddress::new(3, 1),
+        OutletCommand::ImmediateOff,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(e, Error::NoSuchModule { module: 3 }), "{e}");
+    let e = Pdu::poll_outlets_at(&client, &[OutletAddress::new(2, 9)])
+        .await
+        .unwrap_err();
+    assert!(matches!(e, Error::NoSuchOutlet { module: 2, number: 9 }), "{e}");
+
+    let pdu = Pdu::from_client(&client).await.unwrap();
+    assert_eq!(pdu.modules().unwrap().into_iter().collect::<Vec<_>>(), [1, 2]);
+    let second = pdu.module(2).unwrap();
+    let outlets = second.outlets().unwrap();
+    assert_eq!(outlets.len(), 2);
+    assert_eq!(outlets.by_number(1).unwrap().name, "b1");
+    assert_eq!(outlets.by_number(2).unwrap().status.state, State::Off);
+    assert!(matches!(pdu.module(5), Err(Error::NoSuchModule { module: 5 })));
+    second.ident().unwrap();
+
+    /*
+     * Two rows for the same outlet leave its index ambiguous.
+     */
+    let capture = CHAIN.replace(
+        "rPDU2OutletSwitchedControlNumber.4 = INTEGER: 2",
+        "rPDU2OutletSwitchedControlNumber.4 = INTEGER: 1",
+    );
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let e = Pdu::from_walk(walk).unwrap().outlet_map().unwrap_err();
+    match e {
+        Error::TableStructure { message, .. } => {
+            assert_eq!(message, "module 2 outlet 1 appears at indexes 3 and 4")
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn batch_commands() {
+    let (_sim, client) = start().await;
+
+    let report = Pdu::send_commands(
+        &client,
+        [(1, OutletCommand::ImmediateOff), (2, OutletCommand::ImmediateOn)],
+        &BatchOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert!(report.is_complete(), "{report:?}");
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let polled = Pdu::poll_outlets(&client, &[1, 2]).await.unwrap();
+    assert_eq!(polled[&1].state, Some(State::Off));
+    assert_eq!(polled[&2].state, Some(State::On));
+}
+
+#[tokio::test]
+async fn batch_commands_roll_back() {
+    let (sim, client) = start().await;
+    let commands = [
+        (1, OutletCommand::ImmediateOff),
+        (2, OutletCommand::ImmediateOn),
+        (9, OutletCommand::ImmediateOff),
+    ];
+
+    let options = BatchOptions { max_varbinds: 2, ..Default::default() };
+    let report = Pdu::send_commands(&client, commands, &options).await.unwrap();
+    assert!(!report.is_complete());
+    assert_eq!(report.outcomes[&1], CommandOutcome::RolledBack);
+    assert_eq!(report.outcomes[&2], CommandOutcome::RolledBack);
+    assert_eq!(report.outcomes[&9], CommandOutcome::Failed);
+    let e = report.error.unwrap();
+    assert_eq!(e.agent_status(), Some(ErrorStatus::NotWritable));
+    assert!(report.rollback_error.is_none());
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let polled = Pdu::poll_outlets(&client, &[1, 2]).await.unwrap();
+    assert_eq!(polled[&1].state, Some(State::On));
+    assert_eq!(polled[&2].state, Some(State::Off));
+
+    let no_rollback = BatchOptions { rollback: false, ..options.clone() };
+    let report =
+        Pdu::send_commands(&client, commands, &no_rollback).await.unwrap();
+    assert_eq!(report.outcomes[&1], CommandOutcome::Sent);
+    assert_eq!(report.outcomes[&9], CommandOutcome::Failed);
+
+    /*
+     * If the second request times out, the PDU may have acted on it, so its
+     * outlets are rolled back too.  The poll of the prior state and the
+     * first request go through.
+     */
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    sim.inject(Fault::Delay(Duration::ZERO));
+    sim.inject(Fault::Delay(Duration::ZERO));
+    sim.inject(Fault::Drop);
+    let options = BatchOptions { max_varbinds: 1, ..options };
+    let commands =
+        [(1, OutletCommand::ImmediateOn), (2, OutletCommand::ImmediateOn)];
+    let report = Pdu::send_commands(&client, commands, &options).await.unwrap();
+    assert!(report.error.unwrap().is_timeout());
+    assert_eq!(report.outcomes[&1], CommandOutcome::RolledBack);
+    assert_eq!(report.outcomes[&2], CommandOutcome::RolledBack);
+
+    sim.inject(Fault::Drop);
+    let report =
+        Pdu::send_commands(&client, commands, &no_rollback).await.unwrap();
+    assert_eq!(report.outcomes[&1], CommandOutcome::Unknown);
+    assert_eq!(report.outcomes[&2], CommandOutcome::Unknown);
+}
+
+#[tokio::test]
+async fn device_commands() {
+    let (_sim, client) = start_capture(CHAIN, |_| ()).await;
+
+    Pdu::send_device_command(&client, 2, DeviceCommand::ImmediateAllOff)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let addrs = [(1, 1), (2, 1), (2, 2)].map(|(m, n)| OutletAddress::new(m, n));
+    let polled = Pdu::poll_outlets_at(&client, &addrs).await.unwrap();
+    assert_eq!(polled[&addrs[0]].state, Some(State::On));
+    assert_eq!(polled[&addrs[1]].state, Some(State::Off));
+    assert_eq!(polled[&addrs[2]].state, Some(State::Off));
+
+    let e = Pdu::send_device_command(&client, 3, DeviceCommand::ImmediateAllOn)
+        .await
+        .unwrap_err();
+    assert!(matches!(e, Error::NoSuchModule { module: 3 }), "{e}");
+}
+
+#[tokio::test]
+async fn configure_outlets() {
+    let (_sim, client) = start().await;
+
+    let change = OutletConfigChange {
+        name: Some("web-01".into()),
+        power_on_time: Some(30),
+        ..Default::default()
+    };
+    Pdu::configure_outlet(&client, 1, &change).await.unwrap();
+
+    /*
+     * Nothing is sent if any value is out of range.
+     */
+    for bad in [
+        OutletConfigChange { name: Some("x".repeat(33)), ..Default::default() },
+        OutletConfigChange {
+            name: Some("db-01".into()),
+            reboot_duration: Some(90),
+            ..Default::default()
+        },
+        OutletConfigChange {
+            name: Some("db-01".into()),
+            power_off_time: Some(-2),
+            ..Default::default()
+        },
+    ] {
+        let e = Pdu::configure_outlet(&client, 2, &bad).await.unwrap_err();
+        assert!(matches!(e, Error::InvalidSetting { .. }), "{e}");
+    }
+
+    let pdu = Pdu::fetch(&client, &[Group::OutletConfig]).await.unwrap();
+    let config = pdu.outlet_config().unwrap();
+    assert_eq!(config[&1].name, "web-01");
+    assert_eq!(config[&1].power_on_time, 30);
+    assert_eq!(config[&2].name, "db");
+}
+
+#[tokio::test]
+async fn configure_banks() {
+    let (_sim, client) = start().await;
+
+    let change = BankConfigChange {
+        near_overload_current_threshold: Some(14),
+        reset_peak_current: true,
+        ..Default::default()
+    };
+    Pdu::configure_bank(&client, 1, &change).await.unwrap();
+
+    for bad in [
+        BankConfigChange {
+            overload_current_threshold: Some(20),
+            ..Default::default()
+        },
+        BankConfigChange {
+            near_overload_current_threshold: Some(17),
+            ..Default::default()
+        },
+        BankConfigChange {
+            low_load_current_threshold: Some(15),
+            ..Default::default()
+        },
+    ] {
+        let e = Pdu::configure_bank(&client, 1, &bad).await.unwrap_err();
+        assert!(matches!(e, Error::InvalidSetting { .. }), "{e}");
+    }
+
+    let pdu = Pdu::fetch(&client, &[Group::BankConfig]).await.unwrap();
+    let config = pdu.bank_config().unwrap();
+    assert_eq!(config[&1].near_overload_current_threshold, 14);
+    assert_eq!(config[&1].overload_current_threshold, 16);
+}
+
+#[tokio::test]
+async fn reconcile_desired_state() {
+    let (_sim, client) = start().await;
+
+    let desired: DesiredState = toml::from_str(
+        r#"
+        [system]
+        name = "rack1-pdu"
+        location = "rack2"
+
+        [[outlets]]
+        number = 1
+        name = "web"
+
+        [[outlets]]
+        number = 2
+        name = "database"
+        reboot_duration = 20
+
+        [[banks]]
+        number = 1
+        near_overload_current_threshold = 14
+        "#,
+    )
+    .unwrap();
+
+    let plan = reconcile(&client, &desired, true).await.unwrap();
+    let changes = plan
+        .changes()
+        .iter()
+        .map(|c| (c.target, c.field, c.desired.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            (Target::System, Field::SysLocation, Setting::Text("rack2".into())),
+            (
+                Target::Outlet(OutletAddress::new(1, 2)),
+                Field::OutletName,
+                Setting::Text("database".into())
+            ),
+            (
+                Target::Outlet(OutletAddress::new(1, 2)),
+                Field::RebootDuration,
+                Setting::Number(20)
+            ),
+            (
+                Target::Bank { module: 1, number: 1 },
+                Field::NearOverloadCurrentThreshold,
+                Setting::Number(14)
+            ),
+        ]
+    );
+    assert_eq!(
+        plan.to_string().lines().next(),
+        Some(r#"system location: "rack1" -> "rack2""#)
+    );
+
+    /*
+     * A dry run changes nothing, so the same plan is made again.
+     */
+    let again = reconcile(&client, &desired, false).await.unwrap();
+    assert_eq!(again.changes(), plan.changes());
+    assert!(reconcile(&client, &desired, true).await.unwrap().is_empty());
+
+    let system = System::from_client(&client).await.unwrap();
+    assert_eq!(system.location(), "rack2");
+    let pdu = Pdu::fetch(&client, &[Group::OutletConfig]).await.unwrap();
+    assert_eq!(pdu.outlet_config().unwrap()[&2].reboot_duration, 20);
+
+    /*
+     * JSON works as well, and mistakes are caught before anything is sent.
+     */
+    let bad: DesiredState =
+        serde_json::from_str(r#"{ "outlets": [{ "number": 3 }] }"#).unwrap();
+    let e = reconcile(&client, &bad, false).await.unwrap_err();
+    assert!(matches!(e, Error::NoSuchOutlet { module: 1, number: 3 }), "{e}");
+
+    let bad: DesiredState = serde_json::from_str(
+        r#"{ "outlets": [{ "number": 1, "reboot_duration": 90 }] }"#,
+    )
+    .unwrap();
+    let e = reconcile(&client, &bad, false).await.unwrap_err();
+    assert!(matches!(e, Error::InvalidSetting { .. }), "{e}");
+
+    /*
+     * Bank thresholds are checked against each other, the current
+     * configuration and the breaker rating while planning, too.
+     */
+    for bank in [
+        r#"{ "number": 1, "near_overload_current_threshold": 17 }"#,
+        r#"{ "number": 1, "overload_current_threshold": 20 }"#,
+        r#"{ "number": 1, "low_load_current_threshold": 15 }"#,
+    ] {
+        let bad: DesiredState =
+            serde_json::from_str(&format!(r#"{{ "banks": [{bank}] }}"#))
+                .unwrap();
+        let e = Plan::fetch(&client, &bad).await.unwrap_err();
+        assert!(matches!(e, Error::InvalidSetting { .. }), "{bank}: {e}");
+    }
+
+    let bad: DesiredState = serde_json::from_str(
+        r#"{ "outlets": [{ "number": 1 }, { "module": 1, "number": 1 }] }"#,
+    )
+    .unwrap();
+    let e = reconcile(&client, &bad, false).await.unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        Error::InvalidSetting {
+            name: "outlets".into(),
+            message: "module 1 outlet 1 appears more than once".into()
+        }
+        .to_string()
+    );
+
+    assert!(
+        serde_json::from_str::<DesiredState>(r#"{ "outlet": [] }"#).is_err()
+    );
+}
+
+#[tokio::test]
+async fn policy_interlocks() {
+    let (sim, client) = start().await;
+    let web = OutletAddress::new(1, 1);
+    let db = OutletAddress::new(1, 2);
+
+    let policy = Policy {
+        protected: [web].into(),
+        outlet_current: 30,
+        ..Default::default()
+    };
+
+    /*
+     * The bank draws 9.5 A, so another 3 A would pass the near overload
+     * threshold of 12 A, and the protected outlet may not be rebooted.
+     */
+    let dry = GuardOptions { dry_run: true, ..Default::default() };
+    let guarded = policy
+        .send_commands(
+            &client,
+            [
+                (web, OutletCommand::ImmediateReboot),
+                (db, OutletCommand::ImmediateOn),
+            ],
+            &dry,
+        )
+        .await
+        .unwrap();
+    assert!(guarded.report.is_none());
+    let a = &guarded.assessment;
+    assert!(!a.is_allowed());
+    assert_eq!(a.commands[0].refusals, [Refusal::Protected]);
+    assert_eq!(
+        a.commands[1].refusals,
+        [Refusal::BankBudget {
+            module: 1,
+            bank: 1,
+            projected: 125,
+            limit: 120
+        }]
+    );
+    assert_eq!(a.banks[&(1, 1)].current, 95);
+
+    /*
+     * Rebooting an outlet that is off leaves it on, so that is refused too.
+     */
+    let a = policy
+        .assess(&client, [(db, OutletCommand::DelayedReboot)], false)
+        .await
+        .unwrap();
+    assert_eq!(
+        a.commands[0].refusals,
+        [Refusal::BankBudget {
+            module: 1,
+            bank: 1,
+            projected: 125,
+            limit: 120
+        }]
+    );
+
+    /*
+     * A bank that is already near overload takes no more load at all.
+     */
+    let tree = client.tree();
+    let load = tree.oid_by_name("rPDU2BankStatusLoadState.1").unwrap();
+    let restrict =
+        tree.oid_by_name("rPDU2BankConfigOverloadRestriction.1").unwrap();
+    let normal = sim.agent().values()[&load].clone();
+    sim.set(load, sim.agent().values()[&restrict].clone());
+    let idle = Policy { outlet_current: 0, ..policy.clone() };
+    let a = idle
+        .assess(&client, [(db, OutletCommand::DelayedOn)], false)
+        .await
+        .unwrap();
+    assert_eq!(
+        a.commands[0].refusals,
+        [Refusal::BankLoadState {
+            module: 1,
+            bank: 1,
+            state: LoadState::NearOverload
+        }]
+    );
+    sim.set(load, normal);
+
+    let e = policy
+        .send_commands(
+            &client,
+            [(db, OutletCommand::ImmediateOn)],
+            &Default::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(e, Error::PolicyRefused { .. }), "{e}");
+    assert_eq!(Pdu::poll_outlet(&client, 2).await.unwrap().0, State::Off);
+
+    /*
+     * Within the breaker rating, the outlet may be turned on; with the
+     * protection overridden, the protected outlet may be rebooted.
+     */
+    let relaxed = Policy { limit: LoadLimit::BreakerRating, ..policy.clone() };
+    let guarded = relaxed
+        .send_commands(
+            &client,
+            [(db, OutletCommand::ImmediateOn)],
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+    assert!(guarded.report.unwrap().is_complete());
+
+    let overridden =
+        GuardOptions { override_protection: true, ..Default::default() };
+    policy
+        .send_commands(
+            &client,
+            [(web, OutletCommand::ImmediateReboot)],
+            &overridden,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn audit_sets() {
+    let (sim, _) = start().await;
+    let memory = Arc::new(MemorySink::new());
+    let path = std::env::temp_dir()
+        .join(format!("sandgate-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let lines = Arc::new(JsonLinesSink::open(&path).unwrap());
+
+    let mut b = Client::builder();
+    b.port(sim.addr().port())
+        .timeout(Duration::from_millis(500))
+        .oid_tree(mib::bundled())
+        .audit_sink(memory.clone())
+        .read_before_set(true);
+    let client = b.build(sim.addr().ip()).await.unwrap();
+
+    let ops = client.with_audit_context(AuditContext {
+        principal: Some("alice".into()),
+        reason: Some("INC-42: reboot web".into()),
+    });
+    Pdu::send_command(&ops, 1, OutletCommand::ImmediateReboot).await.unwrap();
+
+    let records = memory.take();
+    assert_eq!(records.len(), 1);
+    let r = &records[0];
+    assert_eq!(r.target, sim.addr());
+    assert_eq!(r.name.as_deref(), Some("rPDU2OutletSwitchedControlCommand[1]"));
+    assert_eq!(r.old.as_ref().and_then(|v| v.as_i32()), Some(1));
+    assert_eq!(r.new.as_i32(), Some(3));
+    assert_eq!(r.result.as_ref().ok().and_then(|v| v.as_i32()), Some(3));
+    assert_eq!(r.principal.as_deref(), Some("alice"));
+    assert_eq!(r.reason.as_deref(), Some("INC-42: reboot web"));
+
+    /*
+     * A failed request is recorded too, and the original client keeps its own
+     * (empty) context.
+     */
+    let descr = client.tree().oid_by_name("sysDescr.0").unwrap();
+    let uptime = client.tree().oid_by_name("sysUpTime.0").unwrap();
+    let value = client.get(uptime).await.unwrap();
+    client.set(descr, value).await.unwrap_err();
+    let records = memory.take();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].result.is_err());
+    assert_eq!(records[0].principal, None);
+
+    /*
+     * Records written as JSON lines.
+     */
+    b.audit_sink(lines).read_before_set(false);
+    let client = b.build(sim.addr().ip()).await.unwrap();
+    Pdu::send_command(&client, 2, OutletCommand::ImmediateOn).await.unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let line: serde_json::Value =
+        serde_json::from_str(text.lines().next().unwrap()).unwrap();
+    assert_eq!(line["oid"], "1.3.6.1.4.1.318.1.1.26.9.2.4.1.5.2");
+    assert_eq!(line["old"], serde_json::Value::Null);
+    assert_eq!(line["new"], 1);
+    assert_eq!(line["result"], 1);
+    assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+
+    /*
+     * Opaque values are written as hexadecimal octets.
+     */
+    let path = path.with_extension("opaque.jsonl");
+    b.audit_sink(Arc::new(JsonLinesSink::open(&path).unwrap()));
+    let client = b.build(sim.addr().ip()).await.unwrap();
+    let capture = "iso.3.6.1.3.1.1.0 = OPAQUE: 01 02 AB\n";
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let opaque = experimental(&walk)[&1].clone();
+    client.set(descr, opaque.clone()).await.unwrap_err();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let line: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(line["new"], "01 02 AB");
+
+    /*
+     * A failure to write the record is an error only if the request
+     * succeeded; otherwise the error from the request is more useful.
+     */
+    struct Broken;
+
+    impl AuditSink for Broken {
+        fn record(&self, _: &[AuditRecord]) -> std::io::Result<()> {
+            Err(std::io::Error::other("disk full"))
+        }
+    }
+
+    b.audit_sink(Arc::new(Broken));
+    let client = b.build(sim.addr().ip()).await.unwrap();
+    let e = client.set(descr, opaque).await.unwrap_err();
+    assert!(!matches!(e, Error::Audit(_)), "{e}");
+    let e = Pdu::send_command(&client, 2, OutletCommand::ImmediateOn)
+        .await
+        .unwrap_err();
+    assert!(matches!(e, Error::Audit(_)), "{e}");
+
+    /*
+     * In a batch, the failure is kept in the report; the batch carries on
+     * and the outlets are not rolled back.  Let the reboot of outlet 1
+     * finish first.
+     */
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let report = Pdu::send_commands(
+        &client,
+        [(1, OutletCommand::ImmediateOff), (2, OutletCommand::ImmediateOn)],
+        &BatchOptions { max_varbinds: 1, ..Default::default() },
+    )
+    .await
+    .unwrap();
+    assert!(report.is_complete(), "{report:?}");
+    assert!(report.error.is_none());
+    assert!(matches!(report.audit_error, Some(Error::Audit(_))));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let polled = Pdu::poll_outlets(&client, &[1, 2]).await.unwrap();
+    assert_eq!(polled[&1].state, Some(State::Off));
+    assert_eq!(polled[&2].state, Some(State::On));
+}
+
+#[tokio::test]
+async fn system_uptime() {
+    let (_sim, client) = start().await;
+
+    /*
+     * sysUpTime is in hundredths of a second.
+     */
+    let system = System::from_client(&client).await.unwrap();
+    assert_eq!(system.uptime(), Duration::from_millis(1234560));
+
+    let capture = OUTLETS.replace("(123456)", "(4294967295)");
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let system = System::from_walk(&walk).unwrap();
+    assert_eq!(system.uptime(), Duration::from_millis(42949672950));
+}
+
+#[tokio::test]
+async fn watch_for_changes() {
+    let (sim, client) = start().await;
+    let tree = client.tree();
+    let rpdu2 = tree.oid_by_name("rPDU2").unwrap();
+    let state = tree.oid_by_name("rPDU2OutletSwitchedStatusState.2").unwrap();
+    let load = tree.oid_by_name("rPDU2BankStatusLoadState.1").unwrap();
+    let values = sim.agent().values();
+    let on = values
+        [&tree.oid_by_name("rPDU2OutletSwitchedStatusState.1").unwrap()]
+        .clone();
+    let three = values
+        [&tree.oid_by_name("rPDU2BankConfigOverloadRestriction.1").unwrap()]
+        .clone();
+
+    let before = client.walk(rpdu2).await.unwrap();
+
+    let e =
+        watch(&client, &watch::DEFAULT_GROUPS, Duration::ZERO).err().unwrap();
+    assert!(matches!(e, Error::InvalidSetting { .. }), "{e}");
+
+    let events =
+        watch(&client, &watch::DEFAULT_GROUPS, Duration::from_millis(50))
+            .unwrap();
+    let mut events = std::pin::pin!(events);
+    let (first, ()) = tokio::join!(events.next(), async {
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        sim.set(state, on.clone());
+        sim.set(load, three.clone());
+    });
+    assert_eq!(
+        first.unwrap().unwrap(),
+        Event::OutletState {
+            address: OutletAddress::new(1, 2),
+            old: State::Off,
+            new: State::On
+        }
+    );
+    assert_eq!(
+        events.next().await.unwrap().unwrap(),
+        Event::BankLoadState {
+            module: 1,
+            bank: 1,
+            old: LoadState::Normal,
+            new: LoadState::NearOverload
+        }
+    );
+
+    /*
+     * The same changes, as differences between two walks.
+     */
+    let after = client.walk(rpdu2).await.unwrap();
+    let diff = before.diff(&after);
+    assert_eq!(diff.len(), 2);
+    assert_eq!(diff[0].name.as_deref(), Some("rPDU2BankStatusLoadState[1]"));
+    assert_eq!(
+        diff[1].difference,
+        Difference::Changed {
+            old: Box::new(values[&state].clone()),
+            new: Box::new(on)
+        }
+    );
+    assert!(after.diff(&after).is_empty());
+
+    /*
+     * An uptime that goes backwards is a reboot, unless enough time has
+     * passed for the counter to wrap.  So is an uptime that went forwards by
+     * less than the time between polls.
+     */
+    let system = |uptime: &str| {
+        let capture = OUTLETS.replace("(123456)", uptime);
+        let walk =
+            WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+        System::from_walk(&walk).unwrap()
+    };
+    let (old, new) = (system("(123456)"), system("(500)"));
+    assert_eq!(
+        watch::system_events(&old, &new, None),
+        [Event::Rebooted {
+            old_uptime: Duration::from_millis(1234560),
+            new_uptime: Duration::from_secs(5)
+        }]
+    );
+    let wrap = Duration::from_millis(10 << 32);
+    let wrapped = wrap - Duration::from_millis(1234560 - 5000);
+    assert!(watch::system_events(&old, &new, Some(wrapped)).is_empty());
+    assert_eq!(
+        watch::system_events(&old, &new, Some(wrapped + wrap / 2)).len(),
+        1
+    );
+    assert!(watch::system_events(&new, &old, None).is_empty());
+
+    let elapsed = Duration::from_millis(1234560 - 5000);
+    assert!(watch::system_events(&new, &old, Some(elapsed)).is_empty());
+    assert!(watch::system_events(
+        &new,
+        &old,
+        Some(elapsed + Duration::from_secs(1))
+    )
+    .is_empty());
+    assert_eq!(
+        watch::system_events(&new, &old, Some(Duration::from_secs(3600))),
+        [Event::Rebooted {
+            old_uptime: Duration::from_secs(5),
+            new_uptime: Duration::from_millis(1234560),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn textual_conventions() {
+    let (_sim, client) = start().await;
+
+    let ifs = Interface::from_client(&client).await.unwrap();
+    let eth0 = &ifs[&1];
+    assert_eq!(eth0.descr, "eth0");
+    assert_eq!(eth0.if_type, IfType::EthernetCsmacd);
+    assert_eq!(eth0.oper_status, IfOperStatus::Up);
+    assert_eq!(eth0.phys_address.to_string(), "00:c0:b7:d4:1a:2b");
+    assert_eq!(eth0.last_change, TimeStamp(Duration::from_secs(15)));
+
+    /*
+     * Values of each convention, under the experimental arc:
+     */
+    let capture = "\
+        iso.3.6.1.3.1.1.0 = Hex-STRING: 00 C0 B7 D4 1A 2B\n\
+        iso.3.6.1.3.1.2.0 = Hex-STRING: 07 E8 03 0E 0F 09 1A 05 2B 0A 00\n\
+        iso.3.6.1.3.1.3.0 = Hex-STRING: 07 E8 03 0E 0F 09 1A 05\n\
+        iso.3.6.1.3.1.4.0 = INTEGER: 1\n\
+        iso.3.6.1.3.1.5.0 = INTEGER: 2\n\
+        iso.3.6.1.3.1.6.0 = IpAddress: 192.0.2.7\n\
+        iso.3.6.1.3.1.7.0 = Hex-STRING: FE 80 00 00 00 00 00 00 \
+            00 00 00 00 00 00 00 01 00 00 00 03\n\
+        iso.3.6.1.3.1.8.0 = INTEGER: 4\n\
+        iso.3.6.1.3.1.9.0 = Hex-STRING: 07 E8 0D 0E 0F 09 1A 05\n\
+        iso.3.6.1.3.1.10.0 = INTEGER: 3\n";
+    let walk = WalkedValues::load(capture.as_bytes(), mib::bundled()).unwrap();
+    let values = experimental(&walk);
+    let value = |n| values[&n].into_deserializer();
+
+    let mac = MacAddress::deserialize(value(1)).unwrap();
+    assert_eq!(mac, MacAddress([0x00, 0xc0, 0xb7, 0xd4, 0x1a, 0x2b]));
+    assert!(MacAddress::deserialize(value(2)).is_err());
+
+    let dt = DateAndTime::deserialize(value(2)).unwrap();
+    assert_eq!(dt.to_string(), "2024-03-14T15:09:26.5+10:00");
+    assert_eq!(
+        dt.to_system_time(),
+        Some(std::time::UNIX_EPOCH + Duration::from_millis(1710392966500))
+    );
+    let local = DateAndTime::deserialize(value(3)).unwrap();
+    assert_eq!(local.utc_offset, None);
+    assert_eq!(local.to_system_time(), None);
+    assert!(DateAndTime::deserialize(value(9)).is_err());
+
+    assert!(bool::deserialize(value(4)).unwrap());
+    assert!(!bool::deserialize(value(5)).unwrap());
+    assert!(bool::deserialize(value(8)).is_err());
+    assert_eq!(Option::<bool>::deserialize(value(4)).unwrap(), Some(true));
+
+    let ip = std::net::Ipv4Addr::deserialize(value(6)).unwrap();
+    assert_eq!(ip, std::net::Ipv4Addr::new(192, 0, 2, 7));
+    let ip = InetAddress::deserialize(value(6)).unwrap();
+    assert_eq!(
+        ip.decode(InetAddressType::Ipv4),
+        Some(InetAddr::Ip("192.0.2.7".parse().unwrap()))
+    );
+    assert_eq!(ip.decode(InetAddressType::Ipv6), None);
+    let zoned = InetAddress::deserialize(value(7)).unwrap();
+    assert_eq!(
+        zoned.decode(InetAddressType::Ipv6z),
+        Some(InetAddr::Zoned("fe80::1".parse().unwrap(), 3))
+    );
+
+    let status = RowStatus::deserialize(value(8)).unwrap();
+    assert_eq!(status, RowStatus::CreateAndGo);
+    assert_eq!(&status.to_value(), values[&8]);
+    assert_eq!(&StorageType::NonVolatile.to_value(), values[&10]);
+}
+
+#[tokio::test]
+async fn fetch_selected_groups() {
+    let (_sim, client) = start().await;
+
+    let pdu = Pdu::fetch(&client, &[Group::OutletStatus, Group::OutletControl])
+        .await
+        .unwrap();
+    assert_eq!(pdu.outlet_status().unwrap()[&1].name, "web");
+    assert_eq!(
+        pdu.outlet_control().unwrap()[&2].command,
+        OutletCommand::ImmediateOff
+    );
+
+    let e = pdu.outlet_config().unwrap_err();
+    assert!(matches!(e, Error::NotFetched { .. }), "{e}");
+}
+
+#[tokio::test]
+async fn outlet_command_completes() {
+    let (_sim, client) = start().await;
+
+    Pdu::send_command(&client, 1, OutletCommand::ImmediateOff).await.unwrap();
+
+    let (state, _, pending) = Pdu::poll_outlet(&client, 1).await.unwrap();
+    assert_eq!(state, State::On);
+    assert_eq!(pending, CommandPending::Yes);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let polled = Pdu::poll_outlets(&client, &[1, 2]).await.unwrap();
+    assert_eq!(
+        polled[&1].complete(),
+        Some((State::Off, OutletCommand::ImmediateOff, CommandPending::No))
+    );
+    assert_eq!(
+        polled[&2].complete(),
+        Some((State::Off, OutletCommand::ImmediateOff, CommandPending::No))
+    );
+}
+
+#[tokio::test]
+async fn poll_missing_outlets_and_columns() {
+    let (sim, client) = start().await;
+
+    /*
+     * Model firmware that does not implement the command pending column for
+     * the second outlet.
+     */
+    let pending = client
+        .tree()
+        .oid_by_name("PowerNet-MIB::rPDU2OutletSwitchedStatusCommandPending.2")
+        .unwrap();
+    sim.agent().remove(pending);
+
+    let polled = Pdu::poll_outlets(&client, &[1, 2, 9]).await.unwrap();
+    assert_eq!(
+        polled[&1].complete(),
+        Some((State::On, OutletCommand::ImmediateOn, CommandPending::No))
+    );
+    assert_eq!(polled[&2].state, Some(State::Off));
+    assert_eq!(polled[&2].command_pending, None);
+    assert!(polled[&2].complete().is_none());
+    assert!(!polled[&9].is_present());
+
+    let e = Pdu::poll_outlet(&client, 2).await.unwrap_err();
+    assert_eq!(e.oid(), Some(pending));
+}
+
+#[tokio::test]
+async fn reboot_returns_outlet_to_on() {
+    let (_sim, client) = start().await;
+
+    Pdu::send_command(&client, 2, OutletCommand::ImmediateReboot)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let (state, cmd, pending) = Pdu::poll_outlet(&client, 2).await.unwrap();
+    assert_eq!(state, State::On);
+    assert_eq!(cmd, OutletCommand::ImmediateOn);
+    assert_eq!(pending, CommandPending::No);
+}
+
+#[tokio::test]
+async fn agent_errors() {
+    let (_sim, client) = start().await;
+
+    let e = Pdu::send_command(&client, 9, OutletCommand::ImmediateOn)
+        .await
+        .unwrap_err();
+    assert_eq!(e.agent_status(), Some(ErrorStatus::NotWritable));
+    assert!(e.oid().is_some());
+
+    let e = Pdu::poll_outlet(&client, 9).await.unwrap_err();
+    assert!(
+        matches!(
+            e,
+            Error::Exception { exception: Exception::NoSuchInstance, .. }
+        ),
+        "{e}"
+    );
+}
+
+#[tokio::test]
+async fn injected_faults() {
+    let (sim, client) = start().await;
+
+    sim.inject(Fault::Drop);
+    let e = Pdu::poll_outlet(&client, 1).await.unwrap_err();
+    assert!(e.is_timeout(), "{e}");
+
+    sim.inject(Fault::Malformed);
+    let e = Pdu::poll_outlet(&client, 1).await.unwrap_err();
+    assert!(matches!(e, Error::Protocol { .. }), "{e}");
+
+    sim.inject(Fault::Error(ErrorStatus::TooBig, 0));
+    let e = Pdu::poll_outlet(&client, 1).await.unwrap_err();
+    assert_eq!(e.agent_status(), Some(ErrorStatus::TooBig));
+
+    sim.inject(Fault::Delay(Duration::from_millis(100)));
+    assert!(Pdu::poll_outlet(&client, 1).await.is_ok());
+
+    /*
+     * Faults are consumed, so the agent is now well behaved again.
+     */
+    let (state, _, _) = Pdu::poll_outlet(&client, 1).await.unwrap();
+    assert_eq!(state, State::On);
+}
+
+#[tokio::test]
+async fn walk_adapts_to_small_agents() {
+    let (sim, client) = start_with(|b| {
+        b.max_bindings(8);
+    })
+    .await;
+    let rpdu2 = client.tree().oid_by_name("rPDU2").unwrap();
+    let expected = sim
+        .agent()
+        .values()
+        .keys()
+        .filter(|oid| oid.as_slice().starts_with(rpdu2.as_slice()))
+        .count();
+
+    let walk = client.walk(rpdu2).await.unwrap();
+    assert_eq!(walk.len(), expected);
+
+    let rigid = WalkOptions { adaptive: false, ..Default::default() };
+    let e =
+        client.walk_with(rpdu2, &rigid).await.err().expect("walk should fail");
+    assert_eq!(e.agent_status(), Some(ErrorStatus::TooBig));
+
+    let getnext = WalkOptions { getnext: true, ..rigid };
+    assert_eq!(
+        client.walk_with(rpdu2, &getnext).await.unwrap().len(),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn walk_limits() {
+    let (sim, client) = start().await;
+    let rpdu2 = client.tree().oid_by_name("rPDU2").unwrap();
+
+    let capped = WalkOptions { max_varbinds: Some(10), ..Default::default() };
+    let e =
+        client.walk_with(rpdu2, &capped).await.err().expect("walk should fail");
+    assert!(matches!(e, Error::TooManyValues { limit: 10, .. }), "{e}");
+
+    let hurried = WalkOptions {
+        deadline: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    sim.inject(Fault::Delay(Duration::from_millis(300)));
+    let e = client
+        .walk_with(rpdu2, &hurried)
+        .await
+        .err()
+        .expect("walk should fail");
+    assert!(matches!(e, Error::WalkDeadline { last: None, .. }), "{e}");
+}
+
+#[tokio::test]
+async fn walk_stream_in_order() {
+    let (sim, client) = start_with(|b| {
+        b.max_bindings(8);
+    })
+    .await;
+    let rpdu2 = client.tree().oid_by_name("rPDU2").unwrap();
+
+    let streamed =
+        client.walk_stream(rpdu2).try_collect::<Vec<_>>().await.unwrap();
+    let expected = sim
+        .agent()
+        .values()
+        .into_iter()
+        .filter(|(oid, _)| oid.as_slice().starts_with(rpdu2.as_slice()))
+        .collect::<Vec<_>>();
+    assert_eq!(streamed, expected);
+
+    /*
+     * A walk that fails part of the way through keeps what it received.
+     */
+    let capped = WalkOptions { max_varbinds: Some(10), ..Default::default() };
+    let partial = WalkedValues::from_stream(
+        mib::bundled(),
+        client.walk_stream_with(rpdu2, &capped),
+    )
+    .await
+    .err()
+    .expect("walk should fail");
+    assert_eq!(partial.values.len(), 10);
+    assert!(matches!(partial.error, Error::TooManyValues { .. }));
+}
+
+#[tokio::test]
+async fn table_stream_rows() {
+    /*
+     * With seven columns, only one row fits in each response.
+     */
+    let (_sim, client) = start_with(|b| {
+        b.max_bindings(8);
+    })
+    .await;
+    let entry =
+        client.tree().oid_by_name("rPDU2OutletSwitchedStatusEntry").unwrap();
+
+    let rows = client
+        .table_stream::<OutletStatus>(entry, "rPDU2OutletSwitchedStatus")
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(rows.len(), 2);
+    let (idx, row) = rows[0].as_ref().unwrap();
+    assert_eq!((*idx, row.name.as_str()), (1, "web"));
+    let (idx, row) = rows[1].as_ref().unwrap();
+    assert_eq!((*idx, row.state), (2, State::Off));
+
+    let mut bad =
+        std::pin::pin!(client.table_stream::<OutletStatus>(entry, "rPDU2Bank"));
+    let e = bad.next().await.unwrap().unwrap_err();
+    assert!(matches!(e, Error::TableStructure { .. }), "{e}");
+}